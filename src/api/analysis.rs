@@ -0,0 +1,381 @@
+//! Read-only analysis of cell arrays
+//!
+//! Pure functions over `&[Cell]` and `&Document` used by the WASM API for
+//! study aids.
+
+use serde::{Deserialize, Serialize};
+use crate::api::types::CellFilter;
+use crate::models::{Cell, CursorPosition, Document, ElementKind, PitchSystem};
+use crate::transposition::{spell_western_pitch, SpellingStrategy};
+use crate::utils::pitch_utils::{interval_name, parse_pitch_code, pitch_class, solfege_name};
+
+/// Interval names between consecutive pitched cells ("M3", "-m2", ...)
+///
+/// Non-pitched cells are skipped, as are notes whose pitch code cannot be
+/// read in their pitch system.
+pub fn consecutive_intervals(cells: &[Cell]) -> Vec<String> {
+    let pitches: Vec<(u8, i8, i8)> = cells
+        .iter()
+        .filter(|cell| cell.kind == ElementKind::PitchedElement)
+        .filter_map(|cell| {
+            let code = cell.pitch_code.as_deref().unwrap_or(&cell.glyph);
+            let (degree, alter) = parse_pitch_code(code, cell.pitch_system.unwrap_or_default())?;
+            Some((degree, alter, cell.octave))
+        })
+        .collect();
+
+    pitches
+        .windows(2)
+        .map(|pair| interval_name(pair[0], pair[1]))
+        .collect()
+}
+
+/// Pitch-class set of a group of notes, with its interval-class vector
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PitchClassSet {
+    /// Distinct pitch classes in ascending order (0 = tonic, or C for western notes)
+    pub pitch_classes: Vec<u8>,
+
+    /// Count of each interval class 1-6 between pairs of pitch classes
+    pub interval_vector: [u32; 6],
+}
+
+/// Pitch-class set of the pitched cells in `cells`
+///
+/// Octaves and repeated notes are ignored. Number and sargam notes are
+/// counted from their tonic, so the set is the same in every key.
+pub fn pitch_class_set(cells: &[Cell]) -> PitchClassSet {
+    let mut pitch_classes: Vec<u8> = cells
+        .iter()
+        .filter_map(cell_pitch)
+        .map(|(degree, alter)| pitch_class(degree, alter))
+        .collect();
+    pitch_classes.sort_unstable();
+    pitch_classes.dedup();
+
+    let mut interval_vector = [0; 6];
+    for (index, &low) in pitch_classes.iter().enumerate() {
+        for &high in &pitch_classes[index + 1..] {
+            let interval = high - low;
+            interval_vector[interval.min(12 - interval) as usize - 1] += 1;
+        }
+    }
+
+    PitchClassSet { pitch_classes, interval_vector }
+}
+
+/// Number of notes on each scale degree 1-7 across a document
+///
+/// Accidentals and octaves are ignored, so "3", "3b" and an upper "3" all
+/// count towards degree 3 (index 2). Western notes count from C.
+pub fn degree_histogram(document: &Document) -> [u32; 7] {
+    let mut counts = [0; 7];
+    for (degree, _) in document.lines.iter().flat_map(|line| line.cells.iter()).filter_map(cell_pitch) {
+        counts[(degree.clamp(1, 7) - 1) as usize] += 1;
+    }
+    counts
+}
+
+/// Hover information about a single cell
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CellDescription {
+    /// Element kind name ("Pitched Element", "Barline", ...)
+    pub kind: String,
+
+    /// Scale degree (1-7), for notes
+    pub degree: Option<u8>,
+
+    /// Alteration in semitones, for notes
+    pub accidental: Option<i8>,
+
+    /// Octave offset from the base octave, for notes
+    pub octave: Option<i8>,
+
+    /// Concert pitch name with scientific octave ("E4")
+    pub pitch_name: Option<String>,
+
+    /// Movable-do solfège syllable ("mi")
+    pub solfege: Option<String>,
+
+    /// MIDI note number (C4 = 60)
+    pub midi_number: Option<i32>,
+
+    /// Sounding frequency in Hz under the document's tuning
+    pub frequency_hz: Option<f64>,
+
+    /// One-line summary for a tooltip ("E4 (mi)", "Barline")
+    pub summary: String,
+}
+
+/// Describe the cell at `position` for a hover tooltip
+///
+/// Notes are spelled against the line's effective tonic the same way the
+/// MusicXML exporter spells them. Other cells only carry their kind (a dash
+/// is described as a rest or extension). Returns None if there is no cell.
+pub fn describe_cell(document: &Document, position: &CursorPosition) -> Option<CellDescription> {
+    let line = document.lines.get(position.stave)?;
+    let cell = line.cells.get(position.column)?;
+
+    let mut description = CellDescription {
+        kind: cell.kind.name().to_string(),
+        degree: None,
+        accidental: None,
+        octave: None,
+        pitch_name: None,
+        solfege: None,
+        midi_number: None,
+        frequency_hz: None,
+        summary: match cell.kind {
+            ElementKind::UnpitchedElement => "Rest or extension".to_string(),
+            kind => kind.name().to_string(),
+        },
+    };
+
+    if let Some((degree, alter)) = cell_pitch(cell) {
+        let tonic = match cell.pitch_system.unwrap_or_default() {
+            PitchSystem::Western => None,
+            _ => document.effective_tonic(line).map(String::as_str),
+        };
+        let pitch = spell_western_pitch(degree, alter, cell.octave, tonic, SpellingStrategy::Degree);
+        let pitch_name = format!("{}{}", pitch.name(), pitch.octave);
+        let solfege = solfege_name(degree, alter);
+
+        description.summary = format!("{} ({})", pitch_name, solfege);
+        description.degree = Some(degree);
+        description.accidental = Some(alter);
+        description.octave = Some(cell.octave);
+        description.pitch_name = Some(pitch_name);
+        description.solfege = Some(solfege);
+        description.midi_number = Some(pitch.midi_number());
+
+        // Temperaments are relative to the tonic, whatever the pitch system
+        let key = document.effective_tonic(line).map(String::as_str);
+        let tonic_midi = spell_western_pitch(1, 0, 0, key, SpellingStrategy::Degree).midi_number();
+        description.frequency_hz = Some(document.tuning.frequency(pitch.midi_number(), tonic_midi));
+    }
+
+    Some(description)
+}
+
+/// Scale degree and alteration of a pitched cell
+fn cell_pitch(cell: &Cell) -> Option<(u8, i8)> {
+    if cell.kind != ElementKind::PitchedElement {
+        return None;
+    }
+    let code = cell.pitch_code.as_deref().unwrap_or(&cell.glyph);
+    parse_pitch_code(code, cell.pitch_system.unwrap_or_default())
+}
+
+/// Positions of every cell with the same pitch as the cell at `target`
+///
+/// Pitches match on pitch code, so "3" and "3b" differ; with `degree_only`
+/// any accidental on the same scale degree matches. Octave is ignored unless
+/// `match_octave` is set. The target cell itself is included. Returns an
+/// empty list when the target is not a pitched cell.
+pub fn same_pitch_positions(
+    document: &Document,
+    target: &CursorPosition,
+    match_octave: bool,
+    degree_only: bool,
+) -> Vec<CursorPosition> {
+    let Some(reference) = document
+        .lines
+        .get(target.stave)
+        .and_then(|line| line.cells.get(target.column))
+        .filter(|cell| cell.kind == ElementKind::PitchedElement)
+    else {
+        return Vec::new();
+    };
+    let reference_degree = cell_pitch(reference).map(|(degree, _)| degree);
+
+    let matches = |cell: &Cell| {
+        if cell.kind != ElementKind::PitchedElement || (match_octave && cell.octave != reference.octave) {
+            return false;
+        }
+        if degree_only {
+            let degree = cell_pitch(cell).map(|(degree, _)| degree);
+            degree.is_some() && degree == reference_degree
+        } else {
+            cell.pitch_code == reference.pitch_code
+        }
+    };
+
+    document
+        .lines
+        .iter()
+        .enumerate()
+        .flat_map(|(stave, line)| {
+            line.cells
+                .iter()
+                .enumerate()
+                .filter(|(_, cell)| matches(cell))
+                .map(move |(column, _)| CursorPosition::at(stave, column))
+        })
+        .collect()
+}
+
+/// Positions of every cell passing `filter`, in one line or the whole document
+///
+/// With `line_index` set only that line is searched; a missing line yields
+/// no positions.
+pub fn positions_matching(document: &Document, filter: CellFilter, line_index: Option<usize>) -> Vec<CursorPosition> {
+    document
+        .lines
+        .iter()
+        .enumerate()
+        .filter(|(stave, _)| line_index.is_none_or(|index| index == *stave))
+        .flat_map(|(stave, line)| {
+            line.cells
+                .iter()
+                .enumerate()
+                .filter(move |(_, cell)| filter.matches(cell))
+                .map(move |(column, _)| CursorPosition::at(stave, column))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PitchSystem;
+    use crate::models::tuning::Temperament;
+    use crate::parse::grammar::parse_cells;
+
+    #[test]
+    fn test_ascending_triad() {
+        let cells = parse_cells("1 3 5", PitchSystem::Number);
+        assert_eq!(consecutive_intervals(&cells), vec!["M3", "m3"]);
+    }
+
+    #[test]
+    fn test_descending_line() {
+        let mut cells = parse_cells("S n d", PitchSystem::Sargam);
+        cells[0].octave = 1;
+        assert_eq!(consecutive_intervals(&cells), vec!["-M2", "-M2"]);
+    }
+
+    fn document_with(lines: &[&str]) -> Document {
+        let mut document = Document::new();
+        for text in lines {
+            let mut line = crate::models::Line::new();
+            line.cells = parse_cells(text, PitchSystem::Number);
+            document.add_line(line);
+        }
+        document
+    }
+
+    #[test]
+    fn test_same_pitch_reports_every_occurrence() {
+        let mut document = document_with(&["1 3 3b", "3 5 3"]);
+        document.lines[1].cells[0].octave = 1;
+
+        let positions = same_pitch_positions(&document, &CursorPosition::at(0, 2), false, false);
+
+        assert_eq!(
+            positions,
+            vec![CursorPosition::at(0, 2), CursorPosition::at(1, 0), CursorPosition::at(1, 4)]
+        );
+
+        let same_octave = same_pitch_positions(&document, &CursorPosition::at(0, 2), true, false);
+        assert_eq!(same_octave, vec![CursorPosition::at(0, 2), CursorPosition::at(1, 4)]);
+    }
+
+    #[test]
+    fn test_same_pitch_degree_only_includes_accidentals() {
+        let document = document_with(&["1 3 3b"]);
+
+        let positions = same_pitch_positions(&document, &CursorPosition::at(0, 2), false, true);
+        assert_eq!(positions, vec![CursorPosition::at(0, 2), CursorPosition::at(0, 4)]);
+
+        assert!(same_pitch_positions(&document, &CursorPosition::at(0, 1), false, false).is_empty());
+    }
+
+    #[test]
+    fn test_select_single_barlines() {
+        let document = document_with(&["1 | 2 || 3 | 4 |||", "5 | 6"]);
+        let single = CellFilter::parse("SingleBarline").unwrap();
+
+        assert_eq!(
+            positions_matching(&document, single, Some(0)),
+            vec![CursorPosition::at(0, 2), CursorPosition::at(0, 10)]
+        );
+        assert_eq!(positions_matching(&document, single, None).len(), 3);
+
+        let barlines = positions_matching(&document, CellFilter::parse("barline").unwrap(), Some(0));
+        assert_eq!(barlines.len(), 4);
+        assert!(CellFilter::parse("cadenza").is_none());
+    }
+
+    #[test]
+    fn test_describe_note_in_tonic() {
+        let mut document = document_with(&["1 3 | 2"]);
+        document.tonic = Some("C".to_string());
+
+        let third = describe_cell(&document, &CursorPosition::at(0, 2)).unwrap();
+        assert_eq!(third.degree, Some(3));
+        assert_eq!(third.pitch_name.as_deref(), Some("E4"));
+        assert_eq!(third.midi_number, Some(64));
+        assert_eq!(third.summary, "E4 (mi)");
+
+        document.tonic = Some("D".to_string());
+        let second = describe_cell(&document, &CursorPosition::at(0, 6)).unwrap();
+        assert_eq!(second.pitch_name.as_deref(), Some("E4"));
+        assert_eq!(second.solfege.as_deref(), Some("re"));
+    }
+
+    #[test]
+    fn test_describe_frequency_follows_tuning() {
+        let mut document = document_with(&["1 3"]);
+        document.tonic = Some("C".to_string());
+
+        let tempered = describe_cell(&document, &CursorPosition::at(0, 2)).unwrap();
+        assert!((tempered.frequency_hz.unwrap() - 329.6276).abs() < 1e-3);
+
+        document.tuning.temperament = Temperament::JustIntonation;
+        let tonic = describe_cell(&document, &CursorPosition::at(0, 0)).unwrap().frequency_hz.unwrap();
+        let third = describe_cell(&document, &CursorPosition::at(0, 2)).unwrap().frequency_hz.unwrap();
+        assert!((third - tonic * 1.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_major_triad_pitch_class_set() {
+        let cells = parse_cells("1 3 5 1", PitchSystem::Number);
+        let set = pitch_class_set(&cells);
+        assert_eq!(set.pitch_classes, vec![0, 4, 7]);
+        assert_eq!(set.interval_vector, [0, 0, 1, 1, 1, 0]);
+
+        let western = pitch_class_set(&parse_cells("c e g", PitchSystem::Western));
+        assert_eq!(western, set);
+    }
+
+    #[test]
+    fn test_chromatic_pitch_class_set() {
+        let set = pitch_class_set(&parse_cells("1 1# 2 | -", PitchSystem::Number));
+        assert_eq!(set.pitch_classes, vec![0, 1, 2]);
+        assert_eq!(set.interval_vector, [2, 1, 0, 0, 0, 0]);
+        assert!(pitch_class_set(&[]).pitch_classes.is_empty());
+    }
+
+    #[test]
+    fn test_degree_histogram_counts_notes() {
+        let document = document_with(&["1 1 5 1 5"]);
+        assert_eq!(degree_histogram(&document), [3, 0, 0, 0, 2, 0, 0]);
+
+        let mut document = document_with(&["3b 3 | -", "3"]);
+        document.lines[1].cells[0].octave = 1;
+        assert_eq!(degree_histogram(&document)[2], 3, "accidentals and octaves are ignored");
+
+        assert_eq!(degree_histogram(&Document::new()), [0; 7]);
+    }
+
+    #[test]
+    fn test_describe_non_pitched_cells() {
+        let document = document_with(&["1 | -"]);
+
+        let barline = describe_cell(&document, &CursorPosition::at(0, 2)).unwrap();
+        assert_eq!(barline.summary, "Barline");
+        assert_eq!(barline.midi_number, None);
+        assert_eq!(describe_cell(&document, &CursorPosition::at(0, 4)).unwrap().summary, "Rest or extension");
+        assert!(describe_cell(&document, &CursorPosition::at(0, 9)).is_none());
+    }
+}
@@ -0,0 +1,210 @@
+//! Document diagnostics
+//!
+//! Pure functions that scan a document for likely notation mistakes and
+//! navigate between them. Diagnostics are recomputed on every query, so
+//! they never go stale after an edit.
+
+use serde::{Deserialize, Serialize};
+use crate::api::edit::measure_length;
+use crate::ir::build_export_measures_for;
+use crate::models::{Cell, CursorPosition, Document, ElementKind, PitchSystem};
+use crate::parse::grammar::parse_cells_with_depth;
+
+/// Kind of problem found by a diagnostic
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// A character that is not valid notation in the line's pitch system
+    UnknownToken,
+
+    /// A measure whose length differs from the time signature
+    MeasureLength,
+
+    /// A typed sargam letter whose case was changed to fit the active scale
+    CaseCorrected,
+}
+
+/// A problem at a cell position
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DiagnosticMark {
+    /// Position of the offending cell (stave, column)
+    pub position: CursorPosition,
+
+    pub kind: DiagnosticKind,
+
+    /// Human-readable description
+    pub message: String,
+}
+
+/// Collect all diagnostics in document order
+pub fn collect_diagnostics(document: &Document) -> Vec<DiagnosticMark> {
+    let mut marks = Vec::new();
+
+    for (stave, line) in document.lines.iter().enumerate() {
+        let mut line_marks = Vec::new();
+
+        for (column, cell) in line.cells.iter().enumerate() {
+            if cell.kind == ElementKind::Text {
+                line_marks.push(DiagnosticMark {
+                    position: CursorPosition::at(stave, column),
+                    kind: DiagnosticKind::UnknownToken,
+                    message: format!("'{}' is not valid notation", cell.glyph),
+                });
+            }
+        }
+
+        if let Some(full) = measure_length(&line.time_signature) {
+            for (index, measure) in build_export_measures_for(document, line).iter().enumerate() {
+                let length = measure.duration();
+                let first_cell = measure.events().map(|event| event.cell_index).min();
+                if let (true, Some(column)) = (length != full, first_cell) {
+                    line_marks.push(DiagnosticMark {
+                        position: CursorPosition::at(stave, column),
+                        kind: DiagnosticKind::MeasureLength,
+                        message: format!("Measure {} lasts {} but the time signature is {}", index + 1, length, full),
+                    });
+                }
+            }
+        }
+
+        line_marks.sort_by_key(|mark| mark.position.column);
+        marks.extend(line_marks);
+    }
+
+    marks
+}
+
+/// Cells parsed from a text block, with the characters that were not understood
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ParsedText {
+    pub cells: Vec<Cell>,
+
+    /// One `UnknownToken` mark per uninterpreted cell
+    pub report: Vec<DiagnosticMark>,
+}
+
+/// Parse a text block into cells and report what fell back to text
+///
+/// Report positions are in the source text: `stave` is the text line and
+/// `column` the char offset within it. Newlines only start a new line and
+/// are never reported.
+pub fn parse_text_with_report(text: &str, pitch_system: PitchSystem, max_accidental_depth: u8) -> ParsedText {
+    let cells = parse_cells_with_depth(text, pitch_system, max_accidental_depth);
+    let mut report = Vec::new();
+    let (mut line, mut column) = (0, 0);
+
+    for cell in &cells {
+        let reason = match cell.kind {
+            ElementKind::Text => Some("is not valid notation"),
+            ElementKind::Unknown => Some("could not be interpreted"),
+            _ => None,
+        };
+        // Text cells can absorb newlines, so track position char by char
+        let mut first = None;
+        for c in cell.glyph.chars() {
+            if c == '\n' {
+                line += 1;
+                column = 0;
+                continue;
+            }
+            first.get_or_insert(CursorPosition::at(line, column));
+            column += 1;
+        }
+        if let (Some(reason), Some(position)) = (reason, first) {
+            report.push(DiagnosticMark {
+                position,
+                kind: DiagnosticKind::UnknownToken,
+                message: format!("'{}' {}", cell.glyph.replace('\n', ""), reason),
+            });
+        }
+    }
+
+    ParsedText { cells, report }
+}
+
+/// Find the diagnostic after (or before) a cursor position, wrapping around
+///
+/// Returns None when there are no diagnostics.
+pub fn adjacent_diagnostic<'a>(
+    marks: &'a [DiagnosticMark],
+    cursor: &CursorPosition,
+    forward: bool,
+) -> Option<&'a DiagnosticMark> {
+    if forward {
+        marks
+            .iter()
+            .find(|mark| mark.position > *cursor)
+            .or_else(|| marks.first())
+    } else {
+        marks
+            .iter()
+            .rev()
+            .find(|mark| mark.position < *cursor)
+            .or_else(|| marks.last())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Line, PitchSystem};
+    use crate::parse::grammar::parse_cells;
+
+    fn document_from(text: &str, time_signature: &str) -> Document {
+        let mut document = Document::new();
+        let mut line = Line::new();
+        line.cells = parse_cells(text, PitchSystem::Number);
+        line.time_signature = time_signature.to_string();
+        document.add_line(line);
+        document
+    }
+
+    #[test]
+    fn test_collects_unknown_tokens_and_short_measures() {
+        let document = document_from("1 x 2 | 3 4", "2/4");
+        let marks = collect_diagnostics(&document);
+
+        let found: Vec<(DiagnosticKind, usize)> = marks.iter().map(|m| (m.kind, m.position.column)).collect();
+        assert_eq!(found, vec![(DiagnosticKind::UnknownToken, 2)]);
+
+        let document = document_from("1 2 3 | 4", "2/4");
+        let columns: Vec<usize> = collect_diagnostics(&document).iter().map(|m| m.position.column).collect();
+        assert_eq!(columns, vec![0, 8]);
+    }
+
+    #[test]
+    fn test_parse_report_flags_stray_characters() {
+        let parsed = parse_text_with_report("1 2 q 3", PitchSystem::Number, 2);
+        assert_eq!(parsed.cells.len(), 7);
+        assert_eq!(parsed.report.len(), 1);
+        assert_eq!(parsed.report[0].position, CursorPosition::at(0, 4));
+        assert_eq!(parsed.report[0].message, "'q' is not valid notation");
+
+        let parsed = parse_text_with_report("1# 2\nq 3", PitchSystem::Number, 2);
+        let positions: Vec<CursorPosition> = parsed.report.iter().map(|mark| mark.position).collect();
+        assert_eq!(positions, vec![CursorPosition::at(1, 0)]);
+    }
+
+    #[test]
+    fn test_navigation_cycles_between_diagnostics() {
+        let document = document_from("1 x 2 y", "");
+        let marks = collect_diagnostics(&document);
+        assert_eq!(marks.len(), 2);
+
+        let mut cursor = CursorPosition::new();
+        let mut visited = Vec::new();
+        for _ in 0..3 {
+            cursor = adjacent_diagnostic(&marks, &cursor, true).unwrap().position;
+            visited.push(cursor.column);
+        }
+        assert_eq!(visited, vec![2, 6, 2]);
+
+        let previous = adjacent_diagnostic(&marks, &cursor, false).unwrap();
+        assert_eq!(previous.position.column, 6);
+    }
+
+    #[test]
+    fn test_clean_document_has_nothing_to_visit() {
+        let document = document_from("1 2", "2/4");
+        assert!(adjacent_diagnostic(&collect_diagnostics(&document), &CursorPosition::new(), true).is_none());
+    }
+}
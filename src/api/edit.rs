@@ -0,0 +1,2474 @@
+//! Cell-array editing primitives
+//!
+//! Pure functions over `Vec<Cell>` used by the WASM API. Keeping them free of
+//! `JsValue` lets them be unit-tested natively.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::Hasher;
+use std::io;
+use std::ops::Range;
+use crate::api::types::{CopyMode, PasteMode};
+use crate::ir::{build_export_measures_for, Fraction, BEAT_DURATION};
+use crate::models::barlines::BarlineType;
+use crate::models::pitch_systems::NumberSystem;
+use crate::models::{
+    ActionType, Cell, CursorPosition, Document, DocumentAction, DocumentState, ElementKind, Line, NoteSize, OrnamentType,
+    PitchSystem, SystemMarker,
+};
+use crate::api::diagnostics::{DiagnosticKind, DiagnosticMark};
+use crate::transposition::{degree_from_midi, key_signature_fifths, scale_by_name, to_western_pitch};
+use crate::utils::pitch_utils::{parse_pitch_code, pitch_class, pitch_code_for};
+use crate::parse::grammar::{
+    parse, parse_cells, parse_cells_with_tokens, reparse_token_at, try_combine_tokens, try_combine_tokens_with_depth,
+    parse_single, NotationTokens, FERMATA_TOKEN,
+};
+
+/// The cells `start..end` of a `len`-cell array, in order and within bounds
+///
+/// Selections may be made right to left, so a reversed range is swapped
+/// rather than rejected.
+pub fn clamp_range(len: usize, start: usize, end: usize) -> Range<usize> {
+    let (start, end) = if start <= end { (start, end) } else { (end, start) };
+    start.min(len)..end.min(len)
+}
+
+/// Renumber cell columns so that `col` equals the cell index
+pub fn renumber_columns(cells: &mut [Cell]) {
+    for (index, cell) in cells.iter_mut().enumerate() {
+        cell.col = index;
+    }
+}
+
+/// Insert one typed character at `cursor_pos` and combine it with its neighbours
+///
+/// Next to a note the surrounding token is reparsed as a whole, so quickly
+/// typed or pasted accidentals always land on the note; elsewhere the
+/// look-back/look-forward combiner runs. Returns the insertion index.
+pub fn insert_character_at(
+    cells: &mut Vec<Cell>,
+    c: char,
+    cursor_pos: usize,
+    pitch_system: PitchSystem,
+    max_accidental_depth: u8,
+    tokens: &NotationTokens,
+) -> usize {
+    let column = if cursor_pos == 0 {
+        0
+    } else if cursor_pos <= cells.len() {
+        cells.get(cursor_pos.saturating_sub(1))
+            .map(|c| c.col + 1)
+            .unwrap_or(cursor_pos)
+    } else {
+        cells.last().map(|c| c.col + 1).unwrap_or(0)
+    };
+
+    let insert_pos = cursor_pos.min(cells.len());
+    cells.insert(insert_pos, tokens.parse_single(c, pitch_system, column));
+    for cell in &mut cells[insert_pos + 1..] {
+        cell.col += 1;
+    }
+
+    if !reparse_token_at(cells, insert_pos, pitch_system, max_accidental_depth, tokens) {
+        try_combine_tokens_with_depth(cells, insert_pos, pitch_system, max_accidental_depth);
+    }
+    insert_pos
+}
+
+/// Delete one character of the cell at `at`
+///
+/// A multi-character cell ("1#", "xyz") loses its last character and is
+/// re-parsed, keeping its flags and annotations; a single-character cell is
+/// removed and the columns after it close up. `at` must be a cell index.
+pub fn delete_character_at(cells: &mut Vec<Cell>, at: usize) {
+    let old_cell = &cells[at];
+    let mut chars: Vec<char> = old_cell.glyph.chars().collect();
+    if chars.len() > 1 {
+        chars.pop();
+        let truncated: String = chars.into_iter().collect();
+        let pitch_system = old_cell.pitch_system.unwrap_or(PitchSystem::Unknown);
+        let mut reparsed = parse(&truncated, pitch_system, old_cell.col);
+        reparsed.flags = old_cell.flags;
+        reparsed.copy_annotations_from(old_cell);
+        cells[at] = reparsed;
+    } else {
+        cells.remove(at);
+        for cell in &mut cells[at..] {
+            cell.col = cell.col.saturating_sub(1);
+        }
+    }
+}
+
+/// Delete one character at `at` in a line, as one undoable edit
+///
+/// See `delete_character_at`. Locked lines are refused.
+pub fn delete_character_in_line(document: &mut Document, line_index: usize, at: usize) -> Result<(), String> {
+    document.check_editable(line_index)?;
+    if at >= document.lines[line_index].cells.len() {
+        return Err(format!("Cursor position {} out of bounds", at));
+    }
+
+    let previous = history_snapshot(document);
+    delete_character_at(&mut document.lines[line_index].cells, at);
+    document.reflow_beats(line_index);
+    record_action(document, previous, ActionType::DeleteText, format!("Delete in line {}", line_index + 1));
+    Ok(())
+}
+
+/// Replace the cells in `start..end` with the cells parsed from `text`
+///
+/// An empty range is a plain insert at `start`. Tokens are re-combined at
+/// both edges of the inserted text, so typing "#" after a "1" still yields
+/// "1#". Returns the cell index of the caret after the inserted text.
+pub fn replace_range_with_text(
+    cells: &mut Vec<Cell>,
+    start: usize,
+    end: usize,
+    text: &str,
+    pitch_system: PitchSystem,
+) -> usize {
+    let start = start.min(cells.len());
+    let end = end.clamp(start, cells.len());
+
+    let inserted = parse_cells(text, pitch_system);
+    let inserted_len = inserted.len();
+    cells.splice(start..end, inserted);
+    renumber_columns(cells);
+
+    // Combine across the trailing edge first so the leading edge index stays valid
+    let mut caret = start + inserted_len;
+    if inserted_len > 0 && caret < cells.len() {
+        let before = cells.len();
+        try_combine_tokens(cells, caret, pitch_system);
+        caret -= before - cells.len();
+    }
+    if inserted_len > 0 && start > 0 {
+        let before = cells.len();
+        try_combine_tokens(cells, start, pitch_system);
+        caret -= before - cells.len();
+    }
+
+    renumber_columns(cells);
+    caret
+}
+
+/// Replace the cells between two positions of a document with `text`, as one undo step
+///
+/// The positions may be given in either order. A selection over several
+/// lines collapses onto its start line: the start line is cut at the start
+/// column, the lines after it up to the end line are removed, and the end
+/// line's cells after the end column join the start line. `text` is then
+/// parsed in the start line's pitch system at the start column; an empty
+/// selection is a plain insert. Every line the selection touches must be
+/// editable. Returns the caret after the inserted text.
+pub fn replace_selection_in_document(
+    document: &mut Document,
+    start: CursorPosition,
+    end: CursorPosition,
+    text: &str,
+) -> Result<CursorPosition, String> {
+    let (start, end) = if start <= end { (start, end) } else { (end, start) };
+    for stave in start.stave..=end.stave {
+        document.check_editable(stave)?;
+    }
+    let pitch_system = document.effective_pitch_system(&document.lines[start.stave]);
+
+    let previous = history_snapshot(document);
+    let from = start.column.min(document.lines[start.stave].cells.len());
+    let to = if end.stave > start.stave {
+        let end_cells = &mut document.lines[end.stave].cells;
+        let tail = end_cells.split_off(end.column.min(end_cells.len()));
+        document.lines.drain(start.stave + 1..=end.stave);
+        let cells = &mut document.lines[start.stave].cells;
+        cells.truncate(from);
+        cells.extend(tail);
+        from
+    } else {
+        end.column
+    };
+    let caret = replace_range_with_text(&mut document.lines[start.stave].cells, from, to, text, pitch_system);
+
+    if end.stave > start.stave {
+        let cursor = &mut document.state.cursor;
+        cursor.stave = match cursor.stave > end.stave {
+            true => cursor.stave - (end.stave - start.stave),
+            false => cursor.stave.min(start.stave),
+        };
+        document.recalculate_system_and_part_ids();
+    }
+    document.reflow_beats(start.stave);
+
+    let description = format!("Replace selection with '{}' in line {}", text, start.stave + 1);
+    record_action(document, previous, ActionType::ReplaceSelection, description);
+    Ok(CursorPosition::at(start.stave, caret))
+}
+
+/// Paste clipboard cells at `at`
+///
+/// `Insert` splices the clipboard in. `Overlay` leaves the cell count and
+/// pitches alone: each pitched target cell at `at + i` takes the octave,
+/// slur and ornament of pitched clipboard cell `i`. Returns the cell index
+/// just past the pasted region.
+pub fn paste_cells(cells: &mut Vec<Cell>, at: usize, clipboard: &[Cell], mode: PasteMode) -> usize {
+    let at = at.min(cells.len());
+    match mode {
+        PasteMode::Insert => {
+            cells.splice(at..at, clipboard.iter().cloned());
+            renumber_columns(cells);
+            at + clipboard.len()
+        }
+        PasteMode::Overlay => {
+            for (target, source) in cells[at..].iter_mut().zip(clipboard) {
+                let both_pitched = target.kind == ElementKind::PitchedElement
+                    && source.kind == ElementKind::PitchedElement;
+                if both_pitched {
+                    target.octave = source.octave;
+                    target.slur_indicator = source.slur_indicator;
+                    target.ornament = source.ornament;
+                }
+            }
+            (at + clipboard.len()).min(cells.len())
+        }
+    }
+}
+
+/// Text of the cells in `start..end` for the system clipboard
+///
+/// `Layout` keeps the editor's grid, where every cell takes one column:
+/// a cell wider than that ("1##") would push the rest of the line right, so
+/// the whitespace after it shrinks (down to one space) until the following
+/// cells are back on their columns, counted from the first copied cell.
+/// Lines copied this way stay aligned in a monospace document.
+pub fn copy_text(cells: &[Cell], start: usize, end: usize, mode: CopyMode) -> String {
+    let range = clamp_range(cells.len(), start, end);
+
+    let mut text = String::new();
+    let mut width = 0;
+    for (column, cell) in cells[range].iter().enumerate() {
+        let source = cell.to_source_char();
+        if mode == CopyMode::Layout {
+            let after_space = text.ends_with(' ');
+            if cell.kind == ElementKind::Whitespace && after_space && width > column {
+                continue;
+            }
+            if width < column {
+                text.push_str(&" ".repeat(column - width));
+                width = column;
+            }
+        }
+        width += source.chars().count();
+        text.push_str(&source);
+    }
+    text
+}
+
+/// Highest octave offset accepted by `insert_text_at_octave`
+pub const MAX_OCTAVE_OFFSET: i8 = 2;
+
+/// Insert `text` at `at`, giving every pitched cell it produces `octave`
+///
+/// The octave is clamped to ±`MAX_OCTAVE_OFFSET`; non-pitched cells keep
+/// octave 0. Returns the cell index of the caret after the inserted text.
+pub fn insert_text_at_octave(
+    cells: &mut Vec<Cell>,
+    at: usize,
+    text: &str,
+    pitch_system: PitchSystem,
+    octave: i8,
+) -> usize {
+    let at = at.min(cells.len());
+    let caret = replace_range_with_text(cells, at, at, text, pitch_system);
+
+    let octave = octave.clamp(-MAX_OCTAVE_OFFSET, MAX_OCTAVE_OFFSET);
+    for cell in &mut cells[at..caret] {
+        if cell.kind == ElementKind::PitchedElement {
+            cell.octave = octave;
+        }
+    }
+    caret
+}
+
+/// Insert typed `text` at `at` in a line as one undoable edit
+///
+/// Each character goes through `insert_character_at` in the line's
+/// effective pitch system, with the document's notation tokens and
+/// accidental depth, exactly as if it were typed. With
+/// `Document::auto_barline` set, `auto_insert_barline` runs on the result,
+/// so the note and its barline are undone together. Returns the caret after
+/// the inserted text (and barline).
+pub fn insert_text(document: &mut Document, line_index: usize, at: usize, text: &str) -> Result<usize, String> {
+    insert_text_with_report(document, line_index, at, text).map(|(caret, _)| caret)
+}
+
+/// `insert_text`, also returning the input-assist corrections it made
+///
+/// With `Document::sargam_case_assist` set, typed sargam letters are first
+/// passed through `correct_sargam_case`; each change is reported as a
+/// `CaseCorrected` diagnostic.
+pub fn insert_text_with_report(
+    document: &mut Document,
+    line_index: usize,
+    at: usize,
+    text: &str,
+) -> Result<(usize, Vec<DiagnosticMark>), String> {
+    insert_typed_text(document, line_index, at, text, None)
+}
+
+/// `insert_text`, giving every pitched cell it produces `octave`
+///
+/// For rapid entry while the UI holds an octave modifier. The octave is
+/// clamped to ±`MAX_OCTAVE_OFFSET`, non-pitched cells keep octave 0, and
+/// the octaves are part of the same undo step as the text.
+pub fn insert_text_with_octave(
+    document: &mut Document,
+    line_index: usize,
+    at: usize,
+    text: &str,
+    octave: i8,
+) -> Result<(usize, Vec<DiagnosticMark>), String> {
+    insert_typed_text(document, line_index, at, text, Some(octave))
+}
+
+/// Shared implementation of `insert_text_with_report` and `insert_text_with_octave`
+fn insert_typed_text(
+    document: &mut Document,
+    line_index: usize,
+    at: usize,
+    text: &str,
+    octave: Option<i8>,
+) -> Result<(usize, Vec<DiagnosticMark>), String> {
+    document.check_editable(line_index)?;
+    let line = &document.lines[line_index];
+    let pitch_system = document.effective_pitch_system(line);
+    let constraint = document.active_constraint.trim().to_string();
+    let scale = match document.sargam_case_assist && !constraint.is_empty() {
+        true => Some(scale_by_name(&constraint)?),
+        false => None,
+    };
+
+    let (max_accidental_depth, tokens) = (document.max_accidental_depth, document.notation_tokens);
+    let previous = history_snapshot(document);
+
+    let line = &mut document.lines[line_index];
+    let at = at.min(line.cells.len());
+    let mut caret = at;
+    for c in text.chars() {
+        let before = line.cells.len();
+        insert_character_at(&mut line.cells, c, caret, pitch_system, max_accidental_depth, &tokens);
+        // Combining may merge the new character into its neighbours
+        caret = (caret + line.cells.len()).saturating_sub(before).min(line.cells.len());
+    }
+    let mut corrections = Vec::new();
+    if let Some(scale) = scale {
+        for index in correct_sargam_case(&mut line.cells[at..caret], &scale) {
+            let cell = &line.cells[at + index];
+            corrections.push(DiagnosticMark {
+                position: CursorPosition::at(line_index, at + index),
+                kind: DiagnosticKind::CaseCorrected,
+                message: format!("Corrected to '{}' to fit the scale '{}'", cell.glyph, constraint),
+            });
+        }
+    }
+    if let Some(octave) = octave {
+        let octave = octave.clamp(-MAX_OCTAVE_OFFSET, MAX_OCTAVE_OFFSET);
+        for cell in line.cells[at..caret].iter_mut().filter(|cell| cell.kind == ElementKind::PitchedElement) {
+            cell.octave = octave;
+        }
+    }
+    if document.auto_barline {
+        caret = auto_insert_barline(document, line_index, caret);
+    }
+    document.reflow_beats(line_index);
+
+    record_action(document, previous, ActionType::InsertText, format!("Insert '{}' in line {}", text, line_index + 1));
+    Ok((caret, corrections))
+}
+
+/// Swap komal and shuddha sargam letters that fall outside `scale`
+///
+/// A note whose pitch is not in the scale (read as number degrees over the
+/// tonic) is rewritten with the other case of its letter ("r" and "R", "M"
+/// and "m", ...) when that pitch is in the scale. Only sargam and
+/// Bhatkhande notes are touched. Returns the indices of the changed cells.
+pub fn correct_sargam_case(cells: &mut [Cell], scale: &[(u8, i8)]) -> Vec<usize> {
+    let in_scale = |code: &str, system| {
+        parse_pitch_code(code, system)
+            .is_some_and(|(degree, alter)| scale.iter().any(|&(d, a)| pitch_class(d, a) == pitch_class(degree, alter)))
+    };
+    let swap_case = |text: &str| {
+        let mut chars = text.chars();
+        let first = chars.next().map(|c| if c.is_lowercase() { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() });
+        first.into_iter().chain(chars).collect::<String>()
+    };
+
+    let mut corrected = Vec::new();
+    for (index, cell) in cells.iter_mut().enumerate() {
+        let system = cell.pitch_system.unwrap_or_default();
+        if cell.kind != ElementKind::PitchedElement || !matches!(system, PitchSystem::Sargam | PitchSystem::Bhatkhande) {
+            continue;
+        }
+        let code = cell.pitch_code.clone().unwrap_or_else(|| cell.glyph.clone());
+        let swapped = swap_case(&code);
+        if in_scale(&code, system) || !in_scale(&swapped, system) {
+            continue;
+        }
+        cell.glyph = swap_case(&cell.glyph);
+        cell.pitch_code = Some(swapped);
+        corrected.push(index);
+    }
+    corrected
+}
+
+/// Insert a barline at `caret` if the measure before it has just been filled
+///
+/// Only fires once the beat is closed (the caret follows a whitespace run
+/// long enough to separate beats under the document's `beat_separator`),
+/// since with beat-relative rhythm a beat's length is not known until then.
+/// The measure runs from the previous barline to the caret and is read like
+/// the document exports it; it must exactly match the line's time signature.
+/// Nothing happens if a barline already follows. Returns the caret, moved
+/// past "| " if inserted.
+pub fn auto_insert_barline(document: &mut Document, line_index: usize, caret: usize) -> usize {
+    let Some(line) = document.lines.get(line_index) else {
+        return caret;
+    };
+    let caret = caret.min(line.cells.len());
+    let Some(full) = measure_length(&line.time_signature) else {
+        return caret;
+    };
+    let spaces_before = line.cells[..caret].iter().rev().take_while(|cell| cell.kind == ElementKind::Whitespace).count();
+    if spaces_before < document.beat_separator.gap() {
+        return caret;
+    }
+    let barline_follows = line.cells[caret..]
+        .iter()
+        .find(|cell| cell.kind != ElementKind::Whitespace)
+        .is_some_and(|cell| cell.kind == ElementKind::Barline);
+    if barline_follows {
+        return caret;
+    }
+
+    let start = line.cells[..caret]
+        .iter()
+        .rposition(|cell| cell.kind == ElementKind::Barline)
+        .map_or(0, |index| index + 1);
+    let mut measure = line.clone();
+    measure.cells = line.cells[start..caret].to_vec();
+    let filled = build_export_measures_for(document, &measure)
+        .iter()
+        .fold(Fraction::zero(), |total, measure| total + measure.duration());
+    if filled != full {
+        return caret;
+    }
+
+    let cells = &mut document.lines[line_index].cells;
+    let after_barline = insert_barline_at(cells, caret, caret, BarlineType::Single);
+    replace_range_with_text(cells, after_barline, after_barline, " ", PitchSystem::Unknown)
+}
+
+/// Check if a barline just typed before `caret` ends the piece
+///
+/// True when the cell before the caret is a single barline on the last line
+/// of the document, with a note before it and only whitespace after it. The
+/// UI can then offer `finalize_last_barline`; a barline closing an inner
+/// measure stays single.
+pub fn offers_final_barline(document: &Document, line_index: usize, caret: usize) -> bool {
+    let Some(line) = document.lines.get(line_index).filter(|_| line_index + 1 == document.lines.len()) else {
+        return false;
+    };
+    let caret = caret.min(line.cells.len());
+    let Some(barline) = caret.checked_sub(1).map(|index| &line.cells[index]) else {
+        return false;
+    };
+    barline.kind == ElementKind::Barline
+        && BarlineType::parse(&barline.glyph) == Some(BarlineType::Single)
+        && line.cells[..caret].iter().any(|cell| cell.kind == ElementKind::PitchedElement)
+        && line.cells[caret..].iter().all(|cell| cell.kind == ElementKind::Whitespace)
+}
+
+/// Turn the last barline of the document into a final barline ("|||")
+///
+/// The last barline is the rightmost one on the last line that has any.
+/// A barline that is already final is left alone; otherwise the change is
+/// one undo step. Returns the barline's position.
+pub fn finalize_last_barline(document: &mut Document) -> Result<CursorPosition, String> {
+    let position = document
+        .lines
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(stave, line)| {
+            let column = line.cells.iter().rposition(|cell| cell.kind == ElementKind::Barline)?;
+            Some(CursorPosition::at(stave, column))
+        })
+        .ok_or_else(|| "The document has no barline".to_string())?;
+    document.check_editable(position.stave)?;
+    if BarlineType::parse(&document.lines[position.stave].cells[position.column].glyph) == Some(BarlineType::Final) {
+        return Ok(position);
+    }
+
+    let previous = history_snapshot(document);
+    let line = &mut document.lines[position.stave];
+    insert_barline_at(&mut line.cells, position.column, position.column + 1, BarlineType::Final);
+    document.reflow_beats(position.stave);
+
+    let description = format!("Final barline in line {}", position.stave + 1);
+    record_action(document, previous, ActionType::FinalizeBarline, description);
+    Ok(position)
+}
+
+/// Insert a note played on a MIDI keyboard at `at` in a line
+///
+/// The note number is spelled as a degree of the line's tonic in the line's
+/// pitch system (western notes are absolute); chromatic notes follow the
+/// document's accidental preference, and sargam always uses its komal and
+/// tivra letters. `base_octave` is the scientific octave of the unmarked
+/// middle octave (4 when None). The insertion is one undo step. Returns the
+/// caret after the note, or an error when the note is beyond
+/// ±`MAX_OCTAVE_OFFSET` octaves.
+pub fn insert_midi_note_at(
+    document: &mut Document,
+    line_index: usize,
+    at: usize,
+    midi_note: u8,
+    base_octave: Option<i8>,
+) -> Result<usize, String> {
+    document.check_editable(line_index)?;
+    let line = &document.lines[line_index];
+    let pitch_system = document.effective_pitch_system(line);
+    let tonic = match pitch_system {
+        PitchSystem::Western => None,
+        _ => document.effective_tonic(line).cloned(),
+    };
+
+    let prefer_flats = match pitch_system {
+        PitchSystem::Sargam | PitchSystem::Bhatkhande => true,
+        _ => document.accidental_preference.prefers_flats(key_signature_fifths(tonic.as_deref())),
+    };
+    let (degree, alter, octave) = degree_from_midi(midi_note as i32, tonic.as_deref(), prefer_flats);
+    let (degree, alter) = match (pitch_system, degree, alter) {
+        // Sargam raises only Ma; the other chromatic notes are komal
+        (PitchSystem::Sargam | PitchSystem::Bhatkhande, 5, -1) => (4, 1),
+        _ => (degree, alter),
+    };
+    let octave = base_octave
+        .unwrap_or(4)
+        .checked_sub(4)
+        .and_then(|shift| octave.checked_sub(shift))
+        .filter(|octave| octave.abs() <= MAX_OCTAVE_OFFSET)
+        .ok_or_else(|| format!("MIDI note {} is outside the notated octave range", midi_note))?;
+
+    let code = pitch_code_for(degree, alter, pitch_system).ok_or_else(|| "Unwritable pitch".to_string())?;
+    let previous = history_snapshot(document);
+    let cells = &mut document.lines[line_index].cells;
+    let caret = insert_text_at_octave(cells, at, &code, pitch_system, octave);
+    document.reflow_beats(line_index);
+    record_action(document, previous, ActionType::InsertText, format!("Insert MIDI note {}", midi_note));
+    Ok(caret)
+}
+
+/// Append a line spelling `scale` up from `tonic` over `octaves` octaves
+///
+/// The line takes `tonic` as its own tonic and uses the document's pitch
+/// system; western lines spell each degree on its own letter above the tonic
+/// at its sounding octave. The run ends on the first scale member `octaves`
+/// octaves up, and with a `descending` form (often `scale` itself) comes back
+/// down to the start through that form. Returns the index of the new line, or
+/// an error when the run would leave the ±`MAX_OCTAVE_OFFSET` octave range.
+pub fn generate_scale(
+    document: &mut Document,
+    tonic: &str,
+    scale: &[(u8, i8)],
+    octaves: u8,
+    descending: Option<&[(u8, i8)]>,
+) -> Result<usize, String> {
+    let Some(&(first_degree, first_alter)) = scale.first() else {
+        return Err("Scale has no members".to_string());
+    };
+    let mut line = Line::new();
+    line.tonic = tonic.to_string();
+    let pitch_system = document.effective_pitch_system(&line);
+    if pitch_system == PitchSystem::Tabla {
+        return Err("Tabla lines have no pitches".to_string());
+    }
+
+    let top = i8::try_from(octaves).map_err(|_| format!("{} octaves is too many", octaves))?;
+    let run = |scale: &[(u8, i8)]| -> Vec<(u8, i8, i8)> {
+        (0..top)
+            .flat_map(|octave| scale.iter().map(move |&(degree, alter)| (degree, alter, octave)))
+            .collect()
+    };
+    let mut notes = run(scale);
+    notes.push((first_degree, first_alter, top));
+    if let Some(descending) = descending {
+        notes.extend(run(descending).into_iter().rev());
+    }
+
+    let mut codes = Vec::with_capacity(notes.len());
+    let mut note_octaves = Vec::with_capacity(notes.len());
+    for (degree, alter, octave) in notes {
+        let (degree, alter, octave) = match pitch_system {
+            PitchSystem::Western => {
+                let pitch = to_western_pitch(degree, alter, octave, Some(tonic));
+                let step = "CDEFGAB".find(pitch.step).unwrap_or(0) as u8;
+                (step + 1, pitch.alter, pitch.octave - 4)
+            }
+            _ => (degree, alter, octave),
+        };
+        if octave.abs() > MAX_OCTAVE_OFFSET {
+            return Err(format!("A {}-octave scale from {} is outside the notated octave range", octaves, tonic));
+        }
+        codes.push(pitch_code_for(degree, alter, pitch_system).ok_or_else(|| "Unwritable pitch".to_string())?);
+        note_octaves.push(octave);
+    }
+
+    line.cells = parse_cells(&codes.join(" "), pitch_system);
+    let pitched = line.cells.iter_mut().filter(|cell| cell.kind == ElementKind::PitchedElement);
+    for (cell, octave) in pitched.zip(note_octaves) {
+        cell.octave = octave;
+    }
+
+    document.add_line(line);
+    document.recalculate_system_and_part_ids();
+    Ok(document.lines.len() - 1)
+}
+
+/// The editable source text of a line, one typed token per cell
+///
+/// Notes come back as their pitch code, so imported or respelled glyphs show
+/// the text that would produce them. Octaves are not part of the text.
+pub fn line_source(line: &Line) -> String {
+    line.cells.iter().map(Cell::to_source_char).collect()
+}
+
+/// Reparse a whole line from source text, as one undoable step
+///
+/// Notes that start at the same source offset as an old note keep its
+/// octave, slur, ornament, size and fingering, so writing back an unchanged
+/// `line_source` loses nothing. The document before the edit is pushed onto
+/// the undo history (timestamps are set by the JavaScript layer). Returns the
+/// new number of cells.
+pub fn set_line_source(document: &mut Document, line_index: usize, text: &str) -> Result<usize, String> {
+    document.check_editable(line_index)?;
+    let line = &document.lines[line_index];
+    let pitch_system = document.effective_pitch_system(line);
+
+    let mut offset = 0;
+    let mut old_notes = Vec::new();
+    for cell in &line.cells {
+        if cell.kind == ElementKind::PitchedElement {
+            old_notes.push((offset, cell));
+        }
+        offset += cell.to_source_char().chars().count();
+    }
+
+    let mut cells = parse_cells_with_tokens(text, pitch_system, document.max_accidental_depth, &document.notation_tokens);
+    let mut offset = 0;
+    for cell in &mut cells {
+        if let Some((_, old)) = old_notes.iter().find(|(at, _)| *at == offset) {
+            if cell.kind == ElementKind::PitchedElement {
+                cell.copy_annotations_from(old);
+            }
+        }
+        offset += cell.to_source_char().chars().count();
+    }
+    renumber_columns(&mut cells);
+
+    let previous = history_snapshot(document);
+    document.lines[line_index].cells = cells;
+    document.reflow_beats(line_index);
+
+    record_action(document, previous, ActionType::EditSource, format!("Edit source of line {}", line_index + 1));
+    Ok(document.lines[line_index].cells.len())
+}
+
+/// Copy of `document` for the undo history, without its own history
+pub fn history_snapshot(document: &Document) -> Document {
+    let mut snapshot = document.clone();
+    snapshot.state = DocumentState::new();
+    snapshot
+}
+
+/// `io::Write` adapter that feeds serialized bytes into a hasher
+struct HashWriter(DefaultHasher);
+
+impl io::Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash of the serialized content of a document
+///
+/// Editor state is not serialized, and the selection and focus flags the
+/// renderer sets on cells are cleared while hashing (and restored after),
+/// so neither counts as an edit. The document is streamed into the hasher
+/// without building an intermediate copy.
+pub fn content_hash(document: &mut Document) -> u64 {
+    let cells = document.lines.iter().flat_map(|line| line.cells.iter());
+    let flags: Vec<u8> = cells.map(|cell| cell.flags).collect();
+    for cell in document.lines.iter_mut().flat_map(|line| line.cells.iter_mut()) {
+        cell.set_selected(false);
+        cell.set_focused(false);
+    }
+
+    let mut writer = HashWriter(DefaultHasher::new());
+    let _ = serde_json::to_writer(&mut writer, &*document);
+
+    let cells = document.lines.iter_mut().flat_map(|line| line.cells.iter_mut());
+    for (cell, flags) in cells.zip(flags) {
+        cell.flags = flags;
+    }
+    writer.0.finish()
+}
+
+/// Undo history kept between calls for a document that lives elsewhere
+///
+/// Documents reach the WASM API without their `DocumentState`, so the
+/// history is attached to the document for the length of one edit and
+/// detached again afterwards, together with the `content_hash` of the
+/// document as it was handed back. The next document is the same one,
+/// unedited since, when its hash still matches.
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    history: VecDeque<DocumentAction>,
+    history_index: usize,
+    content_hash: Option<u64>,
+}
+
+impl EditHistory {
+    /// Move the history onto `document`
+    ///
+    /// A history detached from other content, or from content that has
+    /// changed since without a recorded edit, no longer applies: it is
+    /// dropped and false is returned so the caller can report it. An empty
+    /// history always applies.
+    pub fn attach(&mut self, document: &mut Document) -> bool {
+        let applies = self.history.is_empty() || self.content_hash == Some(content_hash(document));
+        if !applies {
+            *self = Self::default();
+        }
+        document.state.history = std::mem::take(&mut self.history);
+        document.state.history_index = self.history_index;
+        applies
+    }
+
+    /// Take the history back from `document` and remember its content
+    pub fn detach(&mut self, document: &mut Document) {
+        self.history = std::mem::take(&mut document.state.history);
+        self.history_index = document.state.history_index;
+        self.content_hash = Some(content_hash(document));
+    }
+}
+
+/// Replace the document content with `snapshot`, keeping its editor state
+fn restore_snapshot(document: &mut Document, snapshot: Document) {
+    let state = std::mem::replace(&mut document.state, DocumentState::new());
+    *document = snapshot;
+    document.state = state;
+}
+
+/// Revert the last recorded edit
+pub fn undo(document: &mut Document) -> Result<(), String> {
+    if !document.state.can_undo() {
+        return Err("Nothing to undo".to_string());
+    }
+    let index = document.state.history_index - 1;
+    let previous = document.state.history[index]
+        .previous_state
+        .clone()
+        .ok_or_else(|| "Undo step has no saved state".to_string())?;
+    restore_snapshot(document, previous);
+    document.state.history_index = index;
+    Ok(())
+}
+
+/// Reapply the last undone edit
+pub fn redo(document: &mut Document) -> Result<(), String> {
+    if !document.state.can_redo() {
+        return Err("Nothing to redo".to_string());
+    }
+    let index = document.state.history_index;
+    let next = document.state.history[index]
+        .new_state
+        .clone()
+        .ok_or_else(|| "Redo step has no saved state".to_string())?;
+    restore_snapshot(document, next);
+    document.state.history_index = index + 1;
+    Ok(())
+}
+
+/// Push one undo step from `previous` to the current document
+///
+/// Timestamps are set by the JavaScript layer.
+pub fn record_action(document: &mut Document, previous: Document, action_type: ActionType, description: String) {
+    let current = history_snapshot(document);
+    document.state.add_action(DocumentAction {
+        action_type,
+        description,
+        previous_state: Some(previous),
+        new_state: Some(current),
+        timestamp: String::new(),
+    });
+}
+
+/// Run `edit` on `document` and push it as one undo step when it succeeds
+///
+/// For edits that have no undo bookkeeping of their own. A failed edit
+/// records nothing, so it must not change the document before failing.
+pub fn record_edit<T>(
+    document: &mut Document,
+    action_type: ActionType,
+    description: String,
+    edit: impl FnOnce(&mut Document) -> Result<T, String>,
+) -> Result<T, String> {
+    let previous = history_snapshot(document);
+    let result = edit(document)?;
+    record_action(document, previous, action_type, description);
+    Ok(result)
+}
+
+/// Exchange the cells of two non-overlapping ranges in one line
+///
+/// The ranges may be given in either order and may differ in length; the
+/// cells between them shift to make room. Cells keep their octaves, slurs,
+/// ornaments and other annotations. The swap is one undo step.
+pub fn swap_ranges(document: &mut Document, line_index: usize, a: Range<usize>, b: Range<usize>) -> Result<(), String> {
+    document.check_editable(line_index)?;
+    let line = &document.lines[line_index];
+    let (first, second) = if a.start <= b.start { (a, b) } else { (b, a) };
+    if first.is_empty() || second.is_empty() {
+        return Err("Both ranges must contain cells".to_string());
+    }
+    if second.end > line.cells.len() {
+        return Err(format!("Range {:?} is beyond the end of line {}", second, line_index + 1));
+    }
+    if first.end > second.start {
+        return Err(format!("Ranges {:?} and {:?} overlap", first, second));
+    }
+
+    let previous = history_snapshot(document);
+    let cells = &mut document.lines[line_index].cells;
+    let mut swapped = cells[..first.start].to_vec();
+    swapped.extend_from_slice(&cells[second.clone()]);
+    swapped.extend_from_slice(&cells[first.end..second.start]);
+    swapped.extend_from_slice(&cells[first.clone()]);
+    swapped.extend_from_slice(&cells[second.end..]);
+    *cells = swapped;
+    renumber_columns(cells);
+    document.reflow_beats(line_index);
+
+    let description = format!("Swap cells {:?} and {:?} in line {}", first, second, line_index + 1);
+    record_action(document, previous, ActionType::SwapRanges, description);
+    Ok(())
+}
+
+/// Insert a copy of a line directly below it
+///
+/// The copy keeps the cells and all line metadata and joins the original's
+/// system: a system start stays on the original, and a system end moves to
+/// the copy so it still closes the group. System and part ids are
+/// recalculated, so the copy gets its own part. The duplication is one undo
+/// step. Returns the copy's index.
+pub fn duplicate_line_below(document: &mut Document, line_index: usize) -> Result<usize, String> {
+    let line = document.lines.get(line_index).ok_or_else(|| format!("Line index {} out of bounds", line_index))?;
+    let grouped = document.lines.iter().filter(|other| other.system_id == line.system_id).count() > 1;
+
+    let previous = history_snapshot(document);
+    let mut copy = line.clone();
+    copy.system_marker = match line.system_marker {
+        SystemMarker::End if grouped => {
+            document.lines[line_index].system_marker = SystemMarker::None;
+            SystemMarker::End
+        }
+        _ => SystemMarker::None,
+    };
+
+    let index = line_index + 1;
+    document.lines.insert(index, copy);
+    if document.state.cursor.stave >= index {
+        document.state.cursor.stave += 1;
+    }
+    document.recalculate_system_and_part_ids();
+
+    let description = format!("Duplicate line {}", line_index + 1);
+    record_action(document, previous, ActionType::DuplicateLine, description);
+    Ok(index)
+}
+
+/// Delete a whole line as one undo step
+///
+/// See `Document::delete_line`. Returns the removed line.
+pub fn delete_line_with_undo(document: &mut Document, line_index: usize) -> Result<Line, String> {
+    let description = format!("Delete line {}", line_index + 1);
+    record_edit(document, ActionType::DeleteLine, description, |document| document.delete_line(line_index))
+}
+
+/// Move a line up or down by one as one undo step
+///
+/// See `Document::move_line`. Returns the line's new index.
+pub fn move_line_with_undo(document: &mut Document, line_index: usize, up: bool) -> Result<usize, String> {
+    let description = format!("Move line {} {}", line_index + 1, if up { "up" } else { "down" });
+    record_edit(document, ActionType::MoveLine, description, |document| document.move_line(line_index, up))
+}
+
+/// Join the same-pitch notes in `range` of a line into one held note
+///
+/// Every note after the first becomes a dash, so the first note sounds
+/// once for the combined duration and beats keep their cell counts ("1 1"
+/// becomes "1 -", a half note; "11" becomes "1-", a quarter). The first
+/// note keeps its annotations. Selections with different pitches, rests or
+/// breath marks between the notes are rejected. Returns the number of notes
+/// merged into the first; the merge is one undo step.
+pub fn merge_notes(document: &mut Document, line_index: usize, range: Range<usize>) -> Result<usize, String> {
+    document.check_editable(line_index)?;
+    let line = &document.lines[line_index];
+    let range = clamp_range(line.cells.len(), range.start, range.end);
+
+    let mut notes = Vec::new();
+    for index in range.clone() {
+        let cell = &line.cells[index];
+        match cell.kind {
+            ElementKind::PitchedElement => notes.push(index),
+            ElementKind::UnpitchedElement if cell.is_rest() => {
+                return Err(format!("Cannot merge across the rest at cell {}", index));
+            }
+            ElementKind::BreathMark => return Err(format!("Cannot merge across the breath mark at cell {}", index)),
+            _ => {}
+        }
+    }
+    let Some((&first, rest)) = notes.split_first().filter(|(_, rest)| !rest.is_empty()) else {
+        return Err("Select at least two notes to merge".to_string());
+    };
+    let pitch = |index: usize| {
+        let cell = &line.cells[index];
+        (cell.pitch_code.as_ref().unwrap_or(&cell.glyph), cell.octave, &cell.extra_pitches)
+    };
+    if let Some(&other) = rest.iter().find(|&&index| pitch(index) != pitch(first)) {
+        return Err(format!(
+            "Cannot merge different pitches ('{}' at cell {} and '{}' at cell {})",
+            line.cells[first].glyph, first, line.cells[other].glyph, other
+        ));
+    }
+
+    let previous = history_snapshot(document);
+    let pitch_system = document.effective_pitch_system(&document.lines[line_index]);
+    let merged = rest.len();
+    for &index in rest {
+        document.lines[line_index].cells[index] = parse_single('-', pitch_system, index);
+    }
+    document.reflow_beats(line_index);
+
+    let description = format!("Merge {} notes in line {}", merged + 1, line_index + 1);
+    record_action(document, previous, ActionType::MergeNotes, description);
+    Ok(merged)
+}
+
+/// Reset every pitched cell in `start..end` to the base octave
+///
+/// An empty range targets the cell just before `start` (the note left of the
+/// caret). Non-pitched cells are skipped. Returns the number of cells changed.
+pub fn clear_octaves_in_range(cells: &mut [Cell], start: usize, end: usize) -> usize {
+    let (start, end) = if start >= end {
+        (start.min(cells.len()).saturating_sub(1), start.min(cells.len()))
+    } else {
+        (start.min(cells.len()), end.min(cells.len()))
+    };
+
+    let mut cleared = 0;
+    for cell in &mut cells[start..end] {
+        if cell.kind == ElementKind::PitchedElement && cell.octave != 0 {
+            cell.octave = 0;
+            cleared += 1;
+        }
+    }
+    cleared
+}
+
+/// Reset the octaves of a line's selection, as one undo step
+///
+/// See `clear_octaves_in_range`. The line must be editable; a selection with
+/// no shifted notes records no step. Returns the number of cells changed.
+pub fn clear_octaves_in_line(document: &mut Document, line_index: usize, start: usize, end: usize) -> Result<usize, String> {
+    document.check_editable(line_index)?;
+
+    let previous = history_snapshot(document);
+    let cleared = clear_octaves_in_range(&mut document.lines[line_index].cells, start, end);
+    if cleared > 0 {
+        let description = format!("Clear {} octave(s) in line {}", cleared, line_index + 1);
+        record_action(document, previous, ActionType::ApplyOctave, description);
+    }
+    Ok(cleared)
+}
+
+/// Strip formatting from `start..end`, keeping only the bare pitches
+///
+/// Notes lose their slur, ornament, fingering, staccato and octave shift;
+/// fermata and breath-mark cells in the range are removed. Chord tones are pitches and
+/// stay. Returns the number of cells changed or removed.
+pub fn clear_annotations_in_range(cells: &mut Vec<Cell>, start: usize, end: usize) -> usize {
+    let range = clamp_range(cells.len(), start, end);
+    let mut cleared = 0;
+
+    for cell in &mut cells[range.clone()] {
+        if cell.kind != ElementKind::PitchedElement {
+            continue;
+        }
+        let annotated = cell.ornament != OrnamentType::None || cell.fingering.is_some() || cell.staccato;
+        if cell.octave != 0 || cell.has_slur() || annotated {
+            cell.octave = 0;
+            cell.clear_slur();
+            cell.ornament = OrnamentType::None;
+            cell.fingering = None;
+            cell.staccato = false;
+            cleared += 1;
+        }
+    }
+
+    let is_articulation = |cell: &Cell| {
+        cell.kind == ElementKind::BreathMark
+            || (cell.kind == ElementKind::UpperAnnotation && cell.glyph == FERMATA_TOKEN)
+    };
+    let before = cells.len();
+    let mut index = 0;
+    cells.retain(|cell| {
+        let keep = !(range.contains(&index) && is_articulation(cell));
+        index += 1;
+        keep
+    });
+    if cells.len() != before {
+        cleared += before - cells.len();
+        renumber_columns(cells);
+    }
+    cleared
+}
+
+/// Re-time the notes in `start..end` to the rhythm of `pattern`
+///
+/// The pattern is notation text in which any note stands for a slot, e.g.
+/// "1 - 1 1" for long-short-short. The selection is rewritten with the
+/// pattern's dashes and spaces, its notes filling the slots in order with
+/// their pitch, octave and other annotations intact. The selection may only
+/// hold notes, dashes and spaces, and must have one note per slot. Returns
+/// the cell index just past the rewritten range.
+pub fn apply_rhythm_pattern(cells: &mut Vec<Cell>, start: usize, end: usize, pattern: &str) -> Result<usize, String> {
+    let is_rhythm_cell = |cell: &Cell| {
+        matches!(cell.kind, ElementKind::PitchedElement | ElementKind::UnpitchedElement | ElementKind::Whitespace)
+    };
+
+    let range = clamp_range(cells.len(), start, end);
+    if !cells[range.clone()].iter().all(is_rhythm_cell) {
+        return Err("A rhythm pattern can only re-time notes, dashes and spaces".to_string());
+    }
+    let template = parse_cells(pattern, PitchSystem::Number);
+    if !template.iter().all(is_rhythm_cell) {
+        return Err(format!("'{}' is not a rhythm pattern", pattern));
+    }
+
+    let mut notes = cells[range.clone()].iter().filter(|cell| cell.kind == ElementKind::PitchedElement).cloned();
+    let slots = template.iter().filter(|cell| cell.kind == ElementKind::PitchedElement).count();
+    if slots != notes.clone().count() {
+        return Err(format!("The pattern has {} notes but the selection has {}", slots, notes.count()));
+    }
+
+    let retimed: Vec<Cell> = template
+        .into_iter()
+        .map(|cell| match cell.kind {
+            ElementKind::PitchedElement => notes.next().unwrap_or(cell),
+            _ => cell,
+        })
+        .collect();
+    let caret = range.start + retimed.len();
+    cells.splice(range, retimed);
+    renumber_columns(cells);
+    Ok(caret)
+}
+
+/// Slur the notes in `start..end`, or remove the slur if the range has one
+///
+/// A new slur runs from the first to the last pitched cell of the range, so
+/// at least two notes are needed. Returns whether the range ends up slurred.
+pub fn toggle_slur(cells: &mut [Cell], start: usize, end: usize) -> Result<bool, String> {
+    let range = clamp_range(cells.len(), start, end);
+
+    if cells[range.clone()].iter().any(Cell::has_slur) {
+        cells[range].iter_mut().for_each(Cell::clear_slur);
+        return Ok(false);
+    }
+
+    let notes: Vec<usize> = range.filter(|&i| cells[i].kind == ElementKind::PitchedElement).collect();
+    match (notes.first(), notes.last()) {
+        (Some(&first), Some(&last)) if first != last => {
+            cells[first].set_slur_start();
+            cells[last].set_slur_end();
+            Ok(true)
+        }
+        _ => Err("A slur needs at least two notes".to_string()),
+    }
+}
+
+/// Toggle a slur over a line's selection, as one undo step
+///
+/// See `toggle_slur`. The line must be editable. Returns true if a slur
+/// was applied, false if one was removed.
+pub fn toggle_slur_in_line(document: &mut Document, line_index: usize, start: usize, end: usize) -> Result<bool, String> {
+    document.check_editable(line_index)?;
+    let description = format!("Toggle slur in line {}", line_index + 1);
+    record_edit(document, ActionType::ApplySlur, description, |document| {
+        toggle_slur(&mut document.lines[line_index].cells, start, end)
+    })
+}
+
+/// Set the printed size of every note and dash in `start..end`
+///
+/// Dashes take the size too, so a held cue note stays cue-sized across
+/// ties. Returns the number of cells changed.
+pub fn set_note_size(cells: &mut [Cell], start: usize, end: usize, size: NoteSize) -> usize {
+    let range = clamp_range(cells.len(), start, end);
+    let mut changed = 0;
+    for cell in &mut cells[range] {
+        if cell.kind.is_temporal() && cell.size != size {
+            cell.size = size;
+            changed += 1;
+        }
+    }
+    changed
+}
+
+/// Mark or unmark every note in `start..end` as staccato
+///
+/// Returns the number of notes changed.
+pub fn set_staccato(cells: &mut [Cell], start: usize, end: usize, staccato: bool) -> usize {
+    let range = clamp_range(cells.len(), start, end);
+    let mut changed = 0;
+    for cell in &mut cells[range] {
+        if cell.kind == ElementKind::PitchedElement && cell.staccato != staccato {
+            cell.staccato = staccato;
+            changed += 1;
+        }
+    }
+    changed
+}
+
+/// Respell the chromatic number-system notes in `start..end` with sharps or flats
+///
+/// The sounding pitch never changes: "1#" becomes "2b" and back. Notes with
+/// no single-accidental enharmonic ("3#", naturals) are left alone. Returns
+/// the number of cells respelled.
+pub fn respell_range(cells: &mut [Cell], start: usize, end: usize, prefer_flats: bool) -> usize {
+    let range = clamp_range(cells.len(), start, end);
+    let mut respelled = 0;
+    for cell in &mut cells[range] {
+        if cell.kind != ElementKind::PitchedElement || cell.pitch_system != Some(PitchSystem::Number) {
+            continue;
+        }
+        let code = cell.pitch_code.clone().unwrap_or_else(|| cell.glyph.clone());
+        if let Some(spelled) = NumberSystem::respell(&code, prefer_flats) {
+            cell.glyph = spelled.clone();
+            cell.pitch_code = Some(spelled);
+            respelled += 1;
+        }
+    }
+    respelled
+}
+
+/// Respell a document selection with sharps or flats, as one undo step
+///
+/// See `respell_range`. The positions may be given in either order and may
+/// be on different lines; every line the selection touches must be
+/// editable. A selection with nothing to respell records no step. Returns
+/// the number of cells respelled.
+pub fn respell_selection_in_document(
+    document: &mut Document,
+    start: CursorPosition,
+    end: CursorPosition,
+    prefer_flats: bool,
+) -> Result<usize, String> {
+    let (start, end) = if start <= end { (start, end) } else { (end, start) };
+    for stave in start.stave..=end.stave {
+        document.check_editable(stave)?;
+    }
+
+    let previous = history_snapshot(document);
+    let mut respelled = 0;
+    for stave in start.stave..=end.stave {
+        let from = if stave == start.stave { start.column } else { 0 };
+        let to = if stave == end.stave { end.column } else { usize::MAX };
+        respelled += respell_range(&mut document.lines[stave].cells, from, to, prefer_flats);
+    }
+
+    if respelled > 0 {
+        let spelling = if prefer_flats { "flats" } else { "sharps" };
+        let description = format!("Respell {} note(s) with {}", respelled, spelling);
+        record_action(document, previous, ActionType::Respell, description);
+    }
+    Ok(respelled)
+}
+
+/// Stack a pitch on the note at `index` as a chord tone
+///
+/// `pitch_text` is parsed in the note's pitch system and must be a single
+/// note ("3", "5b"). Returns the number of pitches now stacked on the note.
+pub fn stack_pitch(cells: &mut [Cell], index: usize, pitch_text: &str, octave: i8) -> Result<usize, String> {
+    let cell = cells
+        .get_mut(index)
+        .filter(|cell| cell.kind == ElementKind::PitchedElement)
+        .ok_or_else(|| "Pitches can only be stacked on a note".to_string())?;
+
+    let parsed = parse_cells(pitch_text.trim(), cell.pitch_system.unwrap_or_default());
+    let pitch_code = match parsed.as_slice() {
+        [note] if note.kind == ElementKind::PitchedElement => note.pitch_code.clone().unwrap_or_else(|| note.glyph.clone()),
+        _ => return Err(format!("'{}' is not a single note", pitch_text)),
+    };
+
+    cell.extra_pitches.push((pitch_code, octave.clamp(-MAX_OCTAVE_OFFSET, MAX_OCTAVE_OFFSET)));
+    Ok(cell.extra_pitches.len())
+}
+
+/// Toggle the slash of the grace note at `index`
+///
+/// An appoggiatura (unslashed) becomes an acciaccatura (slashed) and back.
+/// Returns whether the grace note is now slashed.
+pub fn toggle_grace_slash(cells: &mut [Cell], index: usize) -> Result<bool, String> {
+    let cell = cells.get_mut(index).ok_or_else(|| format!("Cell index {} out of bounds", index))?;
+    let slashed = cell.ornament.grace_slash().ok_or_else(|| "Only grace notes have a slash".to_string())?;
+
+    cell.ornament = if slashed { OrnamentType::Appoggiatura } else { OrnamentType::Acciaccatura };
+    Ok(!slashed)
+}
+
+/// Set the fingering of the note at `index`, or clear it with empty text
+///
+/// The text is kept as typed (trimmed), so piano fingers ("3") and string
+/// sequences ("1-2-3") both work.
+pub fn set_fingering(cells: &mut [Cell], index: usize, fingering: &str) -> Result<(), String> {
+    let cell = cells
+        .get_mut(index)
+        .filter(|cell| cell.kind == ElementKind::PitchedElement)
+        .ok_or_else(|| "Fingerings can only be set on a note".to_string())?;
+
+    let fingering = fingering.trim();
+    cell.fingering = (!fingering.is_empty()).then(|| fingering.to_string());
+    Ok(())
+}
+
+/// Replace the cells in `start..end` with a single barline cell
+///
+/// The barline is built directly rather than parsed, so it is never combined
+/// with a neighbouring "|" or ":". Returns the cell index after the barline.
+pub fn insert_barline_at(cells: &mut Vec<Cell>, start: usize, end: usize, barline: BarlineType) -> usize {
+    let start = start.min(cells.len());
+    let end = end.clamp(start, cells.len());
+
+    let cell = Cell::new(barline.symbol().to_string(), ElementKind::Barline, start);
+    cells.splice(start..end, [cell]);
+    renumber_columns(cells);
+    start + 1
+}
+
+/// Insert a barline in a line over `start..end`, as one undo step
+///
+/// See `insert_barline_at`. Locked lines are refused. Returns the cell index
+/// after the barline.
+pub fn insert_barline_in_line(
+    document: &mut Document,
+    line_index: usize,
+    start: usize,
+    end: usize,
+    barline: BarlineType,
+) -> Result<usize, String> {
+    document.check_editable(line_index)?;
+
+    let previous = history_snapshot(document);
+    let range = clamp_range(document.lines[line_index].cells.len(), start, end);
+    let caret = insert_barline_at(&mut document.lines[line_index].cells, range.start, range.end, barline);
+    document.reflow_beats(line_index);
+
+    let description = format!("Insert {} in line {}", barline.symbol(), line_index + 1);
+    record_action(document, previous, ActionType::InsertBarline, description);
+    Ok(caret)
+}
+
+/// Cell range of a measure's content, without its surrounding whitespace
+///
+/// Measures are the segments between barline cells, counted from 0. An
+/// empty measure yields an empty range after its leading whitespace.
+pub fn measure_content_range(cells: &[Cell], measure_index: usize) -> Option<Range<usize>> {
+    let mut start = 0;
+    let mut segments = Vec::new();
+    for (index, cell) in cells.iter().enumerate() {
+        if cell.kind == ElementKind::Barline {
+            segments.push(start..index);
+            start = index + 1;
+        }
+    }
+    segments.push(start..cells.len());
+
+    let segment = segments.get(measure_index)?.clone();
+    let is_content = |index: &usize| cells[*index].kind != ElementKind::Whitespace;
+    let content_start = segment.clone().find(is_content).unwrap_or(segment.end);
+    let content_end = segment.clone().rev().find(is_content).map_or(content_start, |index| index + 1);
+    Some(content_start..content_end)
+}
+
+/// Copy the content cells of a measure
+pub fn copy_measure(line: &Line, measure_index: usize) -> Result<Vec<Cell>, String> {
+    let range = measure_content_range(&line.cells, measure_index)
+        .ok_or_else(|| format!("Measure index {} out of bounds", measure_index))?;
+    Ok(line.cells[range].to_vec())
+}
+
+/// Replace the content of a measure with copied cells
+///
+/// Barlines and the whitespace next to them are kept, so the measure may
+/// grow or shrink to fit the pasted content. Cells keep their octaves,
+/// slurs and ornaments.
+pub fn paste_measure(line: &mut Line, measure_index: usize, clipboard: &[Cell]) -> Result<(), String> {
+    let range = measure_content_range(&line.cells, measure_index)
+        .ok_or_else(|| format!("Measure index {} out of bounds", measure_index))?;
+    line.cells.splice(range, clipboard.iter().cloned());
+    renumber_columns(&mut line.cells);
+    Ok(())
+}
+
+/// Paste copied cells over a measure of a line, as one undo step
+///
+/// See `paste_measure`. Locked lines are refused.
+pub fn paste_measure_in_line(
+    document: &mut Document,
+    line_index: usize,
+    measure_index: usize,
+    clipboard: &[Cell],
+) -> Result<(), String> {
+    document.check_editable(line_index)?;
+    let description = format!("Paste over measure {} of line {}", measure_index + 1, line_index + 1);
+    record_edit(document, ActionType::PasteMeasure, description, |document| {
+        paste_measure(&mut document.lines[line_index], measure_index, clipboard)?;
+        document.reflow_beats(line_index);
+        Ok(())
+    })
+}
+
+/// Parse a "beats/beat-type" time signature into the measure length
+pub fn measure_length(time_signature: &str) -> Option<Fraction> {
+    let (beats, beat_type) = time_signature.split_once('/')?;
+    let beats: u32 = beats.trim().parse().ok()?;
+    let beat_type: u32 = beat_type.trim().parse().ok()?;
+    (beats > 0 && beat_type > 0).then(|| Fraction::new(beats, beat_type))
+}
+
+/// Append rests to a measure so it fills its line's time signature
+///
+/// Rests are written as dashes whose first dash is flagged as a rest
+/// (`Cell::set_rest`), so they cannot extend the note before them. With the default
+/// beat-relative rhythm each rest is one quarter-note beat; with a fixed
+/// `base_duration` one rest beat holds as many dashes as units are missing.
+/// The measure is read with the document's beat-separator policy, and the
+/// rests are spaced so that each stays its own beat under it.
+/// Returns the number of rest beats added (0 if the measure is already full).
+pub fn pad_measure_with_rests(document: &mut Document, line_index: usize, measure_index: usize) -> Result<usize, String> {
+    let line = document
+        .lines
+        .get(line_index)
+        .ok_or_else(|| format!("Line index {} out of bounds", line_index))?;
+    let full = measure_length(&line.time_signature)
+        .ok_or_else(|| format!("Line has no usable time signature ('{}')", line.time_signature))?;
+
+    let measures = build_export_measures_for(document, line);
+    let measure = measures
+        .get(measure_index)
+        .ok_or_else(|| format!("Measure index {} out of bounds", measure_index))?;
+
+    let current = measure.duration();
+    if current > full {
+        return Err(format!("Measure {} is overfull ({} of {})", measure_index + 1, current, full));
+    }
+    if current == full {
+        return Ok(0);
+    }
+
+    // Missing length as an exact number of unit notes
+    let unit = if line.base_duration == 0 { BEAT_DURATION } else { Fraction::new(1, line.base_duration) };
+    let missing = full - current;
+    let units = missing / unit;
+    if units.denominator != 1 {
+        return Err(format!("Missing {} cannot be filled with {} rests", missing, unit));
+    }
+    let units = units.numerator;
+
+    let rests: Vec<String> = if line.base_duration == 0 {
+        vec!["-".to_string(); units as usize]
+    } else {
+        vec!["-".repeat(units as usize)]
+    };
+
+    // Insert after the measure's last event, before its closing barline
+    let last_cell = measure.events().map(|event| event.cell_index).max().unwrap_or(0);
+    let insert_at = line.cells[last_cell..]
+        .iter()
+        .position(|cell| cell.kind == ElementKind::Barline)
+        .map(|offset| last_cell + offset)
+        .unwrap_or(line.cells.len());
+
+    let gap = document.beat_separator.gap();
+    let line = &mut document.lines[line_index];
+    let spaces_before = line.cells[..insert_at].iter().rev().take_while(|cell| cell.kind == ElementKind::Whitespace).count();
+    let spaces_after = line.cells[insert_at..].iter().take_while(|cell| cell.kind == ElementKind::Whitespace).count();
+
+    let mut text = rests.join(&" ".repeat(gap));
+    if insert_at > 0 {
+        text.insert_str(0, &" ".repeat(gap.saturating_sub(spaces_before)));
+    }
+    if insert_at < line.cells.len() {
+        text.push_str(&" ".repeat(gap.saturating_sub(spaces_after)));
+    }
+
+    let end = replace_range_with_text(&mut line.cells, insert_at, insert_at, &text, PitchSystem::Unknown);
+    for index in insert_at..end {
+        let starts_run = index == 0 || line.cells[index - 1].kind != ElementKind::UnpitchedElement;
+        if line.cells[index].kind == ElementKind::UnpitchedElement && starts_run {
+            line.cells[index].set_rest(true);
+        }
+    }
+    Ok(rests.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::build_export_measures_from_line;
+    use crate::models::BeatSeparator;
+    use crate::transposition::{descending_scale_by_name, scale_by_name, MAJOR_SCALE};
+
+    fn glyphs(cells: &[Cell]) -> Vec<&str> {
+        cells.iter().map(|c| c.glyph.as_str()).collect()
+    }
+
+    #[test]
+    fn test_reversed_ranges_are_put_in_order() {
+        assert_eq!(clamp_range(5, 4, 1), 1..4);
+        assert_eq!(clamp_range(5, 9, 2), 2..5);
+
+        let mut cells = parse_cells("1 2 3", PitchSystem::Number);
+        assert_eq!(toggle_slur(&mut cells, 5, 0), Ok(true));
+        assert!(cells[0].has_slur() && cells[4].has_slur());
+        assert_eq!(set_staccato(&mut cells, 3, 0, true), 2);
+        assert_eq!(copy_text(&cells, 3, 0, CopyMode::Plain), "1 2");
+        assert_eq!(clear_annotations_in_range(&mut cells, 5, 0), 3);
+        assert_eq!(apply_rhythm_pattern(&mut cells, 5, 0, "111"), Ok(3));
+    }
+
+    #[test]
+    fn test_replace_three_cell_selection() {
+        let mut cells = parse_cells("1 2 3", PitchSystem::Number);
+        cells[0].octave = 1;
+
+        let caret = replace_range_with_text(&mut cells, 2, 5, "4 5", PitchSystem::Number);
+
+        assert_eq!(glyphs(&cells), vec!["1", " ", "4", " ", "5"]);
+        assert_eq!(caret, 5);
+        assert_eq!(cells[0].octave, 1, "cells outside the range keep their data");
+        assert!(cells.iter().enumerate().all(|(i, c)| c.col == i));
+    }
+
+    #[test]
+    fn test_replace_selection_across_lines_is_one_undo_step() {
+        let mut document = Document::new();
+        for source in ["1 2 3", "4 4", "5 6 7"] {
+            let mut line = Line::new();
+            line.cells = parse_cells(source, PitchSystem::Number);
+            document.add_line(line);
+        }
+
+        let (start, end) = (CursorPosition::at(0, 2), CursorPosition::at(2, 2));
+        let caret = replace_selection_in_document(&mut document, end, start, "4 5");
+        assert_eq!(caret, Ok(CursorPosition::at(0, 5)), "reversed selections are put in order");
+        assert_eq!(document.lines.len(), 1, "the selection collapses onto its start line");
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1", " ", "4", " ", "5", "6", " ", "7"]);
+
+        undo(&mut document).unwrap();
+        assert_eq!(document.lines.len(), 3);
+        assert_eq!(glyphs(&document.lines[2].cells), vec!["5", " ", "6", " ", "7"]);
+        assert!(!document.state.can_undo(), "delete and insert are one step");
+
+        let caret = replace_selection_in_document(&mut document, start, CursorPosition::at(0, 5), "4 5");
+        assert_eq!(caret, Ok(CursorPosition::at(0, 5)));
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1", " ", "4", " ", "5"]);
+
+        document.lines[1].locked = true;
+        let locked = replace_selection_in_document(&mut document, start, end, "");
+        assert_eq!(locked, Err("Line 2 is locked".to_string()), "every touched line must be editable");
+    }
+
+    #[test]
+    fn test_empty_range_is_plain_insert() {
+        let mut cells = parse_cells("1 3", PitchSystem::Number);
+
+        let caret = replace_range_with_text(&mut cells, 2, 2, "2 ", PitchSystem::Number);
+
+        assert_eq!(glyphs(&cells), vec!["1", " ", "2", " ", "3"]);
+        assert_eq!(caret, 4);
+    }
+
+    #[test]
+    fn test_replacement_combines_with_neighbours() {
+        let mut cells = parse_cells("1 2", PitchSystem::Number);
+
+        let caret = replace_range_with_text(&mut cells, 1, 3, "#", PitchSystem::Number);
+
+        assert_eq!(glyphs(&cells), vec!["1#"]);
+        assert_eq!(cells[0].kind, ElementKind::PitchedElement);
+        assert_eq!(caret, 1);
+    }
+
+    #[test]
+    fn test_insert_text_with_upper_octave() {
+        let mut cells = parse_cells("3", PitchSystem::Number);
+
+        let caret = insert_text_at_octave(&mut cells, 0, "1 2 ", PitchSystem::Number, 1);
+
+        assert_eq!(glyphs(&cells), vec!["1", " ", "2", " ", "3"]);
+        assert_eq!(caret, 4);
+        let octaves: Vec<i8> = cells.iter().map(|c| c.octave).collect();
+        assert_eq!(octaves, vec![1, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_insert_text_with_octave_in_document() {
+        let mut document = Document::new();
+        document.add_line(line_with("3", ""));
+
+        let (caret, _) = insert_text_with_octave(&mut document, 0, 0, "1 2 ", 1).unwrap();
+        assert_eq!(caret, 4);
+        let octaves: Vec<i8> = document.lines[0].cells.iter().map(|c| c.octave).collect();
+        assert_eq!(octaves, vec![1, 0, 1, 0, 0]);
+
+        undo(&mut document).unwrap();
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["3"], "text and octaves are one step");
+
+        document.lines[0].locked = true;
+        assert_eq!(insert_text_with_octave(&mut document, 0, 0, "1", 1), Err("Line 1 is locked".to_string()));
+    }
+
+    #[test]
+    fn test_insert_text_octave_is_clamped() {
+        let mut cells = Vec::new();
+        insert_text_at_octave(&mut cells, 0, "1", PitchSystem::Number, -7);
+        assert_eq!(cells[0].octave, -MAX_OCTAVE_OFFSET);
+    }
+
+    #[test]
+    fn test_paste_insert_renumbers() {
+        let mut cells = parse_cells("1 4", PitchSystem::Number);
+        let clipboard = parse_cells("2 3 ", PitchSystem::Number);
+
+        assert_eq!(paste_cells(&mut cells, 2, &clipboard, PasteMode::Insert), 6);
+        assert_eq!(glyphs(&cells), vec!["1", " ", "2", " ", "3", " ", "4"]);
+        assert!(cells.iter().enumerate().all(|(i, c)| c.col == i));
+    }
+
+    #[test]
+    fn test_paste_overlay_keeps_pitches() {
+        let mut cells = parse_cells("1 2 3", PitchSystem::Number);
+        let mut clipboard = parse_cells("5 6", PitchSystem::Number);
+        clipboard[0].set_slur_start();
+        clipboard[2].set_slur_end();
+        clipboard[2].octave = 1;
+
+        assert_eq!(paste_cells(&mut cells, 0, &clipboard, PasteMode::Overlay), 3);
+
+        assert_eq!(glyphs(&cells), vec!["1", " ", "2", " ", "3"]);
+        assert_eq!(cells[0].pitch_code.as_deref(), Some("1"));
+        assert!(cells[0].is_slur_start());
+        assert!(cells[2].is_slur_end());
+        assert_eq!(cells[2].octave, 1);
+        assert!(!cells[4].is_slur_start() && !cells[4].is_slur_end());
+    }
+
+    #[test]
+    fn test_clear_octaves_in_selection() {
+        let mut cells = parse_cells("1 2 3", PitchSystem::Number);
+        for index in [0, 2, 4] {
+            cells[index].octave = if index == 2 { -1 } else { 1 };
+        }
+        let original = parse_cells("1 2 3", PitchSystem::Number);
+
+        let cleared = clear_octaves_in_range(&mut cells, 0, 5);
+
+        assert_eq!(cleared, 3);
+        assert!(cells.iter().all(|c| c.octave == 0));
+        assert_eq!(glyphs(&cells), glyphs(&original), "base-octave glyphs are unchanged");
+    }
+
+    #[test]
+    fn test_undo_restores_cleared_octaves() {
+        let mut document = document_with("1 2 3", "");
+        for (index, octave) in [(0, 1), (2, -1), (4, 2)] {
+            document.lines[0].cells[index].octave = octave;
+        }
+
+        assert_eq!(clear_octaves_in_line(&mut document, 0, 0, 5), Ok(3));
+        assert!(document.lines[0].cells.iter().all(|c| c.octave == 0));
+        assert_eq!(clear_octaves_in_line(&mut document, 0, 0, 5), Ok(0));
+        assert_eq!(document.state.history.len(), 1, "a clear with nothing to reset records nothing");
+
+        undo(&mut document).unwrap();
+        let octaves: Vec<i8> = document.lines[0].cells.iter().map(|c| c.octave).collect();
+        assert_eq!(octaves, vec![1, 0, -1, 0, 2]);
+
+        document.lines[0].locked = true;
+        assert!(clear_octaves_in_line(&mut document, 0, 0, 5).is_err());
+    }
+
+    #[test]
+    fn test_undo_removes_toggled_slur() {
+        let mut document = document_with("1 2 3", "");
+
+        assert_eq!(toggle_slur_in_line(&mut document, 0, 0, 5), Ok(true));
+        assert!(document.lines[0].cells[0].is_slur_start() && document.lines[0].cells[4].is_slur_end());
+
+        undo(&mut document).unwrap();
+        assert!(document.lines[0].cells.iter().all(|cell| !cell.has_slur()));
+        assert!(!document.state.can_undo());
+
+        assert!(toggle_slur_in_line(&mut document, 0, 0, 1).is_err(), "a slur needs two notes");
+        assert!(!document.state.can_undo(), "a failed toggle records nothing");
+        document.lines[0].locked = true;
+        assert!(toggle_slur_in_line(&mut document, 0, 0, 5).is_err());
+    }
+
+    #[test]
+    fn test_clear_annotations_leaves_bare_pitches() {
+        let mut cells = parse_cells("1 2 3^ ,4", PitchSystem::Number);
+        toggle_slur(&mut cells, 0, 5).unwrap();
+        cells[0].octave = 1;
+        cells[2].ornament = OrnamentType::Trill;
+
+        let cleared = clear_annotations_in_range(&mut cells, 0, 9);
+
+        assert_eq!(cleared, 5, "three notes reset, fermata and breath mark removed");
+        assert_eq!(cells.iter().map(|c| c.glyph.as_str()).collect::<String>(), "1 2 3 4");
+        assert!(cells.iter().all(|c| c.octave == 0 && !c.has_slur() && c.ornament == OrnamentType::None));
+        assert!(cells.iter().enumerate().all(|(col, c)| c.col == col));
+    }
+
+    #[test]
+    fn test_rhythm_pattern_long_short_short() {
+        let mut cells = parse_cells("1 2 3 -", PitchSystem::Number);
+        cells[2].octave = 1;
+
+        let caret = apply_rhythm_pattern(&mut cells, 0, 7, "1 - 11").unwrap();
+
+        assert_eq!(cells.iter().map(|c| c.glyph.as_str()).collect::<String>(), "1 - 23");
+        assert_eq!(caret, 6);
+        assert_eq!(cells[4].octave, 1, "notes keep their octave");
+        assert!(cells.iter().enumerate().all(|(col, c)| c.col == col));
+    }
+
+    #[test]
+    fn test_rhythm_pattern_count_mismatch() {
+        let mut cells = parse_cells("1 2 3", PitchSystem::Number);
+
+        let err = apply_rhythm_pattern(&mut cells, 0, 5, "1 1").unwrap_err();
+        assert!(err.contains("2 notes") && err.contains("has 3"), "{}", err);
+        assert!(apply_rhythm_pattern(&mut cells, 0, 5, "1 | 1 1").is_err(), "barlines are not rhythm");
+        assert_eq!(cells.iter().map(|c| c.glyph.as_str()).collect::<String>(), "1 2 3");
+    }
+
+    #[test]
+    fn test_copy_with_layout_keeps_columns() {
+        // "1##" is one cell, so "3" sits in column 6 of the grid
+        let cells = parse_cells("1## 2   3", PitchSystem::Number);
+        assert_eq!(cells.len(), 7);
+
+        assert_eq!(copy_text(&cells, 0, 7, CopyMode::Plain), "1## 2   3");
+        assert_eq!(copy_text(&cells, 0, 7, CopyMode::Layout), "1## 2 3");
+        assert_eq!(copy_text(&cells, 0, 7, CopyMode::Layout).find('3'), Some(6));
+        assert_eq!(copy_text(&cells, 2, 7, CopyMode::Layout), "2   3", "columns are relative to the copy");
+
+        // One space always separates the tokens
+        let cells = parse_cells("1## 2", PitchSystem::Number);
+        assert_eq!(copy_text(&cells, 0, 3, CopyMode::Layout), "1## 2");
+    }
+
+    #[test]
+    fn test_clear_octaves_targets_cell_before_caret() {
+        let mut cells = parse_cells("1 2", PitchSystem::Number);
+        cells[0].octave = 1;
+        cells[2].octave = 1;
+
+        assert_eq!(clear_octaves_in_range(&mut cells, 3, 3), 1);
+        assert_eq!(cells[0].octave, 1);
+        assert_eq!(cells[2].octave, 0);
+    }
+
+    #[test]
+    fn test_toggle_slur_on_and_off() {
+        let mut cells = parse_cells("1 2 3", PitchSystem::Number);
+
+        assert_eq!(toggle_slur(&mut cells, 0, 5), Ok(true));
+        assert!(cells[0].is_slur_start());
+        assert!(cells[4].is_slur_end());
+        assert!(!cells[2].has_slur());
+
+        assert_eq!(toggle_slur(&mut cells, 0, 5), Ok(false));
+        assert!(cells.iter().all(|cell| !cell.has_slur()));
+
+        assert!(toggle_slur(&mut cells, 0, 2).is_err(), "one note cannot be slurred");
+    }
+
+    #[test]
+    fn test_respell_selection_keeps_sounding_pitch() {
+        use crate::transposition::to_western_pitch;
+        use crate::utils::pitch_utils::parse_pitch_code;
+
+        let mut cells = parse_cells("1# 4# 3# 5", PitchSystem::Number);
+        cells[3].octave = 1;
+        let sounding = |cells: &[Cell]| -> Vec<i32> {
+            cells
+                .iter()
+                .filter(|cell| cell.kind == ElementKind::PitchedElement)
+                .map(|cell| {
+                    let (degree, alter) = parse_pitch_code(cell.pitch_code.as_deref().unwrap(), PitchSystem::Number).unwrap();
+                    to_western_pitch(degree, alter, cell.octave, Some("D")).midi_number()
+                })
+                .collect()
+        };
+        let before = sounding(&cells);
+
+        assert_eq!(respell_range(&mut cells, 0, 10, true), 2);
+        assert_eq!(glyphs(&cells), vec!["2b", " ", "5b", " ", "3#", " ", "5"]);
+        assert_eq!(cells[3].octave, 1, "other attributes are kept");
+        assert_eq!(sounding(&cells), before);
+
+        assert_eq!(respell_range(&mut cells, 0, 2, false), 1);
+        assert_eq!(glyphs(&cells)[..3], ["1#", " ", "5b"], "only the selection changes");
+        assert_eq!(sounding(&cells), before);
+    }
+
+    #[test]
+    fn test_respell_selection_over_lines_is_one_undo_step() {
+        let mut document = Document::new();
+        document.add_line(line_with("1# 2", ""));
+        document.add_line(line_with("4# 6#", ""));
+
+        let (start, end) = (CursorPosition::at(0, 0), CursorPosition::at(1, 2));
+        assert_eq!(respell_selection_in_document(&mut document, end, start, true), Ok(2));
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["2b", " ", "2"]);
+        assert_eq!(glyphs(&document.lines[1].cells), vec!["5b", " ", "6#"], "cells after the end stay");
+
+        undo(&mut document).unwrap();
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1#", " ", "2"]);
+        assert_eq!(glyphs(&document.lines[1].cells), vec!["4#", " ", "6#"]);
+        assert!(!document.state.can_undo());
+
+        document.lines[1].locked = true;
+        let locked = respell_selection_in_document(&mut document, start, end, true);
+        assert_eq!(locked, Err("Line 2 is locked".to_string()));
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1#", " ", "2"]);
+    }
+
+    #[test]
+    fn test_typed_accidentals_reparse_into_one_note() {
+        let tokens = NotationTokens::default();
+        let mut cells = Vec::new();
+        for (pos, c) in "1##".chars().enumerate() {
+            insert_character_at(&mut cells, c, pos, PitchSystem::Number, 2, &tokens);
+        }
+        assert_eq!(glyphs(&cells), vec!["1##"]);
+        assert_eq!(cells[0].pitch_code.as_deref(), Some("1##"));
+        assert_eq!(cells[0].kind, ElementKind::PitchedElement);
+    }
+
+    #[test]
+    fn test_accidental_absorbs_stray_neighbour() {
+        let tokens = NotationTokens::default();
+        let mut cells = parse_cells("1", PitchSystem::Number);
+        cells[0].octave = 1;
+        cells.push(Cell::new("#".to_string(), ElementKind::Text, 1));
+        cells.extend(parse_cells(" 2", PitchSystem::Number));
+        renumber_columns(&mut cells);
+
+        insert_character_at(&mut cells, '#', 1, PitchSystem::Number, 2, &tokens);
+
+        assert_eq!(glyphs(&cells), vec!["1##", " ", "2"]);
+        assert_eq!(cells[0].octave, 1, "the note keeps its octave");
+        assert_eq!(cells.iter().map(|c| c.col).collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_stack_pitch_on_note() {
+        let mut cells = parse_cells("1 2", PitchSystem::Number);
+
+        assert_eq!(stack_pitch(&mut cells, 0, "3", 0), Ok(1));
+        assert_eq!(stack_pitch(&mut cells, 0, "5b", 1), Ok(2));
+        assert_eq!(cells[0].extra_pitches, vec![("3".to_string(), 0), ("5b".to_string(), 1)]);
+        assert_eq!(glyphs(&cells), vec!["1", " ", "2"], "the line stays monophonic");
+
+        assert!(stack_pitch(&mut cells, 1, "3", 0).is_err(), "whitespace carries no chord");
+        assert!(stack_pitch(&mut cells, 2, "3 4", 0).is_err());
+    }
+
+    #[test]
+    fn test_set_and_clear_fingering() {
+        let mut cells = parse_cells("1 2", PitchSystem::Number);
+        set_fingering(&mut cells, 2, " 1-2-3 ").unwrap();
+        assert_eq!(cells[2].fingering.as_deref(), Some("1-2-3"));
+
+        set_fingering(&mut cells, 2, "").unwrap();
+        assert_eq!(cells[2].fingering, None);
+        assert!(set_fingering(&mut cells, 1, "3").is_err(), "whitespace takes no fingering");
+    }
+
+    #[test]
+    fn test_line_source_round_trip_keeps_octaves() {
+        let mut document = Document::new();
+        let mut line = Line::new();
+        line.cells = parse_cells("1# 2b | 3", PitchSystem::Number);
+        line.cells[0].octave = 1;
+        line.cells[2].octave = -1;
+        line.cells[6].octave = 2;
+        document.add_line(line);
+        let before = document.lines[0].cells.clone();
+
+        let source = line_source(&document.lines[0]);
+        assert_eq!(source, "1# 2b | 3");
+        assert_eq!(set_line_source(&mut document, 0, &source), Ok(before.len()));
+
+        let after = &document.lines[0].cells;
+        let view = |cells: &[Cell]| cells.iter().map(|c| (c.glyph.clone(), c.kind, c.octave)).collect::<Vec<_>>();
+        assert_eq!(view(after), view(&before));
+        assert!(document.state.can_undo());
+        assert_eq!(document.state.history[0].previous_state.as_ref().unwrap().lines[0].cells, before);
+    }
+
+    #[test]
+    fn test_set_line_source_reparses_new_text() {
+        let mut document = Document::new();
+        document.add_line(Line::new());
+        document.lines[0].cells = parse_cells("1 2", PitchSystem::Number);
+        document.lines[0].cells[2].octave = 1;
+
+        set_line_source(&mut document, 0, "1 2# 3").unwrap();
+        let cells = &document.lines[0].cells;
+        assert_eq!(glyphs(cells), vec!["1", " ", "2#", " ", "3"]);
+        assert_eq!(cells[2].octave, 1, "the note at the same offset keeps its octave");
+        assert_eq!(cells[4].octave, 0);
+        assert!(set_line_source(&mut document, 3, "1").is_err());
+    }
+
+    #[test]
+    fn test_undo_redo_restore_snapshots() {
+        let mut document = Document::new();
+        document.add_line(Line::new());
+        document.lines[0].cells = parse_cells("1 2", PitchSystem::Number);
+        set_line_source(&mut document, 0, "1 2 3").unwrap();
+        set_line_source(&mut document, 0, "5").unwrap();
+
+        undo(&mut document).unwrap();
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1", " ", "2", " ", "3"]);
+        undo(&mut document).unwrap();
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1", " ", "2"]);
+        assert!(undo(&mut document).is_err());
+
+        redo(&mut document).unwrap();
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1", " ", "2", " ", "3"]);
+        assert_eq!(document.state.history.len(), 2, "stepping keeps the history");
+    }
+
+    #[test]
+    fn test_edit_history_follows_its_document() {
+        let mut document = Document::new();
+        document.add_line(Line::new());
+        let mut history = EditHistory::default();
+        assert!(history.attach(&mut document), "an empty history fits any document");
+
+        set_line_source(&mut document, 0, "1 2").unwrap();
+        history.detach(&mut document);
+        document.lines[0].cells[0].set_selected(true);
+        assert!(history.attach(&mut document), "selection is not an edit");
+        assert!(document.lines[0].cells[0].is_selected(), "hashing keeps the flags");
+        undo(&mut document).unwrap();
+        history.detach(&mut document);
+        assert!(history.attach(&mut document), "the history follows undo");
+        redo(&mut document).unwrap();
+        history.detach(&mut document);
+
+        document.lines[0].cells[0].octave = 1;
+        assert!(!history.attach(&mut document), "an unrecorded edit leaves the history stale");
+        assert!(!document.state.can_undo());
+    }
+
+    #[test]
+    fn test_record_edit_pushes_one_step_on_success() {
+        let mut document = Document::new();
+        document.add_line(Line::new());
+
+        let fail = |_: &mut Document| Err::<(), _>("Refused".to_string());
+        assert!(record_edit(&mut document, ActionType::SetMetadata, "Fail".to_string(), fail).is_err());
+        assert!(!document.state.can_undo(), "a failed edit records nothing");
+
+        record_edit(&mut document, ActionType::SetMetadata, "Set title".to_string(), |document| {
+            document.title = Some("Raga".to_string());
+            Ok(())
+        })
+        .unwrap();
+        undo(&mut document).unwrap();
+        assert_eq!(document.title, None);
+    }
+
+    #[test]
+    fn test_undo_restores_deleted_and_moved_lines() {
+        let mut document = Document::new();
+        for source in ["1", "2", "3"] {
+            let mut line = Line::new();
+            line.cells = parse_cells(source, PitchSystem::Number);
+            document.add_line(line);
+        }
+        let first_glyphs = |document: &Document| -> Vec<String> {
+            document.lines.iter().map(|line| line.cells[0].glyph.clone()).collect()
+        };
+
+        assert_eq!(delete_line_with_undo(&mut document, 1).unwrap().cells[0].glyph, "2");
+        assert_eq!(move_line_with_undo(&mut document, 1, true), Ok(0));
+        assert_eq!(first_glyphs(&document), vec!["3", "1"]);
+
+        undo(&mut document).unwrap();
+        assert_eq!(first_glyphs(&document), vec!["1", "3"]);
+        undo(&mut document).unwrap();
+        assert_eq!(first_glyphs(&document), vec!["1", "2", "3"]);
+
+        document.lines[0].locked = true;
+        assert!(move_line_with_undo(&mut document, 1, true).is_err());
+        assert_eq!(document.state.history_index, 0, "a refused move records nothing");
+    }
+
+    #[test]
+    fn test_insert_midi_note_in_tonic() {
+        let mut document = Document::new();
+        document.tonic = Some("C".to_string());
+        document.add_line(Line::new());
+
+        assert_eq!(insert_midi_note_at(&mut document, 0, 0, 64, None), Ok(1));
+        let cell = &document.lines[0].cells[0];
+        assert_eq!((cell.pitch_code.as_deref(), cell.octave), (Some("3"), 0));
+
+        insert_midi_note_at(&mut document, 0, 1, 74, None).unwrap();
+        assert_eq!(document.lines[0].cells[1].pitch_code.as_deref(), Some("2"));
+        assert_eq!(document.lines[0].cells[1].octave, 1);
+
+        insert_midi_note_at(&mut document, 0, 2, 52, Some(3)).unwrap();
+        assert_eq!(document.lines[0].cells[2].octave, 0, "E3 is unmarked when octave 3 is the base");
+
+        assert!(insert_midi_note_at(&mut document, 0, 0, 100, None).is_err());
+        assert!(insert_midi_note_at(&mut document, 0, 0, 60, Some(i8::MIN)).is_err(), "no overflow");
+        assert!(insert_midi_note_at(&mut document, 0, 0, 60, Some(i8::MAX)).is_err());
+        assert_eq!(document.lines[0].cells.len(), 3);
+    }
+
+    #[test]
+    fn test_insert_midi_note_in_sargam() {
+        let mut document = Document::new();
+        document.tonic = Some("D".to_string());
+        document.pitch_system = Some(PitchSystem::Sargam);
+        document.add_line(Line::new());
+
+        insert_midi_note_at(&mut document, 0, 0, 63, None).unwrap();
+        insert_midi_note_at(&mut document, 0, 1, 68, None).unwrap();
+        let codes: Vec<_> = document.lines[0].cells.iter().map(|c| c.pitch_code.clone().unwrap()).collect();
+        assert_eq!(codes, vec!["r", "M"]);
+    }
+
+    #[test]
+    fn test_generate_two_octave_scale_up_and_down() {
+        let mut document = Document::new();
+        document.add_line(Line::new());
+
+        let index = generate_scale(&mut document, "C", &MAJOR_SCALE, 2, Some(&MAJOR_SCALE)).unwrap();
+        assert_eq!(index, 1);
+        let line = &document.lines[1];
+        assert_eq!(line.tonic, "C");
+
+        let notes: Vec<(&str, i8)> = line
+            .cells
+            .iter()
+            .filter(|cell| cell.kind == ElementKind::PitchedElement)
+            .map(|cell| (cell.glyph.as_str(), cell.octave))
+            .collect();
+        let ascent: Vec<(&str, i8)> = ["1", "2", "3", "4", "5", "6", "7"]
+            .iter()
+            .map(|&degree| (degree, 0))
+            .chain(["1", "2", "3", "4", "5", "6", "7"].iter().map(|&degree| (degree, 1)))
+            .chain(std::iter::once(("1", 2)))
+            .collect();
+        let mut expected = ascent.clone();
+        expected.extend(ascent.iter().rev().skip(1));
+        assert_eq!(notes, expected);
+        assert_eq!(notes.len(), 29);
+    }
+
+    #[test]
+    fn test_generate_melodic_minor_descends_natural() {
+        let mut document = Document::new();
+        let ascending = scale_by_name("melodic-minor").unwrap();
+        let descending = descending_scale_by_name("melodic-minor").unwrap();
+
+        generate_scale(&mut document, "A", &ascending, 1, Some(&descending)).unwrap();
+        let glyphs: Vec<&str> = document.lines[0]
+            .cells
+            .iter()
+            .filter(|cell| cell.kind == ElementKind::PitchedElement)
+            .map(|cell| cell.glyph.as_str())
+            .collect();
+        assert_eq!(glyphs, vec!["1", "2", "3b", "4", "5", "6", "7", "1", "7b", "6b", "5", "4", "3b", "2", "1"]);
+    }
+
+    #[test]
+    fn test_generate_scale_in_western_and_out_of_range() {
+        let mut document = Document::new();
+        document.pitch_system = Some(PitchSystem::Western);
+
+        generate_scale(&mut document, "D", &MAJOR_SCALE, 1, None).unwrap();
+        let notes: Vec<(&str, i8)> = document.lines[0]
+            .cells
+            .iter()
+            .filter(|cell| cell.kind == ElementKind::PitchedElement)
+            .map(|cell| (cell.glyph.as_str(), cell.octave))
+            .collect();
+        assert_eq!(
+            notes,
+            vec![("d", 0), ("e", 0), ("f#", 0), ("g", 0), ("a", 0), ("b", 0), ("c#", 1), ("d", 1)]
+        );
+
+        assert!(generate_scale(&mut document, "D", &MAJOR_SCALE, 3, None).is_err());
+        assert_eq!(document.lines.len(), 1, "a failed scale adds no line");
+    }
+
+    #[test]
+    fn test_insert_each_barline_kind() {
+        let kinds = [
+            ("single", "|"),
+            ("double", "||"),
+            ("final", "|||"),
+            ("repeat-left", "|:"),
+            ("repeat-right", ":|"),
+        ];
+        for (kind, glyph) in kinds {
+            let mut cells = parse_cells("1 2", PitchSystem::Number);
+            let barline = BarlineType::from_kind(kind).unwrap();
+
+            let caret = insert_barline_at(&mut cells, 1, 1, barline);
+
+            assert_eq!(caret, 2);
+            assert_eq!(cells[1].kind, ElementKind::Barline, "{}", kind);
+            assert_eq!(cells[1].glyph, glyph);
+            assert_eq!(glyphs(&cells), vec!["1", glyph, " ", "2"]);
+        }
+    }
+
+    #[test]
+    fn test_insert_barline_replaces_selection() {
+        let mut cells = parse_cells("1 2 3", PitchSystem::Number);
+
+        let caret = insert_barline_at(&mut cells, 2, 3, BarlineType::Double);
+
+        assert_eq!(glyphs(&cells), vec!["1", " ", "||", " ", "3"]);
+        assert_eq!(caret, 3);
+        assert!(cells.iter().enumerate().all(|(i, c)| c.col == i));
+        assert!(BarlineType::from_kind("triple").is_none());
+    }
+
+    #[test]
+    fn test_insert_barline_in_line_is_undoable() {
+        let mut document = Document::new();
+        document.add_line(line_with("1 2 3", ""));
+
+        assert_eq!(insert_barline_in_line(&mut document, 0, 3, 2, BarlineType::Final), Ok(3));
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1", " ", "|||", " ", "3"]);
+        undo(&mut document).unwrap();
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1", " ", "2", " ", "3"]);
+
+        document.lines[0].locked = true;
+        assert!(insert_barline_in_line(&mut document, 0, 1, 1, BarlineType::Single).is_err());
+        assert_eq!(document.lines[0].cells.len(), 5);
+    }
+
+    fn line_with(text: &str, time_signature: &str) -> Line {
+        let mut line = Line::new();
+        line.cells = parse_cells(text, PitchSystem::Number);
+        line.time_signature = time_signature.to_string();
+        line
+    }
+
+    fn document_with(text: &str, time_signature: &str) -> Document {
+        let mut document = Document::new();
+        document.add_line(line_with(text, time_signature));
+        document
+    }
+
+    #[test]
+    fn test_copy_measure_one_over_measure_three() {
+        let mut line = line_with("1 2 3 | 4 | 5 6 | 7", "");
+        line.cells[0].octave = 1;
+
+        let copied = copy_measure(&line, 0).unwrap();
+        paste_measure(&mut line, 2, &copied).unwrap();
+
+        let text: String = line.cells.iter().map(|c| c.glyph.as_str()).collect();
+        assert_eq!(text, "1 2 3 | 4 | 1 2 3 | 7");
+        assert_eq!(glyphs(&copy_measure(&line, 2).unwrap()), glyphs(&copied));
+        assert_eq!(line.cells[12].octave, 1, "cell metadata is pasted");
+        assert!(line.cells.iter().enumerate().all(|(i, c)| c.col == i));
+        assert!(paste_measure(&mut line, 9, &copied).is_err());
+    }
+
+    #[test]
+    fn test_undo_measure_paste_restores_the_measure() {
+        let mut document = Document::new();
+        document.add_line(line_with("1 2 3 | 4 | 5 6 | 7", ""));
+        let copied = copy_measure(&document.lines[0], 0).unwrap();
+
+        paste_measure_in_line(&mut document, 0, 2, &copied).unwrap();
+        assert_eq!(glyphs(&copy_measure(&document.lines[0], 2).unwrap()), glyphs(&copied));
+        assert_eq!(document.state.history.len(), 1, "one paste is one undo step");
+
+        undo(&mut document).unwrap();
+        assert_eq!(glyphs(&copy_measure(&document.lines[0], 2).unwrap()), vec!["5", " ", "6"]);
+        assert!(paste_measure_in_line(&mut document, 0, 9, &copied).is_err());
+        assert_eq!(document.state.history_index, 0, "a failed paste records nothing");
+    }
+
+    #[test]
+    fn test_fill_measure_with_quarter_rests() {
+        let mut document = document_with("1 2 | 3 4 5 6", "4/4");
+
+        assert_eq!(pad_measure_with_rests(&mut document, 0, 0), Ok(2));
+
+        let measures = build_export_measures_from_line(&document.lines[0]);
+        assert_eq!(measures[0].duration(), Fraction::new(1, 1));
+        let rests: Vec<bool> = measures[0].events().map(|e| e.is_rest()).collect();
+        assert_eq!(rests, vec![false, false, true, true]);
+        assert_eq!(measures[1].duration(), Fraction::new(1, 1), "next measure is untouched");
+    }
+
+    #[test]
+    fn test_fill_measure_under_double_space_separator() {
+        let mut document = document_with("1 2  3 4", "4/4");
+        document.beat_separator = BeatSeparator::DoubleSpace;
+
+        assert_eq!(pad_measure_with_rests(&mut document, 0, 0), Ok(2), "the measure holds two beats");
+        let filled = glyphs(&document.lines[0].cells).concat();
+        assert_eq!(filled, "1 2  3 4  -  -");
+
+        let measures = build_export_measures_for(&document, &document.lines[0]);
+        assert_eq!(measures[0].beats.len(), 4, "each rest is its own beat");
+        assert_eq!(measures[0].duration(), Fraction::new(1, 1));
+        assert_eq!(pad_measure_with_rests(&mut document, 0, 0), Ok(0));
+    }
+
+    #[test]
+    fn test_fill_full_and_overfull_measures() {
+        let mut full = document_with("1 2 3", "3/4");
+        assert_eq!(pad_measure_with_rests(&mut full, 0, 0), Ok(0));
+        assert_eq!(full.lines[0].cells.len(), 5);
+
+        let mut overfull = document_with("1 2 3 4", "3/4");
+        assert!(pad_measure_with_rests(&mut overfull, 0, 0).is_err());
+        assert!(pad_measure_with_rests(&mut overfull, 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_fill_measure_with_base_duration() {
+        let mut document = document_with("1", "2/4");
+        document.lines[0].base_duration = 8;
+
+        assert_eq!(pad_measure_with_rests(&mut document, 0, 0), Ok(1));
+        let line = &document.lines[0];
+        assert_eq!(glyphs(&line.cells), vec!["1", " ", "-", "-", "-"]);
+        assert!(line.cells[2].is_rest() && !line.cells[3].is_rest());
+    }
+
+    #[test]
+    fn test_padded_rests_export_without_breath_marks() {
+        let mut document = document_with("1 2 | 3 4 5 6", "4/4");
+        pad_measure_with_rests(&mut document, 0, 0).unwrap();
+
+        let measures = build_export_measures_from_line(&document.lines[0]);
+        assert!(measures[0].events().all(|event| !event.breath_mark));
+        assert!(!measures[0].events().nth(2).unwrap().tie_stop, "the rest does not extend the 2");
+
+        let music = crate::renderers::lilypond::music_expression(&measures, Some("C"));
+        assert_eq!(music, "c4 d4 r4 r4 | e4 f4 g4 a4");
+        assert!(!music.contains("\\breathe"));
+    }
+
+    #[test]
+    fn test_auto_barline_after_filling_a_measure() {
+        let mut document = Document::new();
+        document.auto_barline = true;
+        document.add_line(line_with("", "4/4"));
+        document.lines[0].pitch_system = 1;
+
+        let mut caret = 0;
+        for c in "1 2 3 4 5".chars() {
+            caret = insert_text(&mut document, 0, caret, &c.to_string()).unwrap();
+        }
+
+        let cells = &document.lines[0].cells;
+        assert_eq!(glyphs(cells), vec!["1", " ", "2", " ", "3", " ", "4", " ", "|", " ", "5"]);
+        assert_eq!(cells[8].kind, ElementKind::Barline);
+        assert_eq!(caret, cells.len());
+
+        // Undoing the space that closed the fourth beat also removes the barline
+        let history = &document.state.history;
+        let closing = &history[history.len() - 2];
+        assert_eq!(glyphs(&closing.previous_state.as_ref().unwrap().lines[0].cells).len(), 7);
+        assert_eq!(glyphs(&closing.new_state.as_ref().unwrap().lines[0].cells).len(), 10);
+    }
+
+    #[test]
+    fn test_insert_text_types_like_insert_character() {
+        let mut document = Document::new();
+        document.notation_tokens = NotationTokens { dash: None, rest: Some('z') };
+        document.max_accidental_depth = 1;
+        document.add_line(line_with("", "4/4"));
+        document.lines[0].pitch_system = 1;
+
+        let mut caret = 0;
+        for c in "1#z 2##".chars() {
+            caret = insert_text(&mut document, 0, caret, &c.to_string()).unwrap();
+        }
+
+        let cells = &document.lines[0].cells;
+        let mut typed = Vec::new();
+        for c in "1#z 2##".chars() {
+            let at = typed.len();
+            insert_character_at(&mut typed, c, at, PitchSystem::Number, 1, &document.notation_tokens);
+        }
+        assert_eq!(glyphs(cells), glyphs(&typed));
+        assert!(cells[1].is_rest(), "the dialect rest token is understood");
+        assert_eq!(caret, cells.len());
+    }
+
+    #[test]
+    fn test_auto_barline_waits_for_a_full_closed_beat() {
+        let mut document = document_with("1 2 3 4", "4/4");
+        assert_eq!(auto_insert_barline(&mut document, 0, 7), 7, "the last beat is still open");
+
+        let mut partial = document_with("1 2 3 ", "4/4");
+        assert_eq!(auto_insert_barline(&mut partial, 0, 6), 6);
+
+        let mut existing = document_with("1 2 3 4 | 5", "4/4");
+        assert_eq!(auto_insert_barline(&mut existing, 0, 8), 8);
+        assert_eq!(existing.lines[0].cells.len(), 11);
+
+        let mut disabled = Document::new();
+        disabled.add_line(line_with("1 2 3 4", "4/4"));
+        assert_eq!(insert_text(&mut disabled, 0, 7, " ").unwrap(), 8);
+        assert_eq!(disabled.lines[0].cells.len(), 8);
+    }
+
+    #[test]
+    fn test_auto_barline_under_double_space_separator() {
+        let mut document = document_with("1 2 3 4 ", "4/4");
+        document.beat_separator = BeatSeparator::DoubleSpace;
+        assert_eq!(auto_insert_barline(&mut document, 0, 8), 8, "one space does not close the beat");
+
+        document.lines[0].cells = parse_cells("1 2 3 4  ", PitchSystem::Number);
+        assert_eq!(auto_insert_barline(&mut document, 0, 9), 9, "\"1 2 3 4\" is a single beat");
+
+        document.lines[0].cells = parse_cells("1 2  3  4  5  ", PitchSystem::Number);
+        assert_eq!(auto_insert_barline(&mut document, 0, 13), 13);
+        assert_eq!(auto_insert_barline(&mut document, 0, 14), 16);
+        assert_eq!(glyphs(&document.lines[0].cells).concat(), "1 2  3  4  5  | ");
+    }
+
+    #[test]
+    fn test_swap_ranges_of_equal_length() {
+        let mut document = Document::new();
+        document.add_line(line_with("1 2 3 4 5 6", "4/4"));
+        document.lines[0].cells[0].octave = 1;
+
+        swap_ranges(&mut document, 0, 8..11, 0..3).unwrap();
+        let cells = &document.lines[0].cells;
+        assert_eq!(glyphs(cells), vec!["5", " ", "6", " ", "3", " ", "4", " ", "1", " ", "2"]);
+        assert_eq!(cells[8].octave, 1, "annotations move with their cells");
+        assert!(cells.iter().enumerate().all(|(index, cell)| cell.col == index));
+        assert_eq!(document.state.history.len(), 1);
+    }
+
+    #[test]
+    fn test_swap_ranges_of_different_length() {
+        let mut document = Document::new();
+        document.add_line(line_with("1 2 3 4 5 6", "4/4"));
+
+        swap_ranges(&mut document, 0, 0..1, 8..11).unwrap();
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["5", " ", "6", " ", "2", " ", "3", " ", "4", " ", "1"]);
+
+        assert!(swap_ranges(&mut document, 0, 0..5, 4..8).is_err(), "overlapping ranges");
+        assert!(swap_ranges(&mut document, 0, 0..1, 2..2).is_err(), "empty range");
+        assert!(swap_ranges(&mut document, 0, 0..1, 8..20).is_err(), "past the end");
+        assert_eq!(document.state.history.len(), 1);
+    }
+
+    #[test]
+    fn test_toggle_grace_slash() {
+        let mut cells = parse_cells("1 2", PitchSystem::Number);
+        cells[0].ornament = OrnamentType::Appoggiatura;
+
+        assert_eq!(toggle_grace_slash(&mut cells, 0), Ok(true));
+        assert_eq!(cells[0].ornament, OrnamentType::Acciaccatura);
+        assert_eq!(toggle_grace_slash(&mut cells, 0), Ok(false));
+        assert_eq!(cells[0].ornament, OrnamentType::Appoggiatura);
+        assert!(toggle_grace_slash(&mut cells, 2).is_err(), "not a grace note");
+    }
+
+    #[test]
+    fn test_sargam_case_assist_corrects_to_the_scale() {
+        let mut document = Document::new();
+        document.pitch_system = Some(PitchSystem::Sargam);
+        document.add_line(Line::new());
+        document.active_constraint = "major".to_string();
+
+        let (_, corrections) = insert_text_with_report(&mut document, 0, 0, "S r").unwrap();
+        assert!(corrections.is_empty(), "assist is off");
+        assert_eq!(document.lines[0].cells[2].glyph, "r");
+
+        document.sargam_case_assist = true;
+        let (caret, corrections) = insert_text_with_report(&mut document, 0, 3, " r g").unwrap();
+        assert_eq!(caret, 7);
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["S", " ", "r", " ", "R", " ", "G"]);
+        assert_eq!(document.lines[0].cells[4].pitch_code.as_deref(), Some("R"));
+        assert_eq!(corrections.len(), 2);
+        assert_eq!(corrections[0].position, CursorPosition::at(0, 4));
+        assert_eq!(corrections[0].kind, DiagnosticKind::CaseCorrected);
+        assert!(corrections[0].message.contains("'R'"), "{}", corrections[0].message);
+
+        // Komal Ga belongs to the minor scale, so it is kept
+        document.active_constraint = "minor".to_string();
+        let (_, corrections) = insert_text_with_report(&mut document, 0, 7, " g").unwrap();
+        assert!(corrections.is_empty());
+    }
+
+    #[test]
+    fn test_last_barline_promoted_to_final() {
+        let mut document = Document::new();
+        document.add_line(line_with("1 2 | 3 4 |", "2/4"));
+        document.add_line(line_with("5 6 | 7 1 |", "2/4"));
+
+        assert!(offers_final_barline(&document, 1, 11));
+        assert!(!offers_final_barline(&document, 1, 5), "an inner measure stays single");
+        assert!(!offers_final_barline(&document, 0, 11), "not the last line");
+
+        assert_eq!(finalize_last_barline(&mut document), Ok(CursorPosition::at(1, 10)));
+        assert_eq!(document.lines[1].cells[10].glyph, "|||");
+        assert_eq!(document.lines[1].cells[4].glyph, "|");
+        assert_eq!(document.state.history.len(), 1);
+
+        let xml = crate::renderers::musicxml::MusicXMLExport::export_document(&document);
+        assert_eq!(xml.matches("<bar-style>light-heavy</bar-style>").count(), 1);
+
+        assert_eq!(finalize_last_barline(&mut document), Ok(CursorPosition::at(1, 10)));
+        assert_eq!(document.state.history.len(), 1, "already final");
+        assert!(finalize_last_barline(&mut Document::new()).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_line_gets_its_own_part() {
+        let mut document = Document::new();
+        let mut line = line_with("1 (2 3)", "3/4");
+        line.lyrics = "la la la".to_string();
+        document.add_line(line);
+        document.add_line(line_with("5", ""));
+        document.recalculate_system_and_part_ids();
+
+        assert_eq!(duplicate_line_below(&mut document, 0), Ok(1));
+        assert_eq!(document.lines.len(), 3);
+        let (original, copy) = (&document.lines[0], &document.lines[1]);
+        assert_eq!(copy.cells, original.cells);
+        assert_eq!((copy.lyrics.as_str(), copy.time_signature.as_str()), ("la la la", "3/4"));
+        assert_eq!(document.lines.iter().map(|line| line.part_id.as_str()).collect::<Vec<_>>(), vec!["P1", "P2", "P3"]);
+        assert_eq!(document.state.history.len(), 1);
+        assert!(duplicate_line_below(&mut document, 5).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_line_stays_in_its_system() {
+        let mut document = Document::new();
+        for marker in [SystemMarker::Start, SystemMarker::End] {
+            let mut line = line_with("1", "");
+            line.system_marker = marker;
+            document.add_line(line);
+        }
+        document.recalculate_system_and_part_ids();
+
+        duplicate_line_below(&mut document, 1).unwrap();
+        duplicate_line_below(&mut document, 0).unwrap();
+        let markers: Vec<SystemMarker> = document.lines.iter().map(|line| line.system_marker).collect();
+        assert_eq!(markers, vec![SystemMarker::Start, SystemMarker::None, SystemMarker::None, SystemMarker::End]);
+        assert!(document.lines.iter().all(|line| line.system_id == 1));
+    }
+
+    #[test]
+    fn test_merge_two_quarters_into_a_half() {
+        let mut document = Document::new();
+        document.add_line(line_with("1 1 2", "4/4"));
+        let note_length = |line: &Line| {
+            build_export_measures_from_line(line)[0]
+                .events()
+                .take_while(|event| event.cell_index == 0 || event.tie_stop)
+                .fold(Fraction::zero(), |total, event| total + event.duration)
+        };
+        assert_eq!(note_length(&document.lines[0]), Fraction::new(1, 4));
+
+        assert_eq!(merge_notes(&mut document, 0, 0..3), Ok(1));
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1", " ", "-", " ", "2"]);
+        assert_eq!(note_length(&document.lines[0]), Fraction::new(1, 2));
+        assert_eq!(document.state.history.len(), 1);
+
+        let mut eighths = Document::new();
+        eighths.add_line(line_with("11", "4/4"));
+        merge_notes(&mut eighths, 0, 0..2).unwrap();
+        let measures = build_export_measures_from_line(&eighths.lines[0]);
+        let events: Vec<Fraction> = measures[0].events().map(|event| event.duration).collect();
+        assert_eq!(events, vec![Fraction::new(1, 4)]);
+    }
+
+    #[test]
+    fn test_merge_rejects_different_pitches() {
+        let mut document = Document::new();
+        document.add_line(line_with("1 2", "4/4"));
+
+        let error = merge_notes(&mut document, 0, 0..3).unwrap_err();
+        assert!(error.contains("different pitches"), "{}", error);
+        assert!(merge_notes(&mut document, 0, 0..1).is_err(), "a single note");
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1", " ", "2"]);
+        assert!(document.state.history.is_empty());
+    }
+
+    #[test]
+    fn test_locked_line_refuses_edits() {
+        let mut document = Document::new();
+        document.add_line(line_with("1 2", "4/4"));
+        document.lines[0].locked = true;
+        let before = document.clone();
+
+        let error = insert_text(&mut document, 0, 3, " 3").unwrap_err();
+        assert!(error.contains("locked"), "{}", error);
+        assert!(delete_character_in_line(&mut document, 0, 0).is_err());
+        assert!(set_line_source(&mut document, 0, "5").is_err());
+        assert!(swap_ranges(&mut document, 0, 0..1, 2..3).is_err());
+        assert!(insert_midi_note_at(&mut document, 0, 0, 60, None).is_err());
+        assert_eq!(document, before);
+
+        document.lines[0].locked = false;
+        assert!(insert_text(&mut document, 0, 3, " 3").is_ok());
+        assert!(delete_character_in_line(&mut document, 0, 4).is_ok());
+        assert_eq!(glyphs(&document.lines[0].cells), vec!["1", " ", "2", " "]);
+    }
+}
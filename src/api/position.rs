@@ -0,0 +1,318 @@
+//! Position conversion helpers
+//!
+//! Cells are addressed by index, but a cell's glyph may span several
+//! characters ("1#", "||") and a character may span several bytes (PUA
+//! glyphs, "♯", "♭"). These helpers keep all caret math in character units.
+
+use serde::{Deserialize, Serialize};
+use crate::models::{BeatSeparator, Cell, OrnamentType};
+use crate::parse::beats::BeatDeriver;
+
+/// Number of visible characters in a string (never the byte length)
+pub fn char_len(s: &str) -> usize {
+    s.chars().count()
+}
+
+/// Total number of visible characters across a slice of cells
+pub fn cells_char_len(cells: &[Cell]) -> usize {
+    cells.iter().map(|cell| char_len(&cell.glyph)).sum()
+}
+
+/// Convert a cell index into the character offset where that cell begins
+///
+/// Indices past the end map to the end of the line.
+pub fn cell_index_to_char_pos(cells: &[Cell], index: usize) -> usize {
+    cells_char_len(&cells[..index.min(cells.len())])
+}
+
+/// Convert a character offset into the index of the cell containing it
+///
+/// Offsets past the end map to `cells.len()` (the end-of-line caret).
+pub fn char_pos_to_cell_index(cells: &[Cell], char_pos: usize) -> usize {
+    let mut pos = 0;
+    for (index, cell) in cells.iter().enumerate() {
+        let len = char_len(&cell.glyph);
+        if char_pos < pos + len {
+            return index;
+        }
+        pos += len;
+    }
+    cells.len()
+}
+
+/// Caret offset for a click at horizontal pixel `x`
+///
+/// Uses the cells' last computed layout. A click lands on the nearer edge of
+/// the cell under it, so clicking inside a multi-character glyph never puts
+/// the caret between its characters. Clicks left of the line go to 0 and
+/// clicks right of it to the end.
+pub fn pixel_to_char_pos(cells: &[Cell], x: f32) -> usize {
+    let mut pos = 0;
+    for cell in cells {
+        if x < cell.x + cell.w {
+            return if x < cell.x + cell.w / 2.0 { pos } else { pos + char_len(&cell.glyph) };
+        }
+        pos += char_len(&cell.glyph);
+    }
+    pos
+}
+
+/// Caret rectangle in CSS pixels
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct CaretPixel {
+    pub x: f32,
+    pub y: f32,
+    pub height: f32,
+}
+
+/// Convert a character offset into the caret's pixel position
+///
+/// Uses the cells' last computed layout (`x`, `y`, `w`, `h`). A caret inside
+/// a multi-character cell is placed proportionally across its width; a caret
+/// at the end of the line sits after the last cell. An empty line puts the
+/// caret at the origin with zero height.
+pub fn char_pos_to_pixel(cells: &[Cell], char_pos: usize) -> CaretPixel {
+    let index = char_pos_to_cell_index(cells, char_pos);
+
+    match cells.get(index) {
+        Some(cell) => {
+            let offset = char_pos - cell_index_to_char_pos(cells, index);
+            let fraction = offset as f32 / char_len(&cell.glyph).max(1) as f32;
+            CaretPixel { x: cell.x + cell.w * fraction, y: cell.y, height: cell.h }
+        }
+        None => match cells.last() {
+            Some(last) => CaretPixel { x: last.x + last.w, y: last.y, height: last.h },
+            None => CaretPixel { x: 0.0, y: 0.0, height: 0.0 },
+        },
+    }
+}
+
+/// Where a position lies within a slur
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlurRole {
+    Start,
+    Middle,
+    End,
+}
+
+/// Annotations that apply at a cell, for context menus
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CellAnnotations {
+    /// Role of the cell in a slur, or None outside any slur
+    pub slur: Option<SlurRole>,
+
+    /// Ornament on the cell (notes only)
+    pub ornament: Option<OrnamentType>,
+
+    /// Octave marking on the cell (0 for none)
+    pub octave: i8,
+}
+
+/// Annotations at cell `index`, or None past the end of the line
+///
+/// Any cell between a slur's start and end is `Middle`, including the
+/// whitespace and barlines it spans, so "remove slur" can be offered there.
+pub fn annotations_at(cells: &[Cell], index: usize) -> Option<CellAnnotations> {
+    let cell = cells.get(index)?;
+
+    let slur = if cell.is_slur_start() {
+        Some(SlurRole::Start)
+    } else if cell.is_slur_end() {
+        Some(SlurRole::End)
+    } else {
+        let opened = cells[..index].iter().rev().find(|c| c.has_slur()).is_some_and(Cell::is_slur_start);
+        let closed = cells[index + 1..].iter().find(|c| c.has_slur()).is_some_and(Cell::is_slur_end);
+        (opened && closed).then_some(SlurRole::Middle)
+    };
+
+    Some(CellAnnotations {
+        slur,
+        ornament: Some(cell.ornament).filter(|ornament| *ornament != OrnamentType::None),
+        octave: cell.octave,
+    })
+}
+
+/// Where a cell lies under its beat's underline
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnderlineRole {
+    None,
+    Left,
+    Middle,
+    Right,
+}
+
+/// Underline (beat) and overline (slur) roles of a cell, without pixel layout
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct CellGrouping {
+    pub underline: UnderlineRole,
+    pub slur: Option<SlurRole>,
+}
+
+/// Beat underline and slur roles for every cell of a line
+///
+/// Beats are derived the way `Line::reflow_beats_with` derives them; a beat
+/// of a single cell has no underline.
+pub fn beat_groupings(cells: &[Cell], separator: BeatSeparator) -> Vec<CellGrouping> {
+    let mut underlines = vec![UnderlineRole::None; cells.len()];
+    for beat in BeatDeriver::with_separator(separator).extract_implicit_beats(cells) {
+        if beat.end > beat.start {
+            underlines[beat.start] = UnderlineRole::Left;
+            underlines[beat.start + 1..beat.end].fill(UnderlineRole::Middle);
+            underlines[beat.end] = UnderlineRole::Right;
+        }
+    }
+
+    underlines
+        .into_iter()
+        .enumerate()
+        .map(|(index, underline)| CellGrouping {
+            underline,
+            slur: annotations_at(cells, index).and_then(|annotations| annotations.slur),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Document, ElementKind, Line, PitchSystem};
+    use crate::parse::grammar::parse_cells;
+
+    #[test]
+    fn test_char_len_counts_chars_not_bytes() {
+        assert_eq!(char_len("1#"), 2);
+        assert_eq!(char_len("1♯"), 2);
+        assert_eq!("1♯".len(), 4);
+        assert_eq!(char_len("\u{E000}"), 1);
+    }
+
+    #[test]
+    fn test_caret_after_multibyte_text_is_visible_char_count() {
+        let text = "1 ♭♮ 2";
+        let cells = parse_cells(text, PitchSystem::Number);
+
+        let end_caret = cell_index_to_char_pos(&cells, cells.len());
+        assert_eq!(end_caret, text.chars().count());
+        assert_ne!(end_caret, text.len());
+    }
+
+    #[test]
+    fn test_cursor_column_after_multibyte_insert_is_visible_char_count() {
+        let mut cells = parse_cells("2", PitchSystem::Number);
+        let text = "1 \u{E000}\u{E001} ";
+        let caret = crate::api::edit::replace_range_with_text(&mut cells, 0, 0, text, PitchSystem::Number);
+        assert_eq!(cell_index_to_char_pos(&cells, caret), char_len(text));
+        assert_ne!(cell_index_to_char_pos(&cells, caret), text.len());
+
+        let mut document = Document::new();
+        let mut line = Line::new();
+        line.cells = parse_cells("1 2", PitchSystem::Number);
+        document.add_line(line);
+        let text = "♭♮ ";
+        let caret = crate::api::edit::insert_text(&mut document, 0, 2, text).unwrap();
+        assert_eq!(cell_index_to_char_pos(&document.lines[0].cells, caret), 2 + char_len(text));
+    }
+
+    #[test]
+    fn test_multibyte_single_char_is_not_multichar_token() {
+        let cells = parse_cells("♮", PitchSystem::Number);
+        assert_eq!(cells.len(), 1);
+        assert_eq!(cells[0].kind, ElementKind::Text);
+        assert_eq!(char_pos_to_cell_index(&cells, 0), 0);
+        assert_eq!(char_pos_to_cell_index(&cells, 1), 1);
+    }
+
+    #[test]
+    fn test_char_pos_round_trip_with_combined_cells() {
+        let cells = parse_cells("1# 2", PitchSystem::Number);
+        assert_eq!(cells.len(), 3);
+        assert_eq!(cell_index_to_char_pos(&cells, 1), 2);
+        assert_eq!(char_pos_to_cell_index(&cells, 1), 0);
+        assert_eq!(char_pos_to_cell_index(&cells, 2), 1);
+        assert_eq!(char_pos_to_cell_index(&cells, 3), 2);
+    }
+
+    #[test]
+    fn test_caret_pixel_x_increases_across_line() {
+        let mut cells = parse_cells("1# 2 3", PitchSystem::Number);
+        let mut x = 10.0;
+        for cell in &mut cells {
+            let width = 8.0 * char_len(&cell.glyph) as f32;
+            cell.update_layout(x, 4.0, width, 16.0);
+            x += width;
+        }
+
+        let end = cells_char_len(&cells);
+        let positions: Vec<CaretPixel> = (0..=end).map(|pos| char_pos_to_pixel(&cells, pos)).collect();
+
+        assert!(positions.windows(2).all(|pair| pair[1].x > pair[0].x));
+        assert_eq!(positions[0], CaretPixel { x: 10.0, y: 4.0, height: 16.0 });
+        assert_eq!(positions[end].x, x, "end-of-line caret sits after the last cell");
+        assert_eq!(char_pos_to_pixel(&[], 0).height, 0.0);
+    }
+
+    fn laid_out(text: &str) -> Vec<Cell> {
+        let mut cells = parse_cells(text, PitchSystem::Number);
+        let mut x = 0.0;
+        for cell in &mut cells {
+            let width = 10.0 * char_len(&cell.glyph) as f32;
+            cell.update_layout(x, 0.0, width, 16.0);
+            x += width;
+        }
+        cells
+    }
+
+    #[test]
+    fn test_click_inside_double_barline_snaps_to_cell_boundary() {
+        let cells = laid_out("1 || 2");
+        assert_eq!(cells[2].glyph, "||");
+        assert_eq!(cells[2].x, 20.0);
+
+        assert_eq!(pixel_to_char_pos(&cells, 22.0), 2, "left half of the barline");
+        assert_eq!(pixel_to_char_pos(&cells, 30.0), 4, "middle, between the two bars");
+        assert_eq!(pixel_to_char_pos(&cells, 38.0), 4, "right half of the barline");
+        assert_eq!(pixel_to_char_pos(&cells, -5.0), 0);
+        assert_eq!(pixel_to_char_pos(&cells, 500.0), 6);
+    }
+
+    #[test]
+    fn test_annotations_at_slur_and_ornament_cells() {
+        let mut cells = parse_cells("1 2 | 3 4", PitchSystem::Number);
+        cells[0].set_slur_start();
+        cells[6].set_slur_end();
+        cells[8].ornament = OrnamentType::Trill;
+        cells[8].octave = 1;
+
+        let at = |index| annotations_at(&cells, index).unwrap();
+        assert_eq!(at(0).slur, Some(SlurRole::Start));
+        assert_eq!(at(2).slur, Some(SlurRole::Middle));
+        assert_eq!(at(4).slur, Some(SlurRole::Middle), "a barline inside the slur");
+        assert_eq!(at(6).slur, Some(SlurRole::End));
+        assert_eq!(at(7).slur, None, "whitespace after the slur");
+
+        assert_eq!(at(8), CellAnnotations { slur: None, ornament: Some(OrnamentType::Trill), octave: 1 });
+        assert_eq!(at(2).ornament, None);
+        assert!(annotations_at(&cells, 9).is_none());
+    }
+
+    #[test]
+    fn test_beat_groupings_underline_each_beat() {
+        let mut cells = parse_cells("S--r g-m", PitchSystem::Sargam);
+        cells[0].set_slur_start();
+        cells[5].set_slur_end();
+
+        let groupings = beat_groupings(&cells, BeatSeparator::SingleSpace);
+        let underlines: Vec<UnderlineRole> = groupings.iter().map(|g| g.underline).collect();
+        use UnderlineRole::{Left, Middle, Right};
+        assert_eq!(underlines, vec![Left, Middle, Middle, Right, UnderlineRole::None, Left, Middle, Right]);
+
+        let slurs: Vec<Option<SlurRole>> = groupings.iter().map(|g| g.slur).collect();
+        assert_eq!(slurs[0], Some(SlurRole::Start));
+        assert_eq!(slurs[4], Some(SlurRole::Middle));
+        assert_eq!(slurs[5], Some(SlurRole::End));
+        assert_eq!(slurs[7], None);
+
+        let single = beat_groupings(&parse_cells("1 2", PitchSystem::Number), BeatSeparator::SingleSpace);
+        assert!(single.iter().all(|g| g.underline == UnderlineRole::None), "single-note beats are not underlined");
+    }
+}
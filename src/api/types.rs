@@ -0,0 +1,135 @@
+//! Option and result types shared by the editing API
+
+use serde::{Deserialize, Serialize};
+use crate::api::diagnostics::DiagnosticMark;
+use crate::models::barlines::BarlineType;
+use crate::models::{Cell, CursorPosition, Document, ElementKind};
+
+/// A document after an edit, with the caret and what the edit reported
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EditResult {
+    pub document: Document,
+
+    /// Cell index of the caret after the edit
+    pub caret: usize,
+
+    /// Input-assist corrections and other notes about the edit
+    pub diagnostics: Vec<DiagnosticMark>,
+}
+
+/// A document after another client's edit was applied
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RemoteEditResult {
+    pub document: Document,
+
+    /// Indices of the lines the remote edit replaced, in patch order
+    pub changed_lines: Vec<usize>,
+
+    /// The sending client's cursor, if it shared one
+    pub remote_cursor: Option<CursorPosition>,
+}
+
+/// A cell array after an edit, with the caret
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CellEditResult {
+    pub cells: Vec<Cell>,
+
+    /// Cell index of the caret after the edit
+    pub caret: usize,
+}
+
+/// How `paste_cells` applies clipboard cells to the target
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PasteMode {
+    /// Insert the clipboard cells at the target position
+    #[default]
+    Insert,
+
+    /// Copy octave, slur and ornament from each clipboard cell onto the
+    /// existing cell at the same offset, keeping its pitch ("paste
+    /// articulation only")
+    Overlay,
+}
+
+impl PasteMode {
+    /// Parse a mode name ("overlay", or anything else for insert)
+    pub fn parse(text: &str) -> Self {
+        match text.trim().to_lowercase().as_str() {
+            "overlay" => PasteMode::Overlay,
+            _ => PasteMode::Insert,
+        }
+    }
+}
+
+/// How `copy_text` turns copied cells into text
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CopyMode {
+    /// Concatenate the typed text of the cells
+    #[default]
+    Plain,
+
+    /// Keep every cell on its column of the editor's grid, so the text
+    /// stays aligned when pasted into a monospace text document
+    Layout,
+}
+
+impl CopyMode {
+    /// Parse a mode name ("layout", or anything else for plain)
+    pub fn parse(text: &str) -> Self {
+        match text.trim().to_lowercase().as_str() {
+            "layout" => CopyMode::Layout,
+            _ => CopyMode::Plain,
+        }
+    }
+}
+
+/// Which cells a select-by-kind command picks
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum CellFilter {
+    /// Every cell of an element kind
+    Kind(ElementKind),
+
+    /// Barline cells of one barline type
+    Barline(BarlineType),
+}
+
+impl CellFilter {
+    /// Parse a filter name, ignoring case, spaces, "-" and "_"
+    ///
+    /// Element kinds: "pitched", "unpitched", "barline", "breath-mark",
+    /// "whitespace", "text" (the full kind names such as "PitchedElement"
+    /// also work). Barline types: "SingleBarline", "DoubleBarline",
+    /// "StartRepeat", "EndRepeat", "FinalBarline".
+    pub fn parse(text: &str) -> Option<Self> {
+        let name: String = text
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '_'))
+            .collect::<String>()
+            .to_lowercase();
+        let filter = match name.as_str() {
+            "pitched" | "pitchedelement" => CellFilter::Kind(ElementKind::PitchedElement),
+            "unpitched" | "unpitchedelement" => CellFilter::Kind(ElementKind::UnpitchedElement),
+            "barline" | "barlines" => CellFilter::Kind(ElementKind::Barline),
+            "breath" | "breathmark" => CellFilter::Kind(ElementKind::BreathMark),
+            "whitespace" => CellFilter::Kind(ElementKind::Whitespace),
+            "text" => CellFilter::Kind(ElementKind::Text),
+            "singlebarline" => CellFilter::Barline(BarlineType::Single),
+            "doublebarline" => CellFilter::Barline(BarlineType::Double),
+            "startrepeat" | "repeatleft" => CellFilter::Barline(BarlineType::StartRepeat),
+            "endrepeat" | "repeatright" => CellFilter::Barline(BarlineType::EndRepeat),
+            "finalbarline" => CellFilter::Barline(BarlineType::Final),
+            _ => return None,
+        };
+        Some(filter)
+    }
+
+    /// Check if a cell passes the filter
+    pub fn matches(&self, cell: &Cell) -> bool {
+        match self {
+            CellFilter::Kind(kind) => cell.kind == *kind,
+            CellFilter::Barline(barline) => {
+                cell.kind == ElementKind::Barline && BarlineType::parse(&cell.glyph) == Some(*barline)
+            }
+        }
+    }
+}
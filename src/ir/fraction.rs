@@ -0,0 +1,120 @@
+//! Exact rational durations
+//!
+//! Beat subdivisions (thirds, fifths) are not representable in binary
+//! floating point, so durations are kept as reduced fractions of a whole note.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{Add, Div, Mul, Sub};
+
+/// A non-negative reduced fraction
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Fraction {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+/// Greatest common divisor
+pub fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Least common multiple
+pub fn lcm(a: u32, b: u32) -> u32 {
+    if a == 0 || b == 0 { 0 } else { a / gcd(a, b) * b }
+}
+
+impl Fraction {
+    /// Create a fraction, reduced to lowest terms
+    pub fn new(numerator: u32, denominator: u32) -> Self {
+        assert!(denominator != 0, "fraction denominator must be non-zero");
+        let divisor = gcd(numerator, denominator).max(1);
+        Self {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        }
+    }
+
+    /// The zero duration
+    pub fn zero() -> Self {
+        Self { numerator: 0, denominator: 1 }
+    }
+
+    /// Check if this fraction is zero
+    pub fn is_zero(&self) -> bool {
+        self.numerator == 0
+    }
+
+    /// Approximate value as a float
+    pub fn to_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+impl Default for Fraction {
+    fn default() -> Self {
+        Self::zero()
+    }
+}
+
+impl Add for Fraction {
+    type Output = Fraction;
+
+    fn add(self, other: Fraction) -> Fraction {
+        let denominator = lcm(self.denominator, other.denominator);
+        Fraction::new(
+            self.numerator * (denominator / self.denominator)
+                + other.numerator * (denominator / other.denominator),
+            denominator,
+        )
+    }
+}
+
+impl Sub for Fraction {
+    type Output = Fraction;
+
+    /// Subtract a fraction; panics if the result would be negative
+    fn sub(self, other: Fraction) -> Fraction {
+        let denominator = lcm(self.denominator, other.denominator);
+        Fraction::new(
+            self.numerator * (denominator / self.denominator)
+                - other.numerator * (denominator / other.denominator),
+            denominator,
+        )
+    }
+}
+
+impl Div for Fraction {
+    type Output = Fraction;
+
+    fn div(self, other: Fraction) -> Fraction {
+        Fraction::new(self.numerator * other.denominator, self.denominator * other.numerator)
+    }
+}
+
+impl Mul for Fraction {
+    type Output = Fraction;
+
+    fn mul(self, other: Fraction) -> Fraction {
+        Fraction::new(self.numerator * other.numerator, self.denominator * other.denominator)
+    }
+}
+
+impl PartialOrd for Fraction {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Fraction {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.numerator as u64 * other.denominator as u64)
+            .cmp(&(other.numerator as u64 * self.denominator as u64))
+    }
+}
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
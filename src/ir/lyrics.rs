@@ -0,0 +1,133 @@
+//! Lyric syllable distribution
+//!
+//! A lyric verse is split into syllables that are assigned one per sung
+//! note. Words are separated by whitespace and syllables within a word by
+//! hyphens ("Twin-kle twin-kle"). An underscore joins two syllables onto
+//! one note as an elision ("me_and").
+
+use serde::{Deserialize, Serialize};
+
+/// Position of a syllable within its word
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syllabic {
+    Single,
+    Begin,
+    Middle,
+    End,
+}
+
+impl Syllabic {
+    /// MusicXML syllabic value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Syllabic::Single => "single",
+            Syllabic::Begin => "begin",
+            Syllabic::Middle => "middle",
+            Syllabic::End => "end",
+        }
+    }
+
+    /// Whether the syllable starts a word and whether it ends one
+    fn word_edges(&self) -> (bool, bool) {
+        match self {
+            Syllabic::Single => (true, true),
+            Syllabic::Begin => (true, false),
+            Syllabic::Middle => (false, false),
+            Syllabic::End => (false, true),
+        }
+    }
+
+    fn from_word_edges(starts: bool, ends: bool) -> Self {
+        match (starts, ends) {
+            (true, true) => Syllabic::Single,
+            (true, false) => Syllabic::Begin,
+            (false, false) => Syllabic::Middle,
+            (false, true) => Syllabic::End,
+        }
+    }
+}
+
+/// One syllable of a lyric verse
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LyricSyllable {
+    pub text: String,
+    pub syllabic: Syllabic,
+}
+
+impl LyricSyllable {
+    /// The syllables sung on this note, split at elisions ("me_and")
+    ///
+    /// The first part keeps the syllable's word start and the last part its
+    /// word end; the underscores between them are word boundaries.
+    pub fn elided_parts(&self) -> Vec<(Syllabic, &str)> {
+        let parts: Vec<&str> = self.text.split('_').filter(|part| !part.is_empty()).collect();
+        if parts.is_empty() {
+            return vec![(self.syllabic, self.text.as_str())];
+        }
+
+        let (starts, ends) = self.syllabic.word_edges();
+        let last = parts.len() - 1;
+        parts
+            .into_iter()
+            .enumerate()
+            .map(|(index, part)| (Syllabic::from_word_edges(index > 0 || starts, index < last || ends), part))
+            .collect()
+    }
+}
+
+/// Split a lyric verse into syllables, in the order they are sung
+pub fn distribute_lyrics(text: &str) -> Vec<LyricSyllable> {
+    let mut syllables = Vec::new();
+
+    for word in text.split_whitespace() {
+        let parts: Vec<&str> = word.split('-').filter(|part| !part.is_empty()).collect();
+        let last = parts.len().saturating_sub(1);
+
+        for (index, part) in parts.iter().enumerate() {
+            let syllabic = match (index, last) {
+                (_, 0) => Syllabic::Single,
+                (0, _) => Syllabic::Begin,
+                (i, l) if i == l => Syllabic::End,
+                _ => Syllabic::Middle,
+            };
+            syllables.push(LyricSyllable { text: part.to_string(), syllabic });
+        }
+    }
+
+    syllables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribute_hyphenated_words() {
+        let syllables = distribute_lyrics("Twin-kle lit-tle-ly star");
+
+        let texts: Vec<&str> = syllables.iter().map(|s| s.text.as_str()).collect();
+        let syllabic: Vec<Syllabic> = syllables.iter().map(|s| s.syllabic).collect();
+        assert_eq!(texts, vec!["Twin", "kle", "lit", "tle", "ly", "star"]);
+        assert_eq!(
+            syllabic,
+            vec![
+                Syllabic::Begin,
+                Syllabic::End,
+                Syllabic::Begin,
+                Syllabic::Middle,
+                Syllabic::End,
+                Syllabic::Single,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_elisions_split_into_parts() {
+        let syllables = distribute_lyrics("me_and Twin-kle_a");
+        assert_eq!(syllables.len(), 3, "an elision stays on one note");
+
+        assert_eq!(syllables[0].elided_parts(), vec![(Syllabic::Single, "me"), (Syllabic::Single, "and")]);
+        assert_eq!(syllables[2].elided_parts(), vec![(Syllabic::End, "kle"), (Syllabic::Single, "a")]);
+        assert_eq!(syllables[1].elided_parts(), vec![(Syllabic::Begin, "Twin")]);
+    }
+}
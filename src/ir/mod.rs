@@ -0,0 +1,971 @@
+//! Export intermediate representation
+//!
+//! Lines of cells are grouped into measures (split at barlines), beats
+//! (via `BeatDeriver`) and timed events. Exporters consume this structure
+//! instead of re-deriving rhythm from cells.
+//!
+//! Rhythm follows the usual letter-notation convention: every beat is one
+//! quarter note, divided equally among its pitched and dash cells. A dash
+//! extends the preceding note; a dash at the start of a beat ties over from
+//! the previous beat, or is a rest if there is no note to extend. A cell
+//! written with the document's rest token always starts a rest, which
+//! following dashes extend.
+//!
+//! A line may instead set a `base_duration` (4 = quarter, 8 = eighth), in
+//! which case every pitched or dash cell lasts one unit note and beats are
+//! only grouping: "1-" is a half note under a quarter base.
+//!
+//! A note marked as an appoggiatura or acciaccatura is a grace note: it
+//! takes no time, and its share of the beat goes to the next note of the
+//! beat (or the previous one when it comes last).
+//!
+//! Some of these values have no written form ("1----" under an eighth base
+//! is five eighths). With `Document::explicit_durations` set they are split
+//! into tied standard values (half + eighth) before export.
+
+pub mod fraction;
+pub mod lyrics;
+
+pub use fraction::*;
+pub use lyrics::*;
+
+use serde::{Deserialize, Serialize};
+use crate::models::{BeatSeparator, Cell, Document, ElementKind, Line, NoteSize, OrnamentType, PitchSystem};
+use crate::parse::beats::BeatDeriver;
+use crate::parse::grammar::FERMATA_TOKEN;
+
+/// Duration of one beat as a fraction of a whole note
+pub const BEAT_DURATION: Fraction = Fraction { numerator: 1, denominator: 4 };
+
+/// Beam state of a note within a beamed group
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BeamState {
+    Begin,
+    Continue,
+    End,
+}
+
+impl BeamState {
+    /// MusicXML beam value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BeamState::Begin => "begin",
+            BeamState::Continue => "continue",
+            BeamState::End => "end",
+        }
+    }
+}
+
+/// Tuplet ratio: `actual` notes in the time of `normal`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TupletInfo {
+    pub actual: u32,
+    pub normal: u32,
+}
+
+/// Pitch carried by a note event
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ExportPitch {
+    pub pitch_code: String,
+    pub pitch_system: PitchSystem,
+    pub octave: i8,
+}
+
+/// A note or rest with an exact duration
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ExportEvent {
+    /// Pitch of the note, or None for a rest
+    pub pitch: Option<ExportPitch>,
+
+    /// Duration as a fraction of a whole note
+    pub duration: Fraction,
+
+    /// Number of beat subdivisions this event spans
+    pub subdivisions: u32,
+
+    /// Tie flags (a leading dash continues the previous note)
+    pub tie_start: bool,
+    pub tie_stop: bool,
+
+    /// Slur flags copied from the source cell's slur indicator
+    pub slur_start: bool,
+    pub slur_stop: bool,
+
+    /// Ornament attached to the note (trill, mordent, turn), if any
+    pub ornament: Option<OrnamentType>,
+
+    /// Grace note with its slash (acciaccatura) flag; grace notes have no duration
+    pub grace: Option<bool>,
+
+    /// Fingering text from the source cell (see `Cell::fingering`)
+    pub fingering: Option<String>,
+
+    /// Fermata over the note ("1^")
+    pub fermata: bool,
+
+    /// Breath mark after the note ("1'")
+    pub breath_mark: bool,
+
+    /// Played short (see `Cell::staccato`)
+    pub staccato: bool,
+
+    /// Printed as a cue-sized note
+    pub cue: bool,
+
+    /// Pitches stacked on the note as a chord (see `Cell::extra_pitches`)
+    pub chord: Vec<ExportPitch>,
+
+    /// Index of the source cell in the line
+    pub cell_index: usize,
+}
+
+impl ExportEvent {
+    /// Check if this event is a rest
+    pub fn is_rest(&self) -> bool {
+        self.pitch.is_none()
+    }
+}
+
+/// One beat of events
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ExportBeat {
+    pub events: Vec<ExportEvent>,
+
+    /// Number of equal subdivisions the beat is divided into
+    pub subdivisions: u32,
+
+    /// Tuplet ratio when the subdivision count is not a power of two
+    pub tuplet: Option<TupletInfo>,
+}
+
+impl ExportBeat {
+    /// Written (notated) duration of an event, before any tuplet scaling
+    ///
+    /// Inside a tuplet the written value is longer than the sounding one:
+    /// a triplet eighth sounds for 1/12 but is written as 1/8.
+    pub fn written_duration(&self, event: &ExportEvent) -> Fraction {
+        match self.tuplet {
+            Some(tuplet) => event.duration * Fraction::new(tuplet.actual, tuplet.normal),
+            None => event.duration,
+        }
+    }
+}
+
+/// One measure of beats
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ExportMeasure {
+    pub beats: Vec<ExportBeat>,
+
+    /// Closing barline glyph ("|", "||", ":|", ...), if any
+    pub barline: Option<String>,
+
+    /// Number of times the measure is played (1 unless annotated "×N")
+    pub repeat_count: u32,
+}
+
+impl ExportMeasure {
+    /// Iterate over all events in the measure
+    pub fn events(&self) -> impl Iterator<Item = &ExportEvent> {
+        self.beats.iter().flat_map(|beat| beat.events.iter())
+    }
+
+    /// Total duration of the measure
+    pub fn duration(&self) -> Fraction {
+        self.events().fold(Fraction::zero(), |total, event| total + event.duration)
+    }
+}
+
+/// Tuplet ratio for a beat divided into `subdivisions` parts
+pub fn tuplet_for_subdivisions(subdivisions: u32) -> Option<TupletInfo> {
+    if subdivisions == 0 || subdivisions.is_power_of_two() {
+        None
+    } else {
+        let normal = 1 << (31 - subdivisions.leading_zeros());
+        Some(TupletInfo { actual: subdivisions, normal })
+    }
+}
+
+/// Check if a cell counts as one subdivision of its beat
+fn is_subdivision(cell: &Cell) -> bool {
+    matches!(cell.kind, ElementKind::PitchedElement | ElementKind::UnpitchedElement)
+}
+
+/// Standard ornament attached to a cell, if it has an export equivalent
+fn classify_ornament(cell: &Cell) -> Option<OrnamentType> {
+    Some(cell.ornament).filter(|ornament| ornament.musicxml_element().is_some())
+}
+
+/// Group the cells of one beat into timed events
+///
+/// `offset` is the line index of `cells[0]`. `previous` is the pitch a
+/// leading dash would continue; it is updated as notes are emitted and
+/// cleared by breath marks. `base_duration` is the unit note of each cell
+/// (4 = quarter), or 0 to divide a quarter-note beat among its cells.
+pub fn group_cells_into_events(
+    cells: &[Cell],
+    offset: usize,
+    previous: &mut Option<ExportPitch>,
+    base_duration: u32,
+) -> ExportBeat {
+    let subdivisions = cells.iter().filter(|cell| is_subdivision(cell)).count() as u32;
+    let unit = if base_duration == 0 {
+        Fraction::new(1, 4 * subdivisions.max(1))
+    } else {
+        Fraction::new(1, base_duration)
+    };
+    let mut events: Vec<ExportEvent> = Vec::new();
+    let mut current: Option<ExportEvent> = None;
+
+    let finish = |event: Option<ExportEvent>, events: &mut Vec<ExportEvent>| {
+        if let Some(mut event) = event {
+            event.duration = unit * Fraction::new(event.subdivisions, 1);
+            events.push(event);
+        }
+    };
+
+    for (index, cell) in cells.iter().enumerate() {
+        match cell.kind {
+            ElementKind::PitchedElement => {
+                finish(current.take(), &mut events);
+                let pitch = ExportPitch {
+                    pitch_code: cell.pitch_code.clone().unwrap_or_else(|| cell.glyph.clone()),
+                    pitch_system: cell.pitch_system.unwrap_or_default(),
+                    octave: cell.octave,
+                };
+                *previous = Some(pitch.clone());
+                current = Some(ExportEvent {
+                    pitch: Some(pitch),
+                    duration: Fraction::zero(),
+                    subdivisions: 1,
+                    tie_start: false,
+                    tie_stop: false,
+                    slur_start: cell.is_slur_start(),
+                    slur_stop: cell.is_slur_end(),
+                    ornament: classify_ornament(cell),
+                    grace: cell.ornament.grace_slash(),
+                    fingering: cell.fingering.clone(),
+                    fermata: false,
+                    breath_mark: false,
+                    staccato: cell.staccato,
+                    cue: cell.size == NoteSize::Cue,
+                    chord: cell
+                        .extra_pitches
+                        .iter()
+                        .map(|(pitch_code, octave)| ExportPitch {
+                            pitch_code: pitch_code.clone(),
+                            pitch_system: cell.pitch_system.unwrap_or_default(),
+                            octave: *octave,
+                        })
+                        .collect(),
+                    cell_index: offset + index,
+                });
+            }
+            ElementKind::UnpitchedElement if cell.is_rest() => {
+                finish(current.take(), &mut events);
+                *previous = None;
+                current = Some(ExportEvent {
+                    pitch: None,
+                    duration: Fraction::zero(),
+                    subdivisions: 1,
+                    tie_start: false,
+                    tie_stop: false,
+                    slur_start: false,
+                    slur_stop: false,
+                    ornament: None,
+                    grace: None,
+                    fingering: None,
+                    fermata: false,
+                    breath_mark: false,
+                    staccato: false,
+                    cue: cell.size == NoteSize::Cue,
+                    chord: Vec::new(),
+                    cell_index: offset + index,
+                });
+            }
+            ElementKind::UnpitchedElement => match current.as_mut() {
+                Some(event) => event.subdivisions += 1,
+                None => {
+                    current = Some(ExportEvent {
+                        pitch: previous.clone(),
+                        duration: Fraction::zero(),
+                        subdivisions: 1,
+                        tie_start: false,
+                        tie_stop: previous.is_some(),
+                        slur_start: false,
+                        slur_stop: false,
+                        ornament: None,
+                        grace: None,
+                        fingering: None,
+                        fermata: false,
+                        breath_mark: false,
+                        staccato: false,
+                        cue: cell.size == NoteSize::Cue,
+                        chord: Vec::new(),
+                        cell_index: offset + index,
+                    });
+                }
+            },
+            ElementKind::BreathMark => {
+                finish(current.take(), &mut events);
+                *previous = None;
+            }
+            _ => {}
+        }
+    }
+    finish(current.take(), &mut events);
+    give_grace_time_to_neighbours(&mut events);
+
+    // With a fixed unit note every cell has a plain written value
+    let tuplet = if base_duration == 0 { tuplet_for_subdivisions(subdivisions) } else { None };
+
+    ExportBeat {
+        events,
+        subdivisions,
+        tuplet,
+    }
+}
+
+/// Move the time of grace notes to the next regular note of the beat
+///
+/// Grace notes after the last regular note give their time to the one
+/// before them. A beat of only grace notes keeps its timing.
+fn give_grace_time_to_neighbours(events: &mut [ExportEvent]) {
+    for index in 0..events.len() {
+        if events[index].grace.is_none() {
+            continue;
+        }
+        let receiver = (index + 1..events.len())
+            .find(|&next| events[next].grace.is_none())
+            .or_else(|| (0..index).rev().find(|&before| events[before].grace.is_none()));
+        if let Some(receiver) = receiver {
+            let (duration, subdivisions) = (events[index].duration, events[index].subdivisions);
+            events[receiver].duration = events[receiver].duration + duration;
+            events[receiver].subdivisions += subdivisions;
+            events[index].duration = Fraction::zero();
+        } else {
+            events[index].grace = None;
+        }
+    }
+}
+
+/// Build export measures for a line, splitting at barline cells
+pub fn build_export_measures_from_line(line: &Line) -> Vec<ExportMeasure> {
+    build_export_measures_with_separator(line, BeatSeparator::SingleSpace)
+}
+
+/// Build export measures for a line, grouping beats under `separator`
+pub fn build_export_measures_with_separator(line: &Line, separator: BeatSeparator) -> Vec<ExportMeasure> {
+    let deriver = BeatDeriver::with_separator(separator);
+    let cells = &line.cells;
+    let mut measures = Vec::new();
+    let mut previous: Option<ExportPitch> = None;
+    let mut segment_start = 0;
+    let mut segment_index = 0;
+
+    // Repeat counts are indexed by barline segment, empty ones included, as
+    // in `measure_content_range`
+    let mut close_segment = |start: usize, end: usize, barline: Option<String>, previous: &mut Option<ExportPitch>| {
+        let segment = &cells[start..end];
+        let beats: Vec<ExportBeat> = deriver
+            .extract_implicit_beats(segment)
+            .iter()
+            .map(|span| {
+                group_cells_into_events(&segment[span.start..=span.end], start + span.start, previous, line.base_duration)
+            })
+            .filter(|beat| !beat.events.is_empty())
+            .collect();
+
+        if !beats.is_empty() {
+            let repeat_count = line.measure_repeat_count(segment_index);
+            measures.push(ExportMeasure { beats, barline, repeat_count });
+        }
+        segment_index += 1;
+    };
+
+    for (index, cell) in cells.iter().enumerate() {
+        if cell.kind == ElementKind::Barline {
+            close_segment(segment_start, index, Some(cell.glyph.clone()), &mut previous);
+            segment_start = index + 1;
+        }
+    }
+    close_segment(segment_start, cells.len(), None, &mut previous);
+
+    link_ties(&mut measures);
+    attach_marks(cells, &mut measures);
+    measures
+}
+
+/// Build export measures for a line of `document`, applying its export policies
+///
+/// Beats are grouped by the document's beat separator, near-even irregular
+/// beats are snapped to tuplets within `tuplet_tolerance`, and unwritable
+/// durations are split into ties when `explicit_durations` is set.
+pub fn build_export_measures_for(document: &Document, line: &Line) -> Vec<ExportMeasure> {
+    let mut measures = build_export_measures_with_separator(line, document.beat_separator);
+    if document.tuplet_tolerance > 0.0 {
+        snap_near_tuplets(&mut measures, document.tuplet_tolerance);
+    }
+    if document.explicit_durations {
+        split_unwritable_durations(&mut measures);
+    }
+    measures
+}
+
+/// Build export measures for the cells in `start..end` of a line
+///
+/// Rhythm is derived from the whole line under the document's policies (see
+/// `build_export_measures_for`), so a selection that starts or ends mid-beat
+/// keeps the durations the notes have in a full export. Ties reaching
+/// outside the range are dropped, and beats or measures left empty are
+/// removed. A reversed range is put in order.
+pub fn build_export_measures_from_range(document: &Document, line: &Line, start: usize, end: usize) -> Vec<ExportMeasure> {
+    let (start, end) = if start <= end { (start, end) } else { (end, start) };
+    let mut measures = build_export_measures_for(document, line);
+
+    for measure in &mut measures {
+        for beat in &mut measure.beats {
+            beat.events.retain(|event| (start..end).contains(&event.cell_index));
+        }
+        measure.beats.retain(|beat| !beat.events.is_empty());
+    }
+    measures.retain(|measure| !measure.beats.is_empty());
+
+    // The last measure only closes with its barline if the barline was selected
+    if let Some(last) = measures.last_mut() {
+        let closed = line.cells[start.min(line.cells.len())..end.min(line.cells.len())]
+            .iter()
+            .any(|cell| cell.kind == ElementKind::Barline);
+        if !closed {
+            last.barline = None;
+        }
+    }
+
+    let mut events = measures
+        .iter_mut()
+        .flat_map(|measure| measure.beats.iter_mut())
+        .flat_map(|beat| beat.events.iter_mut())
+        .peekable();
+    if let Some(first) = events.peek_mut() {
+        first.tie_stop = false;
+    }
+    if let Some(last) = events.last() {
+        last.tie_start = false;
+    }
+
+    measures
+}
+
+/// Length of a selection, for rehearsal planning
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SelectionDuration {
+    /// Summed duration of the selected notes and rests
+    pub duration: Fraction,
+
+    /// Beats whose events all lie inside the selection
+    pub complete_beats: usize,
+
+    /// Measures whose events all lie inside the selection
+    pub complete_measures: usize,
+
+    /// Barline cells inside the selection
+    pub barlines: usize,
+}
+
+/// Measure the cells from `start` to `end` of a document
+///
+/// Positions are (line, column) with `end` exclusive, in either order.
+/// Durations come from the whole line, so a beat cut at a selection edge
+/// contributes only its selected part and is not counted as complete.
+pub fn selection_duration(document: &Document, start: (usize, usize), end: (usize, usize)) -> Result<SelectionDuration, String> {
+    let (start, end) = if end < start { (end, start) } else { (start, end) };
+    if end.0 >= document.lines.len() {
+        return Err(format!("Line index {} out of bounds", end.0));
+    }
+
+    let mut total = SelectionDuration { duration: Fraction::zero(), complete_beats: 0, complete_measures: 0, barlines: 0 };
+    let lines = &document.lines[start.0..=end.0];
+    for (offset, line) in lines.iter().enumerate() {
+        let from = if offset == 0 { start.1 } else { 0 };
+        let to = if offset == lines.len() - 1 { end.1 } else { line.cells.len() };
+        let selected = from.min(line.cells.len())..to.min(line.cells.len());
+        let inside = |event: &ExportEvent| selected.contains(&event.cell_index);
+
+        for measure in build_export_measures_for(document, line) {
+            for beat in &measure.beats {
+                for event in beat.events.iter().filter(|event| inside(event)) {
+                    total.duration = total.duration + event.duration;
+                }
+                if beat.events.iter().all(inside) {
+                    total.complete_beats += 1;
+                }
+            }
+            if measure.events().all(inside) {
+                total.complete_measures += 1;
+            }
+        }
+        total.barlines += line.cells[selected].iter().filter(|cell| cell.kind == ElementKind::Barline).count();
+    }
+    Ok(total)
+}
+
+/// Expand measures with a repeat count into consecutive copies
+///
+/// This is the performed order of the music, as a player would hear it.
+/// Each copy keeps a repeat count of 1.
+pub fn unfold_measure_repeats(measures: &[ExportMeasure]) -> Vec<ExportMeasure> {
+    measures
+        .iter()
+        .flat_map(|measure| {
+            let copy = ExportMeasure { repeat_count: 1, ..measure.clone() };
+            std::iter::repeat_n(copy, measure.repeat_count.max(1) as usize)
+        })
+        .collect()
+}
+
+/// Flag each measure whose content repeats the measure before it
+///
+/// Measures match when their beats are identical apart from source cell
+/// indices and they share a repeat count, so a run of equal measures can be
+/// drawn as measure-repeat signs. The first measure is never a repeat.
+pub fn repeated_measures(measures: &[ExportMeasure]) -> Vec<bool> {
+    let content = |measure: &ExportMeasure| {
+        let mut beats = measure.beats.clone();
+        for event in beats.iter_mut().flat_map(|beat| beat.events.iter_mut()) {
+            event.cell_index = 0;
+        }
+        (beats, measure.repeat_count)
+    };
+
+    let mut repeated = vec![false; measures.len()];
+    for index in 1..measures.len() {
+        repeated[index] = content(&measures[index]) == content(&measures[index - 1]);
+    }
+    repeated
+}
+
+/// Whether a value of `numerator`/2^k is a single written note (plain, dotted or double-dotted)
+fn is_writable(numerator: u32) -> bool {
+    numerator > 0 && matches!(numerator >> numerator.trailing_zeros(), 1 | 3 | 7)
+}
+
+/// Note counts a beat may be snapped to by `snap_near_tuplets`
+pub const SNAP_TUPLETS: [u32; 2] = [3, 5];
+
+/// Even out irregular beats whose notes nearly form a triplet or quintuplet
+///
+/// A beat already in a tuplet (such as "1-2-3--", 2/7 + 2/7 + 3/7) with 3 or
+/// 5 notes becomes an even tuplet of those notes when every note's share of
+/// the beat is within `tolerance` of 1/3 (or 1/5). The beat keeps its total
+/// length and gets the matching `TupletInfo`. Other beats keep their exact
+/// durations; grace notes are not counted and keep no duration.
+pub fn snap_near_tuplets(measures: &mut [ExportMeasure], tolerance: f64) {
+    for beat in measures.iter_mut().flat_map(|measure| measure.beats.iter_mut()) {
+        let count = beat.events.iter().filter(|event| event.grace.is_none()).count() as u32;
+        if beat.tuplet.is_none() || beat.subdivisions == count || !SNAP_TUPLETS.contains(&count) {
+            continue;
+        }
+        let total = beat.events.iter().fold(Fraction::zero(), |sum, event| sum + event.duration);
+        let even = 1.0 / count as f64;
+        let near = beat
+            .events
+            .iter()
+            .filter(|event| event.grace.is_none())
+            .all(|event| ((event.duration / total).to_f64() - even).abs() <= tolerance);
+        if !near {
+            continue;
+        }
+
+        let share = total * Fraction::new(1, count);
+        for event in beat.events.iter_mut().filter(|event| event.grace.is_none()) {
+            event.duration = share;
+            event.subdivisions = 1;
+        }
+        beat.subdivisions = count;
+        beat.tuplet = tuplet_for_subdivisions(count);
+    }
+}
+
+/// Split events whose written value needs more than two dots into tied notes
+///
+/// A written value of n/2^k is broken greedily into the longest writable
+/// values that fit (5/8 becomes 1/2 + 1/8). The pieces share the source
+/// cell; ties and slurs are kept on the outer ends and the pieces are tied
+/// together. Values whose denominator is not a power of two are left as is.
+pub fn split_unwritable_durations(measures: &mut [ExportMeasure]) {
+    for beat in measures.iter_mut().flat_map(|measure| measure.beats.iter_mut()) {
+        let mut events = Vec::with_capacity(beat.events.len());
+        for event in std::mem::take(&mut beat.events) {
+            let written = beat.written_duration(&event);
+            if is_writable(written.numerator) || !written.denominator.is_power_of_two() {
+                events.push(event);
+                continue;
+            }
+
+            let mut pieces = Vec::new();
+            let mut remaining = written.numerator;
+            while remaining > 0 {
+                let piece = (1..=remaining).rev().find(|&n| is_writable(n)).unwrap_or(1);
+                pieces.push(piece);
+                remaining -= piece;
+            }
+
+            let last = pieces.len() - 1;
+            for (index, piece) in pieces.into_iter().enumerate() {
+                let share = Fraction::new(piece, written.numerator);
+                events.push(ExportEvent {
+                    duration: event.duration * share,
+                    subdivisions: (event.subdivisions * piece / written.numerator).max(1),
+                    tie_start: if index == last { event.tie_start } else { event.pitch.is_some() },
+                    tie_stop: if index == 0 { event.tie_stop } else { event.pitch.is_some() },
+                    slur_start: index == 0 && event.slur_start,
+                    slur_stop: index == last && event.slur_stop,
+                    ornament: if index == 0 { event.ornament } else { None },
+                    fingering: if index == 0 { event.fingering.clone() } else { None },
+                    fermata: index == last && event.fermata,
+                    breath_mark: index == last && event.breath_mark,
+                    staccato: index == last && event.staccato,
+                    ..event.clone()
+                });
+            }
+        }
+        beat.events = events;
+    }
+}
+
+/// Attach fermata and breath-mark cells to the event they follow
+///
+/// Both marks are written after their note, so each applies to the last
+/// event that starts before it. Marks with no event before them are dropped.
+fn attach_marks(cells: &[Cell], measures: &mut [ExportMeasure]) {
+    let mut events: Vec<&mut ExportEvent> = measures
+        .iter_mut()
+        .flat_map(|measure| measure.beats.iter_mut())
+        .flat_map(|beat| beat.events.iter_mut())
+        .collect();
+
+    for (index, cell) in cells.iter().enumerate() {
+        let fermata = cell.kind == ElementKind::UpperAnnotation && cell.glyph == FERMATA_TOKEN;
+        if !fermata && cell.kind != ElementKind::BreathMark {
+            continue;
+        }
+        if let Some(event) = events.iter_mut().rev().find(|event| event.cell_index < index) {
+            if fermata {
+                event.fermata = true;
+            } else {
+                event.breath_mark = true;
+            }
+        }
+    }
+}
+
+/// Mark the note before each tie continuation as starting the tie
+///
+/// The continuation also takes over the chord stacked on the tied note.
+fn link_ties(measures: &mut [ExportMeasure]) {
+    let mut previous: Option<&mut ExportEvent> = None;
+    for event in measures
+        .iter_mut()
+        .flat_map(|measure| measure.beats.iter_mut())
+        .flat_map(|beat| beat.events.iter_mut())
+    {
+        if event.tie_stop {
+            if let Some(prev) = previous.take() {
+                prev.tie_start = true;
+                event.chord = prev.chord.clone();
+            }
+        }
+        previous = Some(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::grammar::{parse_cells, parse_cells_with_tokens, NotationTokens};
+
+    fn line_from(text: &str) -> Line {
+        let mut line = Line::new();
+        line.cells = parse_cells(text, PitchSystem::Number);
+        line
+    }
+
+    #[test]
+    fn test_grace_notes_take_no_time() {
+        let mut line = Line::new();
+        line.cells = parse_cells("12 34", PitchSystem::Number);
+        line.cells[0].ornament = OrnamentType::Acciaccatura;
+        line.cells[4].ornament = OrnamentType::Appoggiatura;
+
+        let measures = build_export_measures_from_line(&line);
+        let events: Vec<(Option<bool>, Fraction)> = measures[0].events().map(|e| (e.grace, e.duration)).collect();
+        assert_eq!(
+            events,
+            vec![
+                (Some(true), Fraction::zero()),
+                (None, Fraction::new(1, 4)),
+                (None, Fraction::new(1, 4)),
+                (Some(false), Fraction::zero()),
+            ]
+        );
+        assert_eq!(measures[0].duration(), Fraction::new(1, 2));
+    }
+
+    #[test]
+    fn test_explicit_durations_split_into_ties() {
+        let mut line = line_from("1----");
+        line.base_duration = 8;
+        let mut document = Document::new();
+        document.lines.push(line.clone());
+
+        let raw = build_export_measures_for(&document, &line);
+        assert_eq!(raw[0].beats[0].events.len(), 1);
+        assert_eq!(raw[0].beats[0].events[0].duration, Fraction::new(5, 8));
+
+        document.explicit_durations = true;
+        let split = build_export_measures_for(&document, &line);
+        let events = &split[0].beats[0].events;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].duration, Fraction::new(1, 2));
+        assert_eq!(events[1].duration, Fraction::new(1, 8));
+        assert!(events[0].tie_start && !events[0].tie_stop);
+        assert!(events[1].tie_stop && !events[1].tie_start);
+        assert_eq!(events[0].subdivisions + events[1].subdivisions, 5);
+        assert_eq!(split[0].duration(), raw[0].duration());
+    }
+
+    #[test]
+    fn test_explicit_durations_keep_writable_values_and_tuplets() {
+        // "1----2" is a sextuplet whose first note is written as 5/16
+        let mut measures = build_export_measures_from_line(&line_from("1----2 3--"));
+        split_unwritable_durations(&mut measures);
+
+        let beats = &measures[0].beats;
+        assert_eq!(beats[0].events.len(), 3);
+        assert_eq!(beats[0].written_duration(&beats[0].events[0]), Fraction::new(1, 4));
+        assert_eq!(beats[0].written_duration(&beats[0].events[1]), Fraction::new(1, 16));
+        let total = beats[0].events.iter().fold(Fraction::zero(), |sum, event| sum + event.duration);
+        assert_eq!(total, BEAT_DURATION);
+
+        // A triplet "3--" is written as a dotted quarter and stays whole
+        assert_eq!(beats[1].events.len(), 1);
+    }
+
+    #[test]
+    fn test_near_triplet_snaps_within_tolerance() {
+        let mut document = Document::new();
+        // Notes of 2/7, 2/7 and 3/7 of a beat, then 4/7, 2/7 and 1/7
+        document.add_line(line_from("1-2-3-- 1---2-3"));
+        let exact = build_export_measures_for(&document, &document.lines[0]);
+        assert_eq!(exact[0].beats[0].tuplet, Some(TupletInfo { actual: 7, normal: 4 }));
+
+        document.tuplet_tolerance = 0.1;
+        let measures = build_export_measures_for(&document, &document.lines[0]);
+        let triplet = &measures[0].beats[0];
+        assert_eq!(triplet.tuplet, Some(TupletInfo { actual: 3, normal: 2 }));
+        let durations: Vec<Fraction> = triplet.events.iter().map(|event| event.duration).collect();
+        assert_eq!(durations, vec![Fraction::new(1, 12); 3]);
+        assert_eq!(triplet.written_duration(&triplet.events[0]), Fraction::new(1, 8));
+
+        let uneven = &measures[0].beats[1];
+        assert_eq!(uneven.tuplet, Some(TupletInfo { actual: 7, normal: 4 }));
+        let durations: Vec<Fraction> = uneven.events.iter().map(|event| event.duration).collect();
+        assert_eq!(durations, vec![Fraction::new(1, 7), Fraction::new(1, 14), Fraction::new(1, 28)]);
+    }
+
+    #[test]
+    fn test_fermata_and_breath_marks_attach_to_notes() {
+        let measures = build_export_measures_from_line(&line_from("1^2 3' 4"));
+        let beats = &measures[0].beats;
+
+        // The fermata does not split "1^2" into two beats
+        assert_eq!(beats.len(), 3);
+        assert_eq!(beats[0].subdivisions, 2);
+        assert!(beats[0].events[0].fermata);
+        assert!(!beats[0].events[1].fermata);
+        assert!(beats[1].events[0].breath_mark);
+        assert!(!beats[2].events[0].breath_mark);
+    }
+
+    #[test]
+    fn test_rest_token_starts_a_rest() {
+        let tokens = NotationTokens { dash: Some('–'), rest: Some('z') };
+        let mut line = Line::new();
+        line.cells = parse_cells_with_tokens("1–z– 2", PitchSystem::Number, 2, &tokens);
+
+        let measures = build_export_measures_from_line(&line);
+        let beats = &measures[0].beats;
+        assert_eq!(beats.len(), 2);
+        assert_eq!(beats[0].subdivisions, 4);
+        assert_eq!(beats[0].events[0].duration, Fraction::new(1, 8));
+        assert!(beats[0].events[1].is_rest());
+        assert_eq!(beats[0].events[1].duration, Fraction::new(1, 8));
+        assert!(!beats[0].events[0].tie_start);
+    }
+
+    #[test]
+    fn test_beat_separator_policy() {
+        let line = line_from("1 2  3 4");
+
+        let single = build_export_measures_with_separator(&line, BeatSeparator::SingleSpace);
+        assert_eq!(single[0].beats.len(), 4);
+        assert_eq!(single[0].beats[0].events[0].duration, Fraction::new(1, 4));
+
+        let double = build_export_measures_with_separator(&line, BeatSeparator::DoubleSpace);
+        assert_eq!(double[0].beats.len(), 2);
+        assert_eq!(double[0].beats[0].subdivisions, 2);
+        assert_eq!(double[0].beats[1].events[1].cell_index, 7);
+        assert_eq!(double[0].beats[1].events[1].duration, Fraction::new(1, 8));
+    }
+
+    #[test]
+    fn test_beat_subdivisions_and_dashes() {
+        let measures = build_export_measures_from_line(&line_from("1-2 3"));
+        assert_eq!(measures.len(), 1);
+
+        let beats = &measures[0].beats;
+        assert_eq!(beats[0].subdivisions, 3);
+        assert_eq!(beats[0].tuplet, Some(TupletInfo { actual: 3, normal: 2 }));
+        assert_eq!(beats[0].events[0].duration, Fraction::new(1, 6));
+        assert_eq!(beats[0].events[1].duration, Fraction::new(1, 12));
+        assert_eq!(beats[1].events[0].duration, Fraction::new(1, 4));
+    }
+
+    #[test]
+    fn test_leading_dash_ties_across_beats_and_barlines() {
+        let measures = build_export_measures_from_line(&line_from("1 | -"));
+        assert_eq!(measures.len(), 2);
+
+        let first = &measures[0].beats[0].events[0];
+        let second = &measures[1].beats[0].events[0];
+        assert!(first.tie_start);
+        assert!(second.tie_stop);
+        assert_eq!(second.pitch.as_ref().unwrap().pitch_code, "1");
+        assert_eq!(measures[0].barline.as_deref(), Some("|"));
+    }
+
+    #[test]
+    fn test_base_duration_sets_unit_note() {
+        let mut line = line_from("1-");
+
+        let beat_relative = build_export_measures_from_line(&line);
+        assert_eq!(beat_relative[0].beats[0].events[0].duration, Fraction::new(1, 4));
+
+        line.base_duration = 4;
+        let quarter_base = build_export_measures_from_line(&line);
+        assert_eq!(quarter_base[0].beats[0].events[0].duration, Fraction::new(1, 2));
+
+        line.base_duration = 8;
+        let eighth_base = build_export_measures_from_line(&line);
+        assert_eq!(eighth_base[0].beats[0].events[0].duration, Fraction::new(1, 4));
+        assert_eq!(eighth_base[0].beats[0].tuplet, None);
+    }
+
+    #[test]
+    fn test_range_keeps_in_context_durations() {
+        let line = line_from("12 3- | 4");
+
+        // Select "2 3" - starts mid-beat and stops before the tied dash
+        let measures = build_export_measures_from_range(&Document::new(), &line, 1, 4);
+        let events: Vec<&ExportEvent> = measures.iter().flat_map(|m| m.events()).collect();
+
+        assert_eq!(measures.len(), 1);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].duration, Fraction::new(1, 8));
+        assert_eq!(events[1].duration, Fraction::new(1, 4));
+        assert_eq!(measures[0].barline, None);
+    }
+
+    #[test]
+    fn test_range_drops_ties_leaving_selection() {
+        let line = line_from("1 | -");
+
+        let measures = build_export_measures_from_range(&Document::new(), &line, 0, 1);
+        assert!(!measures[0].beats[0].events[0].tie_start);
+
+        let measures = build_export_measures_from_range(&Document::new(), &line, 2, 5);
+        let event = &measures[0].beats[0].events[0];
+        assert!(!event.tie_stop);
+        assert_eq!(event.pitch.as_ref().unwrap().pitch_code, "1");
+    }
+
+    #[test]
+    fn test_range_follows_document_beat_policy() {
+        let mut document = Document::new();
+        document.beat_separator = BeatSeparator::DoubleSpace;
+        document.add_line(line_from("1 2  3 4"));
+
+        let measures = build_export_measures_from_range(&document, &document.lines[0], 0, 3);
+        let durations: Vec<Fraction> = measures.iter().flat_map(|m| m.events()).map(|event| event.duration).collect();
+        assert_eq!(durations, vec![Fraction::new(1, 8), Fraction::new(1, 8)], "\"1 2\" is one beat");
+    }
+
+    #[test]
+    fn test_leading_dash_without_note_is_rest() {
+        let measures = build_export_measures_from_line(&line_from("- 1"));
+        assert!(measures[0].beats[0].events[0].is_rest());
+        assert_eq!(measures[0].duration(), Fraction::new(1, 2));
+    }
+
+    #[test]
+    fn test_measure_repeat_count_unfolds() {
+        let mut line = Line::new();
+        line.cells = parse_cells("1 2 | 3", PitchSystem::Number);
+        line.set_measure_repeat_count(0, 3);
+
+        let measures = build_export_measures_from_line(&line);
+        assert_eq!(measures.iter().map(|m| m.repeat_count).collect::<Vec<_>>(), vec![3, 1]);
+
+        let played: Vec<usize> = unfold_measure_repeats(&measures)
+            .iter()
+            .flat_map(|m| m.events().map(|e| e.cell_index).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(played, vec![0, 2, 0, 2, 0, 2, 6]);
+
+        // An empty segment before a leading barline is measure 0, as for copy and paste
+        let mut leading = Line::new();
+        leading.cells = parse_cells("| 1 | 2", PitchSystem::Number);
+        leading.set_measure_repeat_count(2, 2);
+        let measures = build_export_measures_from_line(&leading);
+        assert_eq!(measures.iter().map(|m| m.repeat_count).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_repeated_measures_ignore_cell_positions() {
+        let mut line = Line::new();
+        line.cells = parse_cells("1 2 | 1 2 | 1 2 | 1 3", PitchSystem::Number);
+
+        let measures = build_export_measures_from_line(&line);
+        assert_eq!(repeated_measures(&measures), vec![false, true, true, false]);
+    }
+
+    #[test]
+    fn test_selection_duration_counts_beats_and_barlines() {
+        let mut document = Document::new();
+        let mut line = Line::new();
+        line.cells = parse_cells("1 2 | 3 4", PitchSystem::Number);
+        document.lines.push(line);
+
+        let whole = selection_duration(&document, (0, 0), (0, 9)).unwrap();
+        assert_eq!(whole.duration, Fraction::new(1, 1), "four beats");
+        assert_eq!((whole.complete_beats, whole.complete_measures, whole.barlines), (4, 2, 1));
+
+        // "2 | 3" selected backwards
+        let middle = selection_duration(&document, (0, 7), (0, 2)).unwrap();
+        assert_eq!(middle.duration, Fraction::new(1, 2));
+        assert_eq!((middle.complete_beats, middle.complete_measures, middle.barlines), (2, 0, 1));
+
+        assert!(selection_duration(&document, (0, 0), (3, 0)).is_err());
+    }
+
+    #[test]
+    fn test_selection_duration_of_partial_beat() {
+        let mut document = Document::new();
+        let mut line = Line::new();
+        line.cells = parse_cells("12 3", PitchSystem::Number);
+        document.lines.push(line);
+
+        let half_beat = selection_duration(&document, (0, 1), (0, 2)).unwrap();
+        assert_eq!(half_beat.duration, Fraction::new(1, 8));
+        assert_eq!(half_beat.complete_beats, 0);
+    }
+}
@@ -54,19 +54,29 @@ class MusicNotationEditor {
         layoutRenderer: new wasmModule.LayoutRenderer(16),
         // New recursive descent API
         insertCharacter: wasmModule.insertCharacter,
+        insertText: wasmModule.insertText,
         parseText: wasmModule.parseText,
         deleteCharacter: wasmModule.deleteCharacter,
+        deleteCharacterInLine: wasmModule.deleteCharacterInLine,
         applyOctave: wasmModule.applyOctave,
         // Slur API
         applySlur: wasmModule.applySlur,
         removeSlur: wasmModule.removeSlur,
         hasSlurInSelection: wasmModule.hasSlurInSelection,
+        toggleSlurSelection: wasmModule.toggleSlurSelection,
         // Document API
         createNewDocument: wasmModule.createNewDocument,
         setTitle: wasmModule.setTitle,
         setStaveLabel: wasmModule.setStaveLabel,
         setStaveLyrics: wasmModule.setStaveLyrics,
-        setStaveTala: wasmModule.setStaveTala
+        setStaveTala: wasmModule.setStaveTala,
+        // Autofix API
+        coalesceAccidentals: wasmModule.coalesceAccidentals,
+        repairStructure: wasmModule.repairStructure,
+        // Vertical movement API
+        setDesiredColumn: wasmModule.setDesiredColumn,
+        clearDesiredColumn: wasmModule.clearDesiredColumn,
+        moveCursorVertical: wasmModule.moveCursorVertical
       };
 
       const loadTime = performance.now() - startTime;
@@ -128,7 +138,12 @@ class MusicNotationEditor {
   async loadDocument(jsonString) {
     try {
       if (this.wasmModule) {
-        this.theDocument = typeof jsonString === 'string' ? JSON.parse(jsonString) : jsonString;
+        const loaded = typeof jsonString === 'string' ? JSON.parse(jsonString) : jsonString;
+        // Merge accidentals that older imports stored in their own cell,
+        // then repair orphan dashes and empty cells
+        this.theDocument = this.wasmModule.repairStructure(
+          this.wasmModule.coalesceAccidentals(loaded)
+        );
         await this.render();
         this.updateDocumentDisplay();
 
@@ -165,6 +180,9 @@ class MusicNotationEditor {
       logger.warn(LOG_CATEGORIES.EDITOR, 'insertText called before initialization');
       return;
     }
+    if (!this.isLineEditable(0)) {
+      return;
+    }
 
     logger.time('insertText', LOG_CATEGORIES.EDITOR);
     const cursorPos = this.getCursorPosition();
@@ -180,46 +198,27 @@ class MusicNotationEditor {
 
     try {
       if (this.theDocument && this.theDocument.lines && this.theDocument.lines.length > 0) {
-        const line = this.theDocument.lines[0];
-        let letterLane = line.cells; // Main line of notation
+        const lengthBefore = this.theDocument.lines[0].cells.length;
 
         logger.debug(LOG_CATEGORIES.PARSER, 'Processing characters', {
           charCount: text.length,
-          initialLaneSize: letterLane.length
+          initialLaneSize: lengthBefore
         });
 
-        // Insert each character using recursive descent parser
-        let currentPos = cursorPos;
-        for (const char of text) {
-          const lengthBefore = letterLane.length;
-
-          logger.debug(LOG_CATEGORIES.PARSER, `Inserting char '${char}'`, {
-            position: currentPos,
-            laneSizeBefore: lengthBefore
-          });
-
-          // Call WASM recursive descent API
-          const updatedCells = this.wasmModule.insertCharacter(
-            letterLane,
-            char,
-            currentPos,
-            pitchSystem
-          );
-
-          const lengthAfter = updatedCells.length;
+        // Insert through the document-level WASM API, which types each
+        // character with the document's notation tokens, adds barlines
+        // (auto_barline) and records one undo step
+        const preservedState = this.theDocument.state;
+        const { document: updatedDocument, caret, diagnostics } =
+          this.wasmModule.insertText(this.theDocument, 0, cursorPos, text);
+        updatedDocument.state = preservedState;
+        this.theDocument = updatedDocument;
+        const line = updatedDocument.lines[0];
+        const currentPos = caret;
 
-          // Update main line with combined cells
-          line.cells = updatedCells;
-          letterLane = updatedCells;
-
-          // Adjust cursor based on actual change in cell count
-          // If cells combined, length might not increase by 1
-          const cellDelta = lengthAfter - lengthBefore;
-          logger.trace(LOG_CATEGORIES.PARSER, `Cell delta: ${cellDelta}`, {
-            lengthBefore,
-            lengthAfter
-          });
-          currentPos += cellDelta;
+        // Input-assist corrections (sargam case assist)
+        for (const diagnostic of diagnostics) {
+          this.showWarning(diagnostic.message, { source: 'Input Assist', details: diagnostic.position });
         }
 
         // Update cursor position (just the column number, not visual position yet)
@@ -242,7 +241,7 @@ class MusicNotationEditor {
       this.updateDocumentDisplay();
 
       // Ensure hitbox values are properly set on the document cells
-      // The WASM insertCharacter may return cells without hitbox fields
+      // The WASM insertText may return cells without hitbox fields
       this.ensureHitboxesAreSet();
 
       // Force hitboxes display update after render
@@ -331,7 +330,7 @@ class MusicNotationEditor {
 
       // Parse text using WASM recursive descent parser
       if (this.theDocument && this.theDocument.lines && this.theDocument.lines.length > 0) {
-        const cells = this.wasmModule.parseText(text, pitchSystem);
+        const cells = this.wasmModule.parseText(text, pitchSystem, this.theDocument.max_accidental_depth, this.theDocument.notation_tokens);
         const line =this.theDocument.lines[0];
         line.cells = cells; // Replace main line with parsed cells
       }
@@ -491,7 +490,7 @@ class MusicNotationEditor {
      * Delete text at specified range
      */
   async deleteRange(start, end) {
-    if (!this.isInitialized || !this.wasmModule) {
+    if (!this.isInitialized || !this.wasmModule || !this.isLineEditable(0)) {
       return;
     }
 
@@ -811,6 +810,11 @@ class MusicNotationEditor {
   handleNavigation(key) {
     const startTime = performance.now();
 
+    // Horizontal moves end the column memory of up/down movement
+    if (key !== 'ArrowUp' && key !== 'ArrowDown') {
+      this.wasmModule?.clearDesiredColumn();
+    }
+
     switch (key) {
       case 'ArrowLeft':
         this.navigateLeft();
@@ -1284,6 +1288,9 @@ class MusicNotationEditor {
      * Handle backspace key with selection awareness and beat recalculation
      */
   async handleBackspace() {
+    if (!this.isLineEditable(0)) {
+      return;
+    }
     logger.time('handleBackspace', LOG_CATEGORIES.EDITOR);
     const cursorPos = this.getCursorPosition();
 
@@ -1308,15 +1315,14 @@ class MusicNotationEditor {
         const glyphLength = cellToDelete ? (cellToDelete.glyph || '').length : 0;
         const hadMultipleChars = glyphLength > 1;
 
-        logger.debug(LOG_CATEGORIES.EDITOR, 'Calling WASM deleteCharacter', {
+        logger.debug(LOG_CATEGORIES.EDITOR, 'Calling WASM deleteCharacterInLine', {
           position: cursorPos - 1,
           laneSize: letterLane.length,
           glyphLength,
           hadMultipleChars
         });
 
-        const updatedCells = this.wasmModule.deleteCharacter(letterLane, cursorPos - 1);
-        line.cells = updatedCells;
+        const updatedCells = this.deleteCharacterInLine(0, cursorPos - 1);
 
         // Only move cursor if the entire cell was deleted (had 1 char or cell is now gone)
         // If it had multiple chars, one char was removed but cursor stays at same position
@@ -1357,6 +1363,9 @@ class MusicNotationEditor {
      * Handle delete key with selection awareness and beat recalculation
      */
   async handleDelete() {
+    if (!this.isLineEditable(0)) {
+      return;
+    }
     if (this.hasSelection()) {
       // Delete selected content
       await this.deleteSelection();
@@ -1368,11 +1377,7 @@ class MusicNotationEditor {
       if (cursorPos < maxPos) {
         // Use WASM API to delete character
         if (this.theDocument && this.theDocument.lines && this.theDocument.lines.length > 0) {
-          const line =this.theDocument.lines[0];
-          const letterLane = line.cells;
-
-          const updatedCells = this.wasmModule.deleteCharacter(letterLane, cursorPos);
-          line.cells = updatedCells;
+          this.deleteCharacterInLine(0, cursorPos);
         }
 
         // Recalculate beats after deletion
@@ -1387,6 +1392,18 @@ class MusicNotationEditor {
     }
   }
 
+  /**
+     * Delete one character of a line through WASM, which refuses locked lines
+     */
+  deleteCharacterInLine(lineIndex, position) {
+    // Preserve the state field before WASM call (it's skipped during serialization)
+    const preservedState = this.theDocument.state;
+    const updatedDocument = this.wasmModule.deleteCharacterInLine(this.theDocument, lineIndex, position);
+    updatedDocument.state = preservedState;
+    this.theDocument = updatedDocument;
+    return updatedDocument.lines[lineIndex].cells;
+  }
+
   /**
      * Recalculate beats after content changes
      */
@@ -1419,10 +1436,28 @@ class MusicNotationEditor {
     return letterLane.map(cell => cell.glyph || '').join('');
   }
 
+  /**
+     * Check whether a line may be edited
+     *
+     * Locked lines allow cursor movement and selection only; edits are
+     * refused with a warning.
+     */
+  isLineEditable(lineIndex = 0) {
+    const line = this.theDocument?.lines?.[lineIndex];
+    if (line?.locked) {
+      this.showWarning(`Line ${lineIndex + 1} is locked`, { source: 'Line Lock', important: true });
+      return false;
+    }
+    return true;
+  }
+
   /**
      * Validate that a selection is valid for musical commands
      */
   validateSelectionForCommands() {
+    if (!this.isLineEditable(0)) {
+      return false;
+    }
     if (!this.hasSelection()) {
       console.log('No selection for command');
       return false;
@@ -1455,7 +1490,28 @@ class MusicNotationEditor {
      * Toggle slur on current selection
      */
   async toggleSlur() {
-    return this.applySlur();
+    if (!this.isInitialized || !this.wasmModule || !this.validateSelectionForCommands()) {
+      return;
+    }
+
+    if (!this.theDocument?.lines?.[0]) {
+      return;
+    }
+
+    try {
+      const selection = this.getSelection();
+      // Preserve the state field before WASM call (it's skipped during serialization)
+      const preservedState = this.theDocument.state;
+      const updatedDocument = this.wasmModule.toggleSlurSelection(this.theDocument, 0, selection.start, selection.end);
+      updatedDocument.state = preservedState;
+      this.theDocument = updatedDocument;
+      this.addToConsoleLog(`Toggled slur on cells ${selection.start}..${selection.end}`);
+
+      await this.render();
+      this.updateSelectionDisplay();
+    } catch (error) {
+      this.addToConsoleLog(`Slur not changed: ${error}`);
+    }
   }
 
   /**
@@ -87,64 +87,15 @@ class DOMRenderer {
       }
 
 
-      /* Octave dots using ::before pseudo-element */
-      /* Upper octave: one dot ABOVE cell (outside bbox) */
-      .char-cell[data-octave="1"]::before {
-        content: '•';
+      /* Marks drawn around cells (octaves, fingerings, fermatas, labels) */
+      .line-mark {
         position: absolute;
-        left: 50%;
-        top: -10px;
-        transform: translateX(-50%);
-        font-size: 12px;
+        font-size: 11px;
         line-height: 1;
         color: #000;
         pointer-events: none;
         z-index: 2;
       }
-
-      /* Upper octave: two dots ABOVE cell (outside bbox) */
-      .char-cell[data-octave="2"]::before {
-        content: '••';
-        position: absolute;
-        left: 50%;
-        top: -10px;
-        transform: translateX(-50%);
-        font-size: 12px;
-        line-height: 1;
-        color: #000;
-        letter-spacing: 2px;
-        pointer-events: none;
-        z-index: 2;
-      }
-
-      /* Lower octave: one dot BELOW cell (outside bbox) */
-      .char-cell[data-octave="-1"]::before {
-        content: '•';
-        position: absolute;
-        left: 50%;
-        bottom: -10px;
-        transform: translateX(-50%);
-        font-size: 12px;
-        line-height: 1;
-        color: #000;
-        pointer-events: none;
-        z-index: 2;
-      }
-
-      /* Lower octave: two dots BELOW cell (outside bbox) */
-      .char-cell[data-octave="-2"]::before {
-        content: '••';
-        position: absolute;
-        left: 50%;
-        bottom: -10px;
-        transform: translateX(-50%);
-        font-size: 12px;
-        line-height: 1;
-        color: #000;
-        letter-spacing: 2px;
-        pointer-events: none;
-        z-index: 2;
-      }
     `;
     document.head.appendChild(style);
   }
@@ -217,6 +168,9 @@ class DOMRenderer {
     // Clear previous content
     this.clearCanvas();
 
+    // Octave style, accidental display, beat separator and tuning come from the document
+    this.applyLayoutSettings(doc);
+
     if (!doc.lines || doc.lines.length === 0) {
       this.showEmptyState();
       return;
@@ -233,7 +187,7 @@ class DOMRenderer {
     // Beat loops are now rendered via CSS on cells (no separate elements needed)
     // Slurs need to be rendered as separate divs (can't use ::after because beat loops use it)
     this.renderSlurs(doc);
-    // Octave markings are now rendered via CSS (data-octave attribute)
+    // Octave markings are drawn with the other line marks (renderLineMarks)
 
     // Update render statistics
     const endTime = performance.now();
@@ -299,16 +253,20 @@ class DOMRenderer {
     const mainLine = line.cells;
     const beats = line.beats || [];
     this.renderCells(mainLine, lineIndex, lineElement, beats);
+    this.renderLineMarks(line, lineIndex, lineElement);
 
     // Render line label
     if (line.label) {
       this.renderLineLabel(line.label, lineElement);
     }
 
-    // Render lyrics (direct field on line)
-    if (line.lyrics) {
-      this.renderLyrics(line.lyrics, lineElement);
-    }
+    // Render lyrics (direct field on line), with further verses stacked below
+    const verses = [line.lyrics || '', ...(line.lyric_verses || [])];
+    verses.forEach((verse, verseIndex) => {
+      if (verse) {
+        this.renderLyrics(verse, lineElement, verseIndex);
+      }
+    });
 
     // Render tala (direct field on line)
     if (line.tala) {
@@ -549,6 +507,105 @@ class DOMRenderer {
     }
   }
 
+  /**
+     * Pass the document's display settings to the WASM layout renderer
+     */
+  applyLayoutSettings(doc) {
+    const layoutRenderer = this.editor?.wasmModule?.layoutRenderer;
+    if (!layoutRenderer) return;
+
+    try {
+      layoutRenderer.applyDocumentSettings(doc);
+    } catch (error) {
+      console.error('Failed to apply layout settings:', error);
+    }
+  }
+
+  /**
+     * Render the marks the WASM layout renderer calculates for a line:
+     * display glyphs, komal/tivra strokes, octave marks, fingerings, stacked
+     * pitches, grace slashes, fermatas, durations, subdivision ticks and cents
+     */
+  renderLineMarks(line, lineIndex, lineElement) {
+    const layoutRenderer = this.editor?.wasmModule?.layoutRenderer;
+    if (!layoutRenderer || !line.cells || line.cells.length === 0) return;
+
+    let marks;
+    try {
+      marks = layoutRenderer.calculateLineMarks(line);
+    } catch (error) {
+      console.error('Failed to calculate line marks:', error);
+      return;
+    }
+
+    // Mark x is laid out on the WASM character grid; keep its offset from
+    // the cell but place it on the cell's measured position
+    const charWidth = layoutRenderer.getCharWidth();
+    const place = (element, mark, x = mark.x, y = mark.y) => {
+      const cell = line.cells[mark.cell_index] || {};
+      const cellX = cell.x ?? 60 + mark.cell_index * charWidth;
+      element.style.left = `${cellX + x - mark.cell_index * charWidth}px`;
+      element.style.top = `${(cell.y ?? 32) + y}px`;
+      lineElement.appendChild(element);
+      return element;
+    };
+    const text = (mark, content, className) => {
+      const element = document.createElement('span');
+      element.className = `line-mark ${className}`;
+      element.textContent = content;
+      return place(element, mark);
+    };
+
+    marks.glyphs.forEach((glyph, cellIndex) => {
+      const element = this.charCellElements.get(`${lineIndex}-${cellIndex}`);
+      if (element && glyph !== line.cells[cellIndex].glyph) {
+        element.textContent = glyph;
+      }
+    });
+
+    marks.accidentals.forEach((marker) => {
+      const element = this.charCellElements.get(`${lineIndex}-${marker.cell_index}`);
+      if (element) {
+        element.textContent = marker.base_glyph;
+      }
+      const stroke = document.createElement('div');
+      stroke.className = `line-mark accidental-${marker.kind.toLowerCase()}`;
+      stroke.style.width = `${marker.width}px`;
+      stroke.style.height = `${marker.height}px`;
+      stroke.style.background = '#000';
+      place(stroke, marker);
+    });
+
+    marks.octaves.forEach(mark => text(mark, mark.glyph, 'octave-mark'));
+    marks.fingerings.forEach(mark => text(mark, mark.text, 'fingering-mark'));
+    marks.stacked_pitches.forEach(mark => text(mark, mark.glyph, 'stacked-pitch'));
+    marks.fermatas.forEach(mark => text(mark, mark.glyph, 'fermata-mark'));
+    marks.durations.forEach(mark => text(mark, mark.text, 'duration-label'));
+    marks.cents.forEach(mark => text(mark, mark.text, 'cents-label'));
+
+    marks.subdivisions.forEach((tick) => {
+      const element = document.createElement('div');
+      element.className = 'line-mark subdivision-tick';
+      element.style.width = '1px';
+      element.style.height = '4px';
+      element.style.background = '#666';
+      place(element, tick);
+    });
+
+    marks.grace_slashes.forEach((slash) => {
+      const dx = slash.x2 - slash.x1;
+      const dy = slash.y2 - slash.y1;
+      const element = document.createElement('div');
+      element.className = 'line-mark grace-slash';
+      element.style.width = `${Math.hypot(dx, dy)}px`;
+      element.style.height = '1px';
+      element.style.background = '#000';
+      element.style.transformOrigin = '0 0';
+      element.style.transform = `rotate(${Math.atan2(dy, dx)}rad)`;
+      place(element, slash, slash.x1, (line.cells[slash.cell_index]?.h ?? 16) + slash.y1);
+    });
+  }
+
   /**
      * Render line label
      */
@@ -568,13 +625,14 @@ class DOMRenderer {
   /**
      * Render lyrics
      */
-  renderLyrics(lyrics, lineElement) {
+  renderLyrics(lyrics, lineElement, verseIndex = 0) {
     const lyricsElement = document.createElement('div');
     lyricsElement.className = 'line-lyrics text-sm';
     lyricsElement.textContent = lyrics;
     lyricsElement.style.position = 'absolute';
     lyricsElement.style.left = '60px'; // Align with cells (LEFT_MARGIN_PX)
-    lyricsElement.style.top = '52px'; // Below cells (32px cell top + 16px cell height + 4px gap)
+    // Below cells (32px cell top + 16px cell height + 4px gap), one 16px row per verse
+    lyricsElement.style.top = `${52 + verseIndex * 16}px`;
     lyricsElement.style.fontStyle = 'italic';
     lyricsElement.style.color = '#6b7280'; // gray-500
 
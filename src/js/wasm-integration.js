@@ -75,12 +75,14 @@ class WasmIntegration {
       applySlur: wasmModule.applySlur,
       removeSlur: wasmModule.removeSlur,
       hasSlurInSelection: wasmModule.hasSlurInSelection,
+      toggleSlurSelection: wasmModule.toggleSlurSelection,
 
       // Document management API
       createNewDocument: wasmModule.createNewDocument,
       setTitle: wasmModule.setTitle,
       setStaveLabel: wasmModule.setStaveLabel,
       setStaveLyrics: wasmModule.setStaveLyrics,
+      setLineLyricVerse: wasmModule.setLineLyricVerse,
       setStaveTala: wasmModule.setStaveTala
     };
   }
@@ -235,6 +237,19 @@ class WasmIntegration {
     return this.safeCall('hasSlurInSelection', cells, startPos, endPos);
   }
 
+  /**
+   * Toggle a slur over the selection (adds one, or removes an existing one)
+   *
+   * @param {Object} document - Current document
+   * @param {number} lineIndex - Line of the selection
+   * @param {number} startPos - Selection start
+   * @param {number} endPos - Selection end
+   * @returns {Object} Updated document
+   */
+  toggleSlurSelection(document, lineIndex, startPos, endPos) {
+    return this.safeCall('toggleSlurSelection', document, lineIndex, startPos, endPos);
+  }
+
   /**
    * Derive beats from cells
    *
@@ -302,6 +317,19 @@ class WasmIntegration {
     return this.safeCall('setStaveLyrics', document, staveIndex, lyrics);
   }
 
+  /**
+   * Set one lyric verse of a stave
+   *
+   * @param {Object} document - Document object
+   * @param {number} staveIndex - Stave index
+   * @param {number} verseIndex - Verse index (0 is the stave lyrics)
+   * @param {string} text - Verse text
+   * @returns {Object} Updated document
+   */
+  setLineLyricVerse(document, staveIndex, verseIndex, text) {
+    return this.safeCall('setLineLyricVerse', document, staveIndex, verseIndex, text);
+  }
+
   /**
    * Set stave tala
    *
@@ -5,7 +5,9 @@
 
 pub mod models;
 pub mod parse;
+pub mod ir;
 pub mod renderers;
+pub mod transposition;
 pub mod utils;
 pub mod api;
 
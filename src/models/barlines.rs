@@ -6,7 +6,7 @@
 use serde::{Serialize, Deserialize};
 
 /// Barline types and handling
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum BarlineType {
     Single,      // |
     Double,      // ||
@@ -28,6 +28,19 @@ impl BarlineType {
         }
     }
 
+    /// Parse a toolbar command kind ("single", "double", "final",
+    /// "repeat-left", "repeat-right")
+    pub fn from_kind(kind: &str) -> Option<Self> {
+        match kind {
+            "single" => Some(BarlineType::Single),
+            "double" => Some(BarlineType::Double),
+            "final" => Some(BarlineType::Final),
+            "repeat-left" => Some(BarlineType::StartRepeat),
+            "repeat-right" => Some(BarlineType::EndRepeat),
+            _ => None,
+        }
+    }
+
     /// Get barline symbol
     pub fn symbol(&self) -> &'static str {
         match self {
@@ -0,0 +1,88 @@
+//! Compact binary document format
+//!
+//! Documents are stored as a 4-byte magic tag, a little-endian `u16` format
+//! version and a bincode payload of the same serde representation used for
+//! JSON. The version lets later releases migrate older payloads.
+
+use super::core::Document;
+
+/// Magic bytes at the start of every binary document
+pub const BINARY_MAGIC: &[u8; 4] = b"EDOC";
+
+/// Current binary format version
+pub const BINARY_FORMAT_VERSION: u16 = 1;
+
+/// Length of the magic tag plus version
+const HEADER_LEN: usize = 6;
+
+impl Document {
+    /// Serialize to the versioned binary format
+    pub fn to_binary(&self) -> Result<Vec<u8>, String> {
+        let payload = bincode::serialize(self)
+            .map_err(|e| format!("Binary serialization error: {}", e))?;
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(BINARY_MAGIC);
+        bytes.extend_from_slice(&BINARY_FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        Ok(bytes)
+    }
+
+    /// Deserialize from the versioned binary format
+    pub fn from_binary(bytes: &[u8]) -> Result<Document, String> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != BINARY_MAGIC {
+            return Err("Not a binary document (missing magic header)".to_string());
+        }
+
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        match version {
+            1 => bincode::deserialize(&bytes[HEADER_LEN..])
+                .map_err(|e| format!("Binary deserialization error: {}", e)),
+            _ => Err(format!("Unsupported binary document version {}", version)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Line, PitchSystem};
+    use crate::parse::grammar::parse_cells;
+
+    fn sample_document() -> Document {
+        let mut document = Document::new();
+        document.title = Some("Yaman".to_string());
+        document.tonic = Some("C".to_string());
+        document.pitch_system = Some(PitchSystem::Sargam);
+        for text in ["S R G M | P - D N", "S' N D P | M G R S"] {
+            let mut line = Line::new();
+            line.cells = parse_cells(text, PitchSystem::Sargam);
+            line.lyrics = "aa re".to_string();
+            document.add_line(line);
+        }
+        document
+    }
+
+    #[test]
+    fn test_binary_round_trip_is_smaller_than_json() {
+        let document = sample_document();
+
+        let bytes = document.to_binary().unwrap();
+        let restored = Document::from_binary(&bytes).unwrap();
+
+        // Runtime state is not serialized, so compare the persisted content
+        let json = serde_json::to_vec(&document).unwrap();
+        assert_eq!(serde_json::to_vec(&restored).unwrap(), json);
+        assert!(bytes.len() < json.len());
+    }
+
+    #[test]
+    fn test_binary_header_is_checked() {
+        let mut bytes = sample_document().to_binary().unwrap();
+        assert_eq!(&bytes[..4], BINARY_MAGIC);
+
+        bytes[4] = 99;
+        assert!(Document::from_binary(&bytes).unwrap_err().contains("version 99"));
+        assert!(Document::from_binary(b"{}").is_err());
+    }
+}
@@ -7,9 +7,13 @@ use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
 // Re-export from other modules
-pub use super::elements::{ElementKind, PitchSystem, SlurIndicator};
-pub use super::notation::{BeatSpan, SlurSpan, Position, Selection, Range, CursorPosition};
+pub use super::elements::{AccidentalPreference, BeatSeparator, ElementKind, OctaveStyle, PitchSystem, SlurIndicator, SystemMarker};
+use super::pitch_systems::NumberSystem;
+use crate::parse::grammar::{NotationTokens, DEFAULT_MAX_ACCIDENTAL_DEPTH};
+use crate::transposition::key_signature_fifths;
+pub use super::notation::{BeatSpan, SlurSpan, Position, Selection, Range, CursorPosition, NoteSize, OrnamentType};
 use super::serde_helpers::serialize_option_as_null;
+use crate::utils::pitch_utils::parse_pitch_code;
 
 /// The fundamental unit representing one visible glyph in musical notation
 #[repr(C)]
@@ -40,6 +44,29 @@ pub struct Cell {
     /// Slur indicator (None, SlurStart, SlurEnd)
     pub slur_indicator: SlurIndicator,
 
+    /// Ornament attached to this note (trill, mordent, turn, ...)
+    #[serde(default)]
+    pub ornament: OrnamentType,
+
+    /// Printed size (normal or cue)
+    #[serde(default)]
+    pub size: NoteSize,
+
+    /// Pitches stacked on this note as a chord, as (pitch code, octave)
+    ///
+    /// Lines stay monophonic: the stack is an annotation drawn above the
+    /// note and exported as chord tones, it takes no columns of its own.
+    #[serde(default)]
+    pub extra_pitches: Vec<(String, i8)>,
+
+    /// Fingering shown above the note, as free text ("3", "1-2-3")
+    #[serde(default)]
+    pub fingering: Option<String>,
+
+    /// Staccato articulation (played short)
+    #[serde(default)]
+    pub staccato: bool,
+
     /// Layout cache properties (calculated at render time) - ephemeral, not saved
     #[serde(skip)]
     pub x: f32,
@@ -71,6 +98,11 @@ impl Cell {
             pitch_system: None,
             octave: 0,
             slur_indicator: SlurIndicator::None,
+            ornament: OrnamentType::None,
+            size: NoteSize::Normal,
+            extra_pitches: Vec::new(),
+            fingering: None,
+            staccato: false,
             x: 0.0,
             y: 0.0,
             w: 0.0,
@@ -80,6 +112,34 @@ impl Cell {
         }
     }
 
+    /// Carry the annotations of `other` over to this cell
+    ///
+    /// Used wherever a cell is re-parsed from its text: the parser only knows
+    /// the glyph, so octave, slur, ornament, size, chord tones, fingering
+    /// and staccato have to be copied from the cell being replaced.
+    pub fn copy_annotations_from(&mut self, other: &Cell) {
+        self.octave = other.octave;
+        self.slur_indicator = other.slur_indicator;
+        self.ornament = other.ornament;
+        self.size = other.size;
+        self.extra_pitches = other.extra_pitches.clone();
+        self.fingering = other.fingering.clone();
+        self.staccato = other.staccato;
+    }
+
+    /// The text a user would type to produce this cell
+    ///
+    /// Glyphs are stored as typed text rather than font codepoints, so this is
+    /// the pitch code for notes (the canonical spelling) and the glyph for
+    /// everything else. Octave is not part of the typed text; it is applied
+    /// separately and stays in `octave`.
+    pub fn to_source_char(&self) -> String {
+        match (&self.kind, &self.pitch_code) {
+            (ElementKind::PitchedElement, Some(code)) => code.clone(),
+            _ => self.glyph.clone(),
+        }
+    }
+
     /// Check if this cell is the head of a multi-character token
     pub fn is_head(&self) -> bool {
         self.flags & 0x01 != 0
@@ -122,6 +182,20 @@ impl Cell {
         }
     }
 
+    /// Check if this cell is a rest written with a dialect rest token
+    pub fn is_rest(&self) -> bool {
+        self.flags & 0x08 != 0
+    }
+
+    /// Set rest flag
+    pub fn set_rest(&mut self, is_rest: bool) {
+        if is_rest {
+            self.flags |= 0x08;
+        } else {
+            self.flags &= !0x08;
+        }
+    }
+
     /// Check if this cell is part of a temporal sequence
     pub fn is_temporal(&self) -> bool {
         self.kind.is_temporal()
@@ -205,6 +279,10 @@ pub struct Line {
     #[serde(default)]
     pub lyrics: String,
 
+    /// Further lyric verses; `lyrics` is verse 0 and `lyric_verses[0]` is verse 1
+    #[serde(default)]
+    pub lyric_verses: Vec<String>,
+
     /// Musical tonic for this line (overrides composition tonic, empty if not set)
     #[serde(default)]
     pub tonic: String,
@@ -221,10 +299,40 @@ pub struct Line {
     #[serde(default)]
     pub tempo: String,
 
+    /// Free-text performance direction such as "rubato" or "ad lib." (empty if not set)
+    #[serde(default)]
+    pub direction: String,
+
     /// Time signature for this line (empty if not set)
     #[serde(default)]
     pub time_signature: String,
 
+    /// Unit note of each cell as a note-value denominator (4 = quarter,
+    /// 8 = eighth); 0 divides each quarter-note beat among its cells
+    #[serde(default)]
+    pub base_duration: u32,
+
+    /// Play counts of measures by index ("×3"); missing entries and values
+    /// below 2 mean the measure is played once
+    #[serde(default)]
+    pub measure_repeat_counts: Vec<u32>,
+
+    /// Read-only line: edits are refused, the cursor and selection still move over it
+    #[serde(default)]
+    pub locked: bool,
+
+    /// Multi-staff system grouping marker
+    #[serde(default)]
+    pub system_marker: SystemMarker,
+
+    /// System this line belongs to (derived by `recalculate_system_and_part_ids`)
+    #[serde(default)]
+    pub system_id: usize,
+
+    /// Export part identifier (derived by `recalculate_system_and_part_ids`)
+    #[serde(default)]
+    pub part_id: String,
+
     /// Derived beat spans (calculated, not stored)
     #[serde(skip)]
     pub beats: Vec<BeatSpan>,
@@ -242,16 +350,179 @@ impl Line {
             label: String::new(),
             tala: String::new(),
             lyrics: String::new(),
+            lyric_verses: Vec::new(),
             tonic: String::new(),
             pitch_system: 0,
             key_signature: String::new(),
             tempo: String::new(),
+            direction: String::new(),
             time_signature: String::new(),
+            base_duration: 0,
+            measure_repeat_counts: Vec::new(),
+            locked: false,
+            system_marker: SystemMarker::None,
+            system_id: 0,
+            part_id: String::new(),
             beats: Vec::new(),
             slurs: Vec::new(),
         }
     }
 
+    /// Check if the line's direction frees it from strict tempo
+    ///
+    /// True for "rubato", "ad lib." ("ad libitum"), "senza misura" and
+    /// "free time", in any case and with or without a trailing period.
+    pub fn is_free_time(&self) -> bool {
+        let direction = self.direction.trim().trim_end_matches('.').to_lowercase();
+        matches!(direction.as_str(), "rubato" | "ad lib" | "ad libitum" | "senza misura" | "free time")
+    }
+
+    /// Number of times measure `measure_index` is played (at least 1)
+    ///
+    /// Measures are the segments between barline cells, counted from 0 with
+    /// empty segments included, as in `measure_content_range`.
+    pub fn measure_repeat_count(&self, measure_index: usize) -> u32 {
+        self.measure_repeat_counts.get(measure_index).copied().unwrap_or(1).max(1)
+    }
+
+    /// Set how many times measure `measure_index` is played
+    ///
+    /// A count of 0 or 1 clears the annotation.
+    pub fn set_measure_repeat_count(&mut self, measure_index: usize, count: u32) {
+        if self.measure_repeat_counts.len() <= measure_index {
+            self.measure_repeat_counts.resize(measure_index + 1, 1);
+        }
+        self.measure_repeat_counts[measure_index] = count.max(1);
+        while self.measure_repeat_counts.last() == Some(&1) {
+            self.measure_repeat_counts.pop();
+        }
+    }
+
+    /// All lyric verses in order, starting with `lyrics` as verse 0
+    ///
+    /// Trailing empty verses are omitted.
+    pub fn verses(&self) -> Vec<&str> {
+        let mut verses: Vec<&str> = std::iter::once(self.lyrics.as_str())
+            .chain(self.lyric_verses.iter().map(String::as_str))
+            .collect();
+        while verses.last().is_some_and(|verse| verse.is_empty()) {
+            verses.pop();
+        }
+        verses
+    }
+
+    /// Set the text of lyric verse `verse_index` (0 is `lyrics`)
+    ///
+    /// Missing verses in between are created empty.
+    pub fn set_lyric_verse(&mut self, verse_index: usize, text: &str) {
+        if verse_index == 0 {
+            self.lyrics = text.to_string();
+            return;
+        }
+        if self.lyric_verses.len() < verse_index {
+            self.lyric_verses.resize(verse_index, String::new());
+        }
+        self.lyric_verses[verse_index - 1] = text.to_string();
+    }
+
+    /// Re-derive the beat spans of this line from its cells
+    ///
+    /// `beats` is a cache that goes stale after structural edits (inserting a
+    /// space splits a beat, deleting one merges two).
+    pub fn reflow_beats(&mut self) -> &[BeatSpan] {
+        self.reflow_beats_with(BeatSeparator::SingleSpace)
+    }
+
+    /// Re-derive the beat spans of this line under a whitespace separation policy
+    pub fn reflow_beats_with(&mut self, separator: BeatSeparator) -> &[BeatSpan] {
+        self.beats = crate::parse::beats::BeatDeriver::with_separator(separator).extract_implicit_beats(&self.cells);
+        &self.beats
+    }
+
+    /// Merge split accidental cells into the preceding note (see
+    /// `Document::coalesce_accidentals`)
+    pub fn coalesce_accidentals(&mut self) -> usize {
+        let mut merged = 0;
+        let mut index = 1;
+
+        while index < self.cells.len() {
+            let (before, after) = self.cells.split_at_mut(index);
+            let (note, accidental) = (&mut before[index - 1], &after[0]);
+
+            let is_bare_accidental = accidental.kind == ElementKind::Text
+                && matches!(accidental.glyph.as_str(), "#" | "##" | "b" | "bb");
+            let pitch_code = note
+                .pitch_code
+                .as_ref()
+                .map(|code| format!("{}{}", code, accidental.glyph));
+            let valid = note.kind == ElementKind::PitchedElement
+                && pitch_code.as_deref().is_some_and(|code| {
+                    parse_pitch_code(code, note.pitch_system.unwrap_or_default()).is_some()
+                });
+
+            if is_bare_accidental && valid {
+                note.glyph.push_str(&accidental.glyph);
+                note.pitch_code = pitch_code;
+                if !note.has_slur() {
+                    note.slur_indicator = accidental.slur_indicator;
+                }
+                self.cells.remove(index);
+                merged += 1;
+            } else {
+                index += 1;
+            }
+        }
+
+        if merged > 0 {
+            for (col, cell) in self.cells.iter_mut().enumerate() {
+                cell.col = col;
+            }
+        }
+        merged
+    }
+
+    /// Repair cell structure left behind by older documents (see
+    /// `Document::repair_structure`)
+    pub fn repair_structure(&mut self) -> usize {
+        let before = self.cells.len();
+        self.cells.retain(|cell| !cell.glyph.is_empty());
+        let mut repaired = before - self.cells.len();
+
+        // A dash run before the first note has nothing to extend
+        let first_note = self
+            .cells
+            .iter()
+            .position(|cell| cell.kind == ElementKind::PitchedElement)
+            .unwrap_or(self.cells.len());
+        let mut in_run = false;
+        for cell in &mut self.cells[..first_note] {
+            let is_dash = cell.kind == ElementKind::UnpitchedElement;
+            if is_dash && !in_run && !cell.is_rest() {
+                cell.set_rest(true);
+                repaired += 1;
+            }
+            in_run = is_dash;
+        }
+
+        if self.quantize_columns() {
+            repaired += 1;
+        }
+        repaired
+    }
+
+    /// Make each cell's `col` equal its index, closing gaps and overlaps
+    ///
+    /// Returns whether any column changed.
+    pub fn quantize_columns(&mut self) -> bool {
+        let misnumbered = self.cells.iter().enumerate().any(|(col, cell)| cell.col != col);
+        if misnumbered {
+            for (col, cell) in self.cells.iter_mut().enumerate() {
+                cell.col = col;
+            }
+        }
+        misnumbered
+    }
+
     /// Get all cells (for compatibility)
     pub fn get_all_cells(&self) -> &[Cell] {
         &self.cells
@@ -316,6 +587,62 @@ pub struct Document {
     /// Default key signature for the composition
     pub key_signature: Option<String>,
 
+    /// Preferred sharp/flat spelling of chromatic number-system pitches
+    #[serde(default)]
+    pub accidental_preference: AccidentalPreference,
+
+    /// Maximum repeated accidentals on one note (2 = double sharp/flat)
+    #[serde(default = "default_max_accidental_depth")]
+    pub max_accidental_depth: u8,
+
+    /// Whitespace needed between beats (single or double space)
+    #[serde(default)]
+    pub beat_separator: BeatSeparator,
+
+    /// Split note values that need more than two dots into tied notes on export
+    #[serde(default)]
+    pub explicit_durations: bool,
+
+    /// How far (as a share of the beat) each note of an irregular beat may be
+    /// from an even triplet or quintuplet and still be exported as one;
+    /// 0 keeps exact durations
+    #[serde(default)]
+    pub tuplet_tolerance: f64,
+
+    /// Insert a barline after typing the beat that fills a measure
+    #[serde(default)]
+    pub auto_barline: bool,
+
+    /// Scale that typed notes are expected to follow: a scale name or number
+    /// pitch codes, as accepted by `scale_by_name` (empty if not set)
+    #[serde(default)]
+    pub active_constraint: String,
+
+    /// Correct the case of typed sargam letters (komal/shuddha) to fit `active_constraint`
+    #[serde(default)]
+    pub sargam_case_assist: bool,
+
+    /// A4 reference and temperament for frequency and pitch-bend output
+    #[serde(default)]
+    pub tuning: super::tuning::TuningSettings,
+
+    /// Dialect characters for extensions and rests
+    #[serde(default)]
+    pub notation_tokens: NotationTokens,
+
+    /// Draw komal/tivra as traditional strokes on sargam lines instead of letter case
+    #[serde(default)]
+    pub traditional_accidentals: bool,
+
+    /// Show komal sargam notes as the shuddha letter with a flat sign ("R♭")
+    /// instead of lowercase ("r")
+    #[serde(default)]
+    pub sargam_flat_signs: bool,
+
+    /// How octave markers are drawn and written in text export
+    #[serde(default)]
+    pub octave_style: OctaveStyle,
+
     /// Creation and modification timestamps
     pub created_at: Option<String>,
     pub modified_at: Option<String>,
@@ -331,6 +658,11 @@ pub struct Document {
     pub state: DocumentState,
 }
 
+/// Serde default for `Document::max_accidental_depth`
+fn default_max_accidental_depth() -> u8 {
+    DEFAULT_MAX_ACCIDENTAL_DEPTH
+}
+
 impl Document {
     /// Create a new empty document
     pub fn new() -> Self {
@@ -340,6 +672,19 @@ impl Document {
             tonic: None,
             pitch_system: None,
             key_signature: None,
+            accidental_preference: AccidentalPreference::Auto,
+            max_accidental_depth: DEFAULT_MAX_ACCIDENTAL_DEPTH,
+            beat_separator: BeatSeparator::SingleSpace,
+            explicit_durations: false,
+            tuplet_tolerance: 0.0,
+            auto_barline: false,
+            active_constraint: String::new(),
+            sargam_case_assist: false,
+            tuning: super::tuning::TuningSettings::default(),
+            notation_tokens: NotationTokens::default(),
+            traditional_accidentals: false,
+            sargam_flat_signs: false,
+            octave_style: OctaveStyle::Dots,
             created_at: None,  // Timestamps set by JavaScript layer
             modified_at: None,  // Timestamps set by JavaScript layer
             version: None,
@@ -423,6 +768,439 @@ impl Document {
         }
     }
 
+    /// Recompute every line's `system_id` and `part_id` from its system marker
+    pub fn recalculate_system_and_part_ids(&mut self) {
+        let ids = compute_system_and_part_ids(&self.lines);
+        for (line, (system_id, part_id)) in self.lines.iter_mut().zip(ids) {
+            line.system_id = system_id;
+            line.part_id = part_id;
+        }
+    }
+
+    /// Position after the last cell of the last line
+    pub fn end_position(&self) -> CursorPosition {
+        let stave = self.lines.len().saturating_sub(1);
+        let column = self.lines.last().map_or(0, |line| line.cells.len());
+        CursorPosition::at(stave, column)
+    }
+
+    /// Move the cursor to a line's column, as a horizontal move or a click
+    ///
+    /// Forgets the desired column of vertical movement.
+    pub fn move_cursor_to(&mut self, cursor: CursorPosition) {
+        self.state.cursor = cursor;
+        self.state.selection_manager.desired_col = None;
+    }
+
+    /// Set the column that up/down movement aims for
+    ///
+    /// Use after a programmatic jump; it stays in effect until the next
+    /// `move_cursor_to`.
+    pub fn set_desired_column(&mut self, column: usize) {
+        self.state.selection_manager.desired_col = Some(column);
+    }
+
+    /// Move the cursor one line up or down
+    ///
+    /// The cursor aims for the desired column (by default the column it
+    /// starts from) and is clamped to the end of shorter lines, so passing
+    /// through a short line does not lose the column. At the first or last
+    /// line the cursor stays put.
+    pub fn move_cursor_vertically(&mut self, down: bool) -> CursorPosition {
+        let cursor = self.state.cursor;
+        let stave = if down { cursor.stave + 1 } else { cursor.stave.wrapping_sub(1) };
+        let Some(line) = self.lines.get(stave) else {
+            return cursor;
+        };
+
+        let desired = *self.state.selection_manager.desired_col.get_or_insert(cursor.column);
+        self.state.cursor = CursorPosition::at(stave, desired.min(line.cells.len()));
+        self.state.cursor
+    }
+
+    /// Select from `cursor` to the start of the document (0, 0) or to its end
+    ///
+    /// The cursor moves to the boundary and the selection is anchored at the
+    /// old cursor, as Ctrl+Shift+Home/End do. A cursor already at the
+    /// boundary yields an empty selection.
+    pub fn select_to_document_boundary(&mut self, cursor: CursorPosition, to_end: bool) -> Selection {
+        let boundary = if to_end { self.end_position() } else { CursorPosition::at(0, 0) };
+
+        self.state.cursor = cursor;
+        self.state.start_selection();
+        self.state.cursor = boundary;
+        self.state.extend_selection();
+
+        self.state
+            .get_selection()
+            .cloned()
+            .expect("selection was just started")
+    }
+
+    /// Check that a line exists and may be edited
+    pub fn check_editable(&self, line_index: usize) -> Result<(), String> {
+        let line = self.lines.get(line_index).ok_or_else(|| format!("Line index {} out of bounds", line_index))?;
+        if line.locked {
+            return Err(format!("Line {} is locked", line_index + 1));
+        }
+        Ok(())
+    }
+
+    /// Delete a whole line, keeping at least one (empty) line in the document
+    ///
+    /// System and part ids are recalculated and the cursor is kept on a
+    /// valid line. Locked lines cannot be deleted. Returns the removed line.
+    pub fn delete_line(&mut self, line_index: usize) -> Result<Line, String> {
+        self.check_editable(line_index)?;
+
+        let removed = self.lines.remove(line_index);
+        if self.lines.is_empty() {
+            self.lines.push(Line::new());
+        }
+        self.state.cursor.stave = self.state.cursor.stave.min(self.lines.len() - 1);
+        self.recalculate_system_and_part_ids();
+        Ok(removed)
+    }
+
+    /// Swap a line with its upper (`up`) or lower neighbour
+    ///
+    /// The line keeps all of its metadata, including its system marker, so
+    /// moving it across a system boundary changes which system it belongs
+    /// to. As with deleting, locked lines stay where they are: neither the
+    /// line nor the neighbour it swaps with may be locked. Returns the line's
+    /// new index.
+    pub fn move_line(&mut self, line_index: usize, up: bool) -> Result<usize, String> {
+        self.check_editable(line_index)?;
+        let target = match up {
+            true if line_index > 0 => line_index - 1,
+            false if line_index + 1 < self.lines.len() => line_index + 1,
+            _ => return Err(format!("Line {} cannot move {}", line_index, if up { "up" } else { "down" })),
+        };
+        self.check_editable(target)?;
+
+        self.lines.swap(line_index, target);
+        self.recalculate_system_and_part_ids();
+        Ok(target)
+    }
+
+    /// Insert an empty staff above or below a line, in the line's system
+    ///
+    /// A line outside any system becomes a two-staff system with the new
+    /// staff. Inside a system, a start or end marker on the line moves to the
+    /// new staff when it is inserted at that edge. The new staff takes the
+    /// line's tonic and time signature. Returns the new line's index.
+    pub fn add_staff_to_system(&mut self, line_index: usize, below: bool) -> Result<usize, String> {
+        let line = self.lines.get(line_index).ok_or_else(|| format!("Line index {} out of bounds", line_index))?;
+        let ids = compute_system_and_part_ids(&self.lines);
+        let system_id = ids[line_index].0;
+        let grouped = ids.iter().filter(|(id, _)| *id == system_id).count() > 1;
+
+        let mut staff = Line::new();
+        staff.tonic = line.tonic.clone();
+        staff.time_signature = line.time_signature.clone();
+
+        let (line_marker, staff_marker) = match (grouped, below, line.system_marker) {
+            (false, true, _) => (SystemMarker::Start, SystemMarker::End),
+            (false, false, _) => (SystemMarker::End, SystemMarker::Start),
+            (true, true, SystemMarker::End) => (SystemMarker::None, SystemMarker::End),
+            (true, false, SystemMarker::Start) => (SystemMarker::None, SystemMarker::Start),
+            (true, _, marker) => (marker, SystemMarker::None),
+        };
+        self.lines[line_index].system_marker = line_marker;
+        staff.system_marker = staff_marker;
+
+        let index = if below { line_index + 1 } else { line_index };
+        self.lines.insert(index, staff);
+        if self.state.cursor.stave >= index {
+            self.state.cursor.stave += 1;
+        }
+        self.recalculate_system_and_part_ids();
+        Ok(index)
+    }
+
+    /// Copy one system into a standalone document
+    ///
+    /// The copy keeps the document metadata and settings and only the lines
+    /// of system `system_id` (as numbered by `compute_system_and_part_ids`).
+    /// Its ids are recalculated, so the system becomes system 1 with parts
+    /// from "P1"; editor state and undo history are not copied.
+    pub fn extract_system(&self, system_id: usize) -> Result<Document, String> {
+        let ids = compute_system_and_part_ids(&self.lines);
+        let lines: Vec<Line> = self
+            .lines
+            .iter()
+            .zip(&ids)
+            .filter(|(_, (id, _))| *id == system_id)
+            .map(|(line, _)| line.clone())
+            .collect();
+        if lines.is_empty() {
+            return Err(format!("System {} has no lines", system_id));
+        }
+
+        let mut extracted = Document { lines, state: DocumentState::new(), ..self.clone() };
+        extracted.recalculate_system_and_part_ids();
+        Ok(extracted)
+    }
+
+    /// Re-derive the beat spans of one line (see `Line::reflow_beats`)
+    pub fn reflow_beats(&mut self, line_index: usize) -> Option<&[BeatSpan]> {
+        let separator = self.beat_separator;
+        self.lines.get_mut(line_index).map(|line| line.reflow_beats_with(separator))
+    }
+
+    /// Collapse whitespace runs in one line and re-derive its beats
+    ///
+    /// Leading and trailing whitespace is removed. Inner runs shrink to the
+    /// shortest run that keeps the same beat split: one space, or two under
+    /// `BeatSeparator::DoubleSpace` where a single space would join beats.
+    /// Returns the number of cells removed.
+    pub fn compact_whitespace(&mut self, line_index: usize) -> Result<usize, String> {
+        let max_run = self.beat_separator.gap();
+        self.check_editable(line_index)?;
+        let line = &mut self.lines[line_index];
+
+        let before = line.cells.len();
+        let mut compacted: Vec<Cell> = Vec::with_capacity(before);
+        let mut run = 0;
+        for cell in line.cells.drain(..) {
+            if cell.kind == ElementKind::Whitespace {
+                run += 1;
+                if run > max_run || compacted.is_empty() {
+                    continue;
+                }
+            } else {
+                run = 0;
+            }
+            compacted.push(cell);
+        }
+        while compacted.last().is_some_and(|cell| cell.kind == ElementKind::Whitespace) {
+            compacted.pop();
+        }
+        for (index, cell) in compacted.iter_mut().enumerate() {
+            cell.col = index;
+        }
+
+        line.cells = compacted;
+        let removed = before - line.cells.len();
+        self.reflow_beats(line_index);
+        Ok(removed)
+    }
+
+    /// Respell chromatic number-system notes per `accidental_preference`
+    ///
+    /// `Auto` reads the key from the line (or document) key signature, then
+    /// the tonic. Returns the number of cells respelled.
+    pub fn apply_accidental_preference(&mut self) -> usize {
+        let mut respelled = 0;
+        for index in 0..self.lines.len() {
+            let line = &self.lines[index];
+            let key = if !line.key_signature.is_empty() {
+                Some(line.key_signature.as_str())
+            } else {
+                self.key_signature.as_deref().or(self.effective_tonic(line).map(|t| t.as_str()))
+            };
+            let prefer_flats = self.accidental_preference.prefers_flats(key_signature_fifths(key));
+
+            for cell in &mut self.lines[index].cells {
+                if cell.kind != ElementKind::PitchedElement || cell.pitch_system != Some(PitchSystem::Number) {
+                    continue;
+                }
+                let code = cell.pitch_code.clone().unwrap_or_else(|| cell.glyph.clone());
+                if let Some(spelled) = NumberSystem::respell(&code, prefer_flats) {
+                    cell.glyph = spelled.clone();
+                    cell.pitch_code = Some(spelled);
+                    respelled += 1;
+                }
+            }
+        }
+        respelled
+    }
+
+    /// Merge accidentals that were stored in their own cell into the preceding note
+    ///
+    /// Documents built by older importers may hold "1" and "#" as a note
+    /// followed by a stray text cell. Each such pair becomes a single pitched
+    /// cell ("1#") and columns are renumbered. Locked lines are left as they
+    /// are. Returns the number of merges.
+    pub fn coalesce_accidentals(&mut self) -> usize {
+        self.lines.iter_mut().filter(|line| !line.locked).map(|line| line.coalesce_accidentals()).sum()
+    }
+
+    /// Repair structural leftovers from documents saved by older versions
+    ///
+    /// Empty cells are dropped, column numbers are made sequential, and a run
+    /// of dashes before a line's first note becomes an explicit rest (its
+    /// first dash gets the rest flag, so the run keeps its length). Returns
+    /// the number of repairs.
+    pub fn repair_structure(&mut self) -> usize {
+        let mut repaired = 0;
+        for index in 0..self.lines.len() {
+            let fixes = self.lines[index].repair_structure();
+            if fixes > 0 {
+                self.reflow_beats(index);
+                repaired += fixes;
+            }
+        }
+        repaired
+    }
+
+    /// Renumber cell columns so `col` equals the cell index on every line
+    ///
+    /// Selection and caret code assume this invariant; imported documents
+    /// may break it. Beats are re-derived on changed lines. Returns the
+    /// number of lines changed.
+    pub fn quantize_columns(&mut self) -> usize {
+        let mut changed = 0;
+        for index in 0..self.lines.len() {
+            if self.lines[index].quantize_columns() {
+                self.reflow_beats(index);
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Copy document-level pitch system, tonic and key signature into every line
+    ///
+    /// Each line's pitch system is set to its effective pitch system, which
+    /// also replaces unknown codes. Empty tonics and key signatures are filled
+    /// from the document. Tabla lines have no pitches, so any tonic or key
+    /// signature on them is cleared instead. Locked lines are left as they
+    /// are. Returns the number of fields changed.
+    pub fn normalize_line_metadata(&mut self) -> usize {
+        let mut changed = 0;
+        for index in 0..self.lines.len() {
+            if self.lines[index].locked {
+                continue;
+            }
+            let pitch_system = self.effective_pitch_system(&self.lines[index]);
+            let line = &mut self.lines[index];
+
+            if line.pitch_system != pitch_system as u8 {
+                line.pitch_system = pitch_system as u8;
+                changed += 1;
+            }
+
+            if pitch_system == PitchSystem::Tabla {
+                for field in [&mut line.tonic, &mut line.key_signature] {
+                    if !field.is_empty() {
+                        field.clear();
+                        changed += 1;
+                    }
+                }
+                continue;
+            }
+
+            for (field, inherited) in [(&mut line.tonic, &self.tonic), (&mut line.key_signature, &self.key_signature)] {
+                if let Some(value) = inherited.as_ref().filter(|value| field.is_empty() && !value.is_empty()) {
+                    *field = value.clone();
+                    changed += 1;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Carry remotely replaced lines into every undo and redo snapshot
+    ///
+    /// Undoing a local edit then restores the local lines around the remote
+    /// edit instead of reverting it. Snapshots are whole documents, so line
+    /// indices only carry over while a snapshot has the current number of
+    /// lines: undo steps older than the newest one recorded on another line
+    /// layout (an added or deleted line) are dropped, as are redo steps from
+    /// the first such one. Steps that only changed the replaced lines have
+    /// nothing left to undo and are dropped as well.
+    fn rebase_history(&mut self, dirty_indices: &[usize]) {
+        let line_count = self.lines.len();
+        let fits = |action: &DocumentAction| {
+            [&action.previous_state, &action.new_state]
+                .iter()
+                .all(|snapshot| snapshot.as_ref().is_some_and(|snapshot| snapshot.lines.len() == line_count))
+        };
+
+        let mut history = std::mem::take(&mut self.state.history);
+        let mut index = self.state.history_index.min(history.len());
+        if let Some(first_unfit) = history.iter().skip(index).position(|action| !fits(action)) {
+            history.truncate(index + first_unfit);
+        }
+        if let Some(last_unfit) = history.iter().take(index).rposition(|action| !fits(action)) {
+            history.drain(..=last_unfit);
+            index -= last_unfit + 1;
+        }
+
+        for action in history.iter_mut() {
+            for snapshot in [&mut action.previous_state, &mut action.new_state].into_iter().flatten() {
+                for &line_index in dirty_indices {
+                    let line = &mut snapshot.lines[line_index];
+                    line.cells = self.lines[line_index].cells.clone();
+                    line.reflow_beats_with(snapshot.beat_separator);
+                }
+            }
+        }
+
+        let undo_steps = index;
+        let mut kept = VecDeque::with_capacity(history.len());
+        for (position, action) in history.into_iter().enumerate() {
+            if action.previous_state != action.new_state {
+                kept.push_back(action);
+            } else if position < undo_steps {
+                index -= 1;
+            }
+        }
+        self.state.history = kept;
+        self.state.history_index = index;
+    }
+
+    /// Effective pitch system of every line, in line order
+    pub fn effective_pitch_systems(&self) -> Vec<PitchSystem> {
+        self.lines.iter().map(|line| self.effective_pitch_system(line)).collect()
+    }
+
+    /// Apply an edit made by another client
+    ///
+    /// Each dirty line's cells replace the local ones. Every remote cell is
+    /// re-parsed from its glyph in the line's pitch system, keeping its
+    /// annotations, and columns and beats are re-derived, since the sender's
+    /// derived fields and layout are not trusted. The patch is validated
+    /// before anything changes. Remote edits do not enter the local undo
+    /// history, but the history is kept: see `rebase_history`. The sender's
+    /// cursor is kept as `state.remote_cursor`, and the local cursor is
+    /// clamped to its line. Returns the number of lines replaced.
+    pub fn apply_remote_diff(&mut self, diff: RemoteDiff) -> Result<usize, String> {
+        if let Some(dirty) = diff.dirty_lines.iter().find(|dirty| dirty.line_index >= self.lines.len()) {
+            return Err(format!("Line index {} out of bounds", dirty.line_index));
+        }
+
+        let separator = self.beat_separator;
+        let replaced = diff.dirty_lines.len();
+        let dirty_indices: Vec<usize> = diff.dirty_lines.iter().map(|dirty| dirty.line_index).collect();
+        for dirty in diff.dirty_lines {
+            let pitch_system = self.effective_pitch_system(&self.lines[dirty.line_index]);
+            let line = &mut self.lines[dirty.line_index];
+            line.cells = dirty
+                .cells
+                .iter()
+                .map(|remote| {
+                    let mut cell = crate::parse::grammar::parse(&remote.glyph, pitch_system, remote.col);
+                    cell.set_head(remote.is_head());
+                    cell.set_rest(remote.is_rest());
+                    cell.copy_annotations_from(remote);
+                    cell
+                })
+                .collect();
+            line.quantize_columns();
+            line.reflow_beats_with(separator);
+        }
+
+        self.rebase_history(&dirty_indices);
+
+        let cursor = &mut self.state.cursor;
+        cursor.stave = cursor.stave.min(self.lines.len().saturating_sub(1));
+        cursor.column = cursor.column.min(self.lines.get(cursor.stave).map_or(0, |line| line.cells.len()));
+        self.state.remote_cursor = diff.cursor;
+        Ok(replaced)
+    }
+
     /// Get the effective tonic for a line
     pub fn effective_tonic<'a>(&'a self, line: &'a Line) -> Option<&'a String> {
         if !line.tonic.is_empty() {
@@ -433,6 +1211,60 @@ impl Document {
     }
 }
 
+/// Derive `(system_id, part_id)` for each line from the system markers
+///
+/// System ids start at 1; part ids are "P1", "P2", ... in line order.
+/// This is a single forward pass over the markers alone, so the result never
+/// depends on ids left over from before an edit and recomputing is a no-op:
+/// - `Start` opens a new system, even inside an unclosed group
+/// - `End` closes the current group; outside a group it is a line of its own
+/// - any other line joins the open group or forms its own system
+pub fn compute_system_and_part_ids(lines: &[Line]) -> Vec<(usize, String)> {
+    let mut ids = Vec::with_capacity(lines.len());
+    let mut system_id = 0;
+    let mut in_group = false;
+
+    for (index, line) in lines.iter().enumerate() {
+        match line.system_marker {
+            SystemMarker::Start => {
+                system_id += 1;
+                in_group = true;
+            }
+            _ if !in_group => system_id += 1,
+            _ => {}
+        }
+
+        ids.push((system_id, format!("P{}", index + 1)));
+
+        if line.system_marker == SystemMarker::End {
+            in_group = false;
+        }
+    }
+
+    ids
+}
+
+/// One line's new cells in a `RemoteDiff`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DirtyLine {
+    /// Index of the replaced line
+    pub line_index: usize,
+
+    /// The line's complete cell array after the remote edit
+    pub cells: Vec<Cell>,
+}
+
+/// Patch describing an edit made by another client
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct RemoteDiff {
+    /// Lines whose cells changed
+    pub dirty_lines: Vec<DirtyLine>,
+
+    /// The sending client's cursor, if it shared one
+    #[serde(default)]
+    pub cursor: Option<CursorPosition>,
+}
+
 /// Application state including cursor position, selection, and focus information
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
 pub struct DocumentState {
@@ -454,6 +1286,10 @@ pub struct DocumentState {
 
     /// Performance and rendering state
     pub render_state: RenderState,
+
+    /// Cursor of the collaborator whose edit was applied last
+    #[serde(default)]
+    pub remote_cursor: Option<CursorPosition>,
 }
 
 impl DocumentState {
@@ -467,6 +1303,7 @@ impl DocumentState {
             history: VecDeque::new(),
             history_index: 0,
             render_state: RenderState::new(),
+            remote_cursor: None,
         }
     }
 
@@ -559,6 +1396,93 @@ pub enum ActionType {
     ApplyOctave,
     SetTala,
     SetMetadata,
+    EditSource,
+    SwapRanges,
+    MergeNotes,
+    DuplicateLine,
+    FinalizeBarline,
+    ClearAnnotations,
+    Autofix,
+    FillMeasure,
+    AddLine,
+    DeleteLine,
+    MoveLine,
+    PasteMeasure,
+    CompactWhitespace,
+    ReplaceSelection,
+    InsertBarline,
+    Respell,
+}
+
+/// Default number of auto-save snapshots kept
+pub const DEFAULT_AUTO_SNAPSHOT_CAPACITY: usize = 10;
+
+/// Bounded ring of serialized document snapshots
+///
+/// Unlike the undo history this stores whole documents as JSON, newest last.
+/// When full, pushing a snapshot evicts the oldest one.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AutoSnapshots {
+    /// Snapshot JSON blobs, oldest first
+    pub snapshots: VecDeque<String>,
+
+    /// Maximum number of snapshots retained
+    pub capacity: usize,
+}
+
+impl AutoSnapshots {
+    /// Create an empty ring with the default capacity
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_AUTO_SNAPSHOT_CAPACITY)
+    }
+
+    /// Create an empty ring holding at most `capacity` snapshots
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Change the capacity, evicting the oldest snapshots if needed
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        while self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Serialize a document and store it as the newest snapshot
+    pub fn push(&mut self, document: &Document) -> Result<(), serde_json::Error> {
+        let json = serde_json::to_string(document)?;
+        self.snapshots.push_back(json);
+        while self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Restore a snapshot; index 0 is the most recent
+    pub fn get(&self, index: usize) -> Option<Result<Document, serde_json::Error>> {
+        let position = self.snapshots.len().checked_sub(index + 1)?;
+        Some(serde_json::from_str(&self.snapshots[position]))
+    }
+
+    /// Number of snapshots currently retained
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    /// Check if no snapshots are retained
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+impl Default for AutoSnapshots {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Rendering state information
@@ -664,6 +1588,10 @@ pub struct SelectionManager {
 
     /// Whether selection is active
     pub active: bool,
+
+    /// Column that up/down movement aims for until the next horizontal move
+    #[serde(default)]
+    pub desired_col: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
@@ -687,6 +1615,7 @@ impl SelectionManager {
             anchor: None,
             mode: SelectionMode::Normal,
             active: false,
+            desired_col: None,
         }
     }
 
@@ -941,6 +1870,19 @@ mod chrono {
 mod tests {
     use super::*;
     
+    #[test]
+    fn test_set_lyric_verses() {
+        let mut line = Line::new();
+        line.set_lyric_verse(0, "first");
+        line.set_lyric_verse(2, "third");
+
+        assert_eq!(line.lyrics, "first");
+        assert_eq!(line.verses(), vec!["first", "", "third"]);
+
+        line.set_lyric_verse(2, "");
+        assert_eq!(line.verses(), vec!["first"]);
+    }
+
     #[test]
     fn test_line_serialization_includes_null_fields() {
         let line = Line::new();
@@ -955,4 +1897,503 @@ mod tests {
         assert!(json.contains("\"pitch_system\""), "pitch_system field should be present");
         assert!(json.contains("\"key_signature\""), "key_signature field should be present");
     }
+
+    fn split_cell(glyph: &str, kind: ElementKind, col: usize) -> Cell {
+        let mut cell = Cell::new(glyph.to_string(), kind, col);
+        if kind == ElementKind::PitchedElement {
+            cell.pitch_code = Some(glyph.to_string());
+            cell.pitch_system = Some(PitchSystem::Number);
+        }
+        cell
+    }
+
+    #[test]
+    fn test_coalesce_split_accidental() {
+        let mut document = Document::new();
+        let mut line = Line::new();
+        line.cells = vec![
+            split_cell("1", ElementKind::PitchedElement, 0),
+            split_cell("#", ElementKind::Text, 1),
+            split_cell(" ", ElementKind::Whitespace, 2),
+            split_cell("2", ElementKind::PitchedElement, 3),
+        ];
+        line.cells[0].octave = 1;
+        document.add_line(line);
+
+        assert_eq!(document.coalesce_accidentals(), 1);
+
+        let cells = &document.lines[0].cells;
+        assert_eq!(cells.len(), 3);
+        assert_eq!(cells[0].glyph, "1#");
+        assert_eq!(cells[0].pitch_code.as_deref(), Some("1#"));
+        assert_eq!(cells[0].kind, ElementKind::PitchedElement);
+        assert_eq!(cells[0].octave, 1);
+        assert_eq!(cells.iter().map(|c| c.col).collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_coalesce_leaves_invalid_accidentals() {
+        let mut line = Line::new();
+        line.cells = vec![
+            split_cell("1#", ElementKind::PitchedElement, 0),
+            split_cell("b", ElementKind::Text, 1),
+            split_cell("#", ElementKind::Text, 2),
+        ];
+
+        assert_eq!(line.coalesce_accidentals(), 0);
+        assert_eq!(line.cells.len(), 3);
+    }
+
+    #[test]
+    fn test_repair_structure_turns_orphan_dashes_into_a_rest() {
+        let mut document = Document::new();
+        let mut line = Line::new();
+        line.cells = crate::parse::grammar::parse_cells("--1", PitchSystem::Number);
+        line.cells.insert(1, split_cell("", ElementKind::Text, 1));
+        line.cells[3].col = 7;
+        document.add_line(line);
+
+        assert_eq!(document.repair_structure(), 3);
+
+        let cells = &document.lines[0].cells;
+        assert_eq!(cells.iter().map(|c| c.glyph.as_str()).collect::<Vec<_>>(), vec!["-", "-", "1"]);
+        assert!(cells[0].is_rest() && !cells[1].is_rest());
+        assert_eq!(cells.iter().map(|c| c.col).collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        let events: Vec<_> = crate::ir::build_export_measures_from_line(&document.lines[0])[0].events().cloned().collect();
+        assert_eq!(events.len(), 2);
+        assert!(events[0].is_rest());
+        assert_eq!(events[0].subdivisions, 2);
+
+        assert_eq!(document.repair_structure(), 0, "repairing twice changes nothing");
+    }
+
+    #[test]
+    fn test_effective_pitch_systems_resolve_inherited_lines() {
+        let mut document = Document::new();
+        document.pitch_system = Some(PitchSystem::Sargam);
+        document.add_line(Line::new());
+        let mut western = Line::new();
+        western.pitch_system = PitchSystem::Western as u8;
+        document.add_line(western);
+
+        assert_eq!(document.effective_pitch_systems(), vec![PitchSystem::Sargam, PitchSystem::Western]);
+
+        document.pitch_system = None;
+        assert_eq!(document.effective_pitch_systems()[0], PitchSystem::Number);
+    }
+
+    #[test]
+    fn test_quantize_columns_makes_a_clean_sequence() {
+        let mut document = Document::new();
+        let mut line = Line::new();
+        line.cells = crate::parse::grammar::parse_cells("1 2 3", PitchSystem::Number);
+        for (cell, col) in line.cells.iter_mut().zip([0, 4, 4, 9, 2]) {
+            cell.col = col;
+        }
+        document.add_line(line);
+        let mut clean = Line::new();
+        clean.cells = crate::parse::grammar::parse_cells("5", PitchSystem::Number);
+        document.add_line(clean);
+
+        assert_eq!(document.quantize_columns(), 1);
+        let cols: Vec<usize> = document.lines[0].cells.iter().map(|c| c.col).collect();
+        assert_eq!(cols, vec![0, 1, 2, 3, 4]);
+        assert_eq!(document.quantize_columns(), 0);
+    }
+
+    #[test]
+    fn test_normalize_line_metadata_inherits_document_values() {
+        let mut document = Document::new();
+        document.pitch_system = Some(PitchSystem::Sargam);
+        document.tonic = Some("D".to_string());
+        let mut line = Line::new();
+        line.cells = crate::parse::grammar::parse_cells("S r", PitchSystem::Sargam);
+        document.add_line(line);
+        let mut tabla = Line::new();
+        tabla.pitch_system = PitchSystem::Tabla as u8;
+        tabla.tonic = "C".to_string();
+        document.add_line(tabla);
+
+        assert_eq!(document.normalize_line_metadata(), 3);
+        assert_eq!(document.lines[0].pitch_system, PitchSystem::Sargam as u8);
+        assert_eq!(document.lines[0].tonic, "D");
+        assert!(document.lines[1].tonic.is_empty(), "tabla lines have no tonic");
+        assert_eq!(document.normalize_line_metadata(), 0, "normalizing twice changes nothing");
+
+        // The line keeps rendering as sargam in D after the document changes
+        document.pitch_system = Some(PitchSystem::Number);
+        document.tonic = None;
+        let xml = crate::renderers::musicxml::MusicXMLExport::export_document(&document);
+        assert!(xml.contains("<step>D</step>"));
+        assert!(xml.contains("<step>E</step>\n          <alter>-1</alter>"));
+    }
+
+    #[test]
+    fn test_document_fixes_skip_locked_lines() {
+        let mut document = Document::new();
+        document.tonic = Some("D".to_string());
+        let mut line = Line::new();
+        line.cells = crate::parse::grammar::parse_cells("1", PitchSystem::Number);
+        line.cells.push(Cell::new("#".to_string(), ElementKind::Text, 1));
+        line.locked = true;
+        document.add_line(line);
+        let before = document.lines[0].clone();
+
+        assert_eq!(document.normalize_line_metadata(), 0);
+        assert_eq!(document.coalesce_accidentals(), 0);
+        assert_eq!(document.lines[0], before);
+    }
+
+    #[test]
+    fn test_accidental_preference_respells_number_pitches() {
+        let mut document = Document::new();
+        let mut line = Line::new();
+        line.cells = vec![split_cell("1#", ElementKind::PitchedElement, 0)];
+        document.add_line(line);
+
+        document.accidental_preference = AccidentalPreference::Flats;
+        assert_eq!(document.apply_accidental_preference(), 1);
+        assert_eq!(document.lines[0].cells[0].glyph, "2b");
+
+        document.accidental_preference = AccidentalPreference::Sharps;
+        document.apply_accidental_preference();
+        assert_eq!(document.lines[0].cells[0].glyph, "1#");
+        assert_eq!(document.lines[0].cells[0].pitch_code.as_deref(), Some("1#"));
+
+        // Auto follows a flat key signature
+        document.accidental_preference = AccidentalPreference::Auto;
+        document.key_signature = Some("F".to_string());
+        document.apply_accidental_preference();
+        assert_eq!(document.lines[0].cells[0].glyph, "2b");
+    }
+
+    #[test]
+    fn test_reflow_beats_after_inserting_space() {
+        let mut document = Document::new();
+        let mut line = Line::new();
+        line.cells = ["1", "2", "3"]
+            .iter()
+            .enumerate()
+            .map(|(col, glyph)| split_cell(glyph, ElementKind::PitchedElement, col))
+            .collect();
+        document.add_line(line);
+
+        let spans: Vec<(usize, usize)> = document.reflow_beats(0).unwrap().iter().map(|b| (b.start, b.end)).collect();
+        assert_eq!(spans, vec![(0, 2)]);
+
+        document.lines[0].cells.insert(1, split_cell(" ", ElementKind::Whitespace, 1));
+        let spans: Vec<(usize, usize)> = document.reflow_beats(0).unwrap().iter().map(|b| (b.start, b.end)).collect();
+        assert_eq!(spans, vec![(0, 0), (2, 3)]);
+        assert!(document.reflow_beats(1).is_none());
+    }
+
+    #[test]
+    fn test_to_source_char_round_trips() {
+        let mut cells = crate::parse::grammar::parse_cells("1# || -", PitchSystem::Number);
+        cells[0].octave = 1;
+
+        let source: String = cells.iter().map(|c| c.to_source_char()).collect();
+        assert_eq!(source, "1# || -");
+
+        let reparsed = crate::parse::grammar::parse_cells(&cells[0].to_source_char(), PitchSystem::Number);
+        assert_eq!(reparsed[0].pitch_code, cells[0].pitch_code);
+        assert_eq!(cells[0].octave, 1, "octave is kept on the cell, not in the text");
+    }
+
+    #[test]
+    fn test_system_markers_group_lines() {
+        let mut document = Document::new();
+        for marker in [SystemMarker::None, SystemMarker::Start, SystemMarker::None, SystemMarker::End, SystemMarker::None] {
+            let mut line = Line::new();
+            line.system_marker = marker;
+            document.add_line(line);
+        }
+
+        document.recalculate_system_and_part_ids();
+
+        let system_ids: Vec<usize> = document.lines.iter().map(|l| l.system_id).collect();
+        assert_eq!(system_ids, vec![1, 2, 2, 2, 3]);
+        assert_eq!(document.lines[3].part_id, "P4");
+    }
+
+    #[test]
+    fn test_system_ids_ignore_stale_ids_and_are_idempotent() {
+        use SystemMarker::{End, None, Start};
+        let markers = [Start, None, Start, End, End, None, Start];
+        let mut document = Document::new();
+        for (index, marker) in markers.into_iter().enumerate() {
+            let mut line = Line::new();
+            line.system_marker = marker;
+            line.system_id = 40 - index;
+            line.part_id = "stale".to_string();
+            document.add_line(line);
+        }
+
+        document.recalculate_system_and_part_ids();
+        let first: Vec<(usize, String)> = document.lines.iter().map(|l| (l.system_id, l.part_id.clone())).collect();
+        document.recalculate_system_and_part_ids();
+        let second: Vec<(usize, String)> = document.lines.iter().map(|l| (l.system_id, l.part_id.clone())).collect();
+
+        assert_eq!(first, second);
+        let system_ids: Vec<usize> = first.iter().map(|(id, _)| *id).collect();
+        // A nested start opens system 2, a stray end stands alone, an unclosed start runs to the end
+        assert_eq!(system_ids, vec![1, 1, 2, 2, 3, 4, 5]);
+        assert_eq!(first[6].1, "P7");
+    }
+
+    fn document_with_text(text: &str) -> Document {
+        let mut document = Document::new();
+        let mut line = Line::new();
+        line.cells = crate::parse::grammar::parse_cells(text, PitchSystem::Number);
+        document.add_line(line);
+        document
+    }
+
+    #[test]
+    fn test_compact_whitespace_collapses_runs() {
+        let mut document = document_with_text("  1   2 ");
+
+        assert_eq!(document.compact_whitespace(0), Ok(5));
+        let text: String = document.lines[0].cells.iter().map(|c| c.glyph.as_str()).collect();
+        assert_eq!(text, "1 2");
+        assert_eq!(document.lines[0].cells[2].col, 2);
+        assert_eq!(document.lines[0].beats.len(), 2);
+        assert!(document.compact_whitespace(1).is_err());
+    }
+
+    #[test]
+    fn test_compact_whitespace_keeps_double_space_beats() {
+        let mut document = document_with_text("1 2    3 4");
+        document.beat_separator = BeatSeparator::DoubleSpace;
+
+        assert_eq!(document.compact_whitespace(0), Ok(2));
+        let text: String = document.lines[0].cells.iter().map(|c| c.glyph.as_str()).collect();
+        assert_eq!(text, "1 2  3 4");
+        let spans: Vec<(usize, usize)> = document.lines[0].beats.iter().map(|b| (b.start, b.end)).collect();
+        assert_eq!(spans, vec![(0, 2), (5, 7)]);
+    }
+
+    fn labelled_document(labels: &[&str]) -> Document {
+        let mut document = Document::new();
+        for label in labels {
+            let mut line = Line::new();
+            line.label = label.to_string();
+            document.add_line(line);
+        }
+        document
+    }
+
+    #[test]
+    fn test_delete_middle_and_last_line() {
+        let mut document = labelled_document(&["a", "b", "c"]);
+
+        assert_eq!(document.delete_line(1).unwrap().label, "b");
+        let labels: Vec<&str> = document.lines.iter().map(|l| l.label.as_str()).collect();
+        assert_eq!(labels, vec!["a", "c"]);
+        assert_eq!(document.lines[1].part_id, "P2");
+
+        let mut single = labelled_document(&["only"]);
+        single.delete_line(0).unwrap();
+        assert_eq!(single.lines.len(), 1);
+        assert!(single.lines[0].label.is_empty());
+        assert!(single.delete_line(3).is_err());
+    }
+
+    #[test]
+    fn test_move_line_across_system_boundary() {
+        let mut document = labelled_document(&["a", "b", "c"]);
+        document.lines[0].system_marker = SystemMarker::Start;
+        document.lines[1].system_marker = SystemMarker::End;
+        document.lines[2].tala = "1+2".to_string();
+        document.recalculate_system_and_part_ids();
+        assert_eq!(document.lines[2].system_id, 2);
+
+        assert_eq!(document.move_line(2, true), Ok(1));
+
+        let labels: Vec<&str> = document.lines.iter().map(|l| l.label.as_str()).collect();
+        assert_eq!(labels, vec!["a", "c", "b"]);
+        assert_eq!(document.lines[1].tala, "1+2", "metadata moves with the line");
+        let system_ids: Vec<usize> = document.lines.iter().map(|l| l.system_id).collect();
+        assert_eq!(system_ids, vec![1, 1, 1]);
+        assert!(document.move_line(0, true).is_err());
+
+        document.lines[2].locked = true;
+        assert_eq!(document.move_line(2, true), Err("Line 3 is locked".to_string()));
+        let displaced = document.move_line(1, false);
+        assert_eq!(displaced, Err("Line 3 is locked".to_string()), "a locked neighbour is not displaced");
+    }
+
+    #[test]
+    fn test_add_staff_below_a_melody_line() {
+        let mut document = labelled_document(&["intro", "melody", "coda"]);
+        document.lines[1].tonic = "D".to_string();
+        document.lines[1].time_signature = "3/4".to_string();
+
+        assert_eq!(document.add_staff_to_system(1, true), Ok(2));
+
+        let (melody, harmony) = (&document.lines[1], &document.lines[2]);
+        assert_eq!(melody.system_id, harmony.system_id);
+        assert_ne!(melody.part_id, harmony.part_id);
+        assert_eq!((harmony.tonic.as_str(), harmony.time_signature.as_str()), ("D", "3/4"));
+        assert!(harmony.cells.is_empty());
+        let system_ids: Vec<usize> = document.lines.iter().map(|l| l.system_id).collect();
+        assert_eq!(system_ids, vec![1, 2, 2, 3], "neighbouring lines stay in their own systems");
+    }
+
+    #[test]
+    fn test_add_staff_at_the_edges_of_a_system() {
+        let mut document = labelled_document(&["a", "b"]);
+        document.lines[0].system_marker = SystemMarker::Start;
+        document.lines[1].system_marker = SystemMarker::End;
+
+        assert_eq!(document.add_staff_to_system(1, true), Ok(2));
+        assert_eq!(document.add_staff_to_system(0, false), Ok(0));
+
+        let markers: Vec<SystemMarker> = document.lines.iter().map(|l| l.system_marker).collect();
+        use SystemMarker::{End, None, Start};
+        assert_eq!(markers, vec![Start, None, None, End]);
+        assert!(document.lines.iter().all(|l| l.system_id == 1));
+        assert!(document.add_staff_to_system(9, true).is_err());
+    }
+
+    #[test]
+    fn test_vertical_movement_restores_desired_column() {
+        let mut document = Document::new();
+        for text in ["1 2 3 4", "5", "6 7 1 2 3"] {
+            let mut line = Line::new();
+            line.cells = crate::parse::grammar::parse_cells(text, PitchSystem::Number);
+            document.add_line(line);
+        }
+
+        document.move_cursor_to(CursorPosition::at(0, 2));
+        document.set_desired_column(6);
+        assert_eq!(document.move_cursor_vertically(true), CursorPosition::at(1, 1), "clamped to the short line");
+        assert_eq!(document.move_cursor_vertically(true), CursorPosition::at(2, 6));
+        assert_eq!(document.move_cursor_vertically(true), CursorPosition::at(2, 6), "no line below");
+        assert_eq!(document.move_cursor_vertically(false), CursorPosition::at(1, 1));
+
+        // A horizontal move forgets the desired column
+        document.move_cursor_to(CursorPosition::at(1, 0));
+        assert_eq!(document.move_cursor_vertically(false), CursorPosition::at(0, 0));
+    }
+
+    #[test]
+    fn test_select_to_document_boundaries() {
+        let mut document = Document::new();
+        for text in ["1 2", "3 4 5", "6"] {
+            let mut line = Line::new();
+            line.cells = crate::parse::grammar::parse_cells(text, PitchSystem::Number);
+            document.add_line(line);
+        }
+
+        let to_end = document.select_to_document_boundary(CursorPosition::at(1, 2), true);
+        assert_eq!((to_end.start, to_end.end), (CursorPosition::at(1, 2), CursorPosition::at(2, 1)));
+        assert_eq!(document.state.cursor, CursorPosition::at(2, 1));
+
+        let to_start = document.select_to_document_boundary(CursorPosition::at(1, 2), false);
+        assert_eq!((to_start.start, to_start.end), (CursorPosition::at(0, 0), CursorPosition::at(1, 2)));
+
+        let empty = document.select_to_document_boundary(CursorPosition::at(0, 0), false);
+        assert_eq!(empty.start, empty.end);
+    }
+
+    #[test]
+    fn test_apply_remote_diff_keeps_local_undo() {
+        let cells = |text: &str| crate::parse::grammar::parse_cells(text, PitchSystem::Number);
+        let source = |line: &Line| line.cells.iter().map(|cell| cell.to_source_char()).collect::<String>();
+        let mut document = Document::new();
+        for text in ["1 2", "5"] {
+            let mut line = Line::new();
+            line.cells = cells(text);
+            document.add_line(line);
+        }
+
+        // A local edit of line 2, recorded for undo
+        let mut before = document.clone();
+        before.state = DocumentState::new();
+        document.lines[1].cells = cells("5 6");
+        let mut after = document.clone();
+        after.state = DocumentState::new();
+        document.state.add_action(DocumentAction {
+            action_type: ActionType::EditSource,
+            description: "Edit line 2".to_string(),
+            previous_state: Some(before),
+            new_state: Some(after),
+            timestamp: String::new(),
+        });
+        document.state.cursor = CursorPosition::at(0, 3);
+
+        // The sender's derived fields are stale: re-parsing restores them
+        let mut remote = cells("3 4 5");
+        remote.iter_mut().for_each(|cell| cell.col = 40);
+        remote[0].pitch_code = Some("7".to_string());
+        remote[2].octave = 1;
+        let diff = RemoteDiff {
+            dirty_lines: vec![DirtyLine { line_index: 0, cells: remote }],
+            cursor: Some(CursorPosition::at(0, 5)),
+        };
+        assert_eq!(document.apply_remote_diff(diff), Ok(1));
+
+        assert_eq!(source(&document.lines[0]), "3 4 5");
+        assert_eq!(document.lines[0].cells[0].pitch_code.as_deref(), Some("3"));
+        assert_eq!(document.lines[0].cells[2].octave, 1, "annotations survive the re-parse");
+        assert_eq!(document.lines[0].cells.iter().map(|cell| cell.col).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(document.lines[0].beats.len(), 3);
+        assert_eq!(source(&document.lines[1]), "5 6");
+        assert_eq!(document.state.remote_cursor, Some(CursorPosition::at(0, 5)));
+        assert_eq!(document.state.history.len(), 1, "the remote edit is not a local step");
+
+        // Undoing the local edit restores line 2 and keeps the remote line 1
+        let mut undone = document.clone();
+        crate::api::undo(&mut undone).unwrap();
+        assert_eq!(source(&undone.lines[0]), "3 4 5");
+        assert_eq!(source(&undone.lines[1]), "5");
+
+        // A local step that only touched a remotely replaced line is dropped
+        let overwritten = RemoteDiff {
+            dirty_lines: vec![DirtyLine { line_index: 1, cells: cells("7") }],
+            cursor: None,
+        };
+        assert_eq!(document.apply_remote_diff(overwritten), Ok(1));
+        assert!(!document.state.can_undo());
+
+        let out_of_range = RemoteDiff {
+            dirty_lines: vec![DirtyLine { line_index: 7, cells: cells("1") }],
+            cursor: None,
+        };
+        assert!(document.apply_remote_diff(out_of_range).is_err());
+        assert_eq!(source(&document.lines[0]), "3 4 5");
+
+        let mut empty = Document::new();
+        let no_lines = RemoteDiff { dirty_lines: Vec::new(), cursor: None };
+        assert_eq!(empty.apply_remote_diff(no_lines), Ok(0));
+    }
+
+    #[test]
+    fn test_extract_system() {
+        let mut document = Document::new();
+        document.title = Some("Suite".to_string());
+        for (text, marker) in [("1", SystemMarker::Start), ("2", SystemMarker::End), ("3", SystemMarker::Start), ("4", SystemMarker::End)] {
+            let mut line = Line::new();
+            line.cells = crate::parse::grammar::parse_cells(text, PitchSystem::Number);
+            line.system_marker = marker;
+            document.add_line(line);
+        }
+        document.recalculate_system_and_part_ids();
+
+        let second = document.extract_system(2).unwrap();
+        let glyphs: Vec<&str> = second.lines.iter().map(|line| line.cells[0].glyph.as_str()).collect();
+        assert_eq!(glyphs, vec!["3", "4"]);
+        assert_eq!(second.title.as_deref(), Some("Suite"));
+        assert!(second.lines.iter().all(|line| line.system_id == 1));
+        assert_eq!((second.lines[0].part_id.as_str(), second.lines[1].part_id.as_str()), ("P1", "P2"));
+
+        let json = serde_json::to_string(&second).unwrap();
+        let loaded: Document = serde_json::from_str(&json).unwrap();
+        assert!(loaded.validate().is_ok());
+        assert_eq!(loaded.lines.len(), 2);
+
+        assert!(document.extract_system(3).is_err());
+    }
 }
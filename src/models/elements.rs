@@ -367,6 +367,168 @@ impl Default for SlurIndicator {
     }
 }
 
+/// Marker grouping consecutive lines into one multi-staff system
+///
+/// Lines from a `Start` line through the next `End` line (inclusive) share a
+/// system; unmarked lines outside a group are systems of their own.
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+#[derive(serde_repr::Serialize_repr, serde_repr::Deserialize_repr)]
+pub enum SystemMarker {
+    /// No marker
+    #[default]
+    None = 0,
+
+    /// This line starts a multi-staff system
+    Start = 1,
+
+    /// This line ends a multi-staff system
+    End = 2,
+}
+
+impl SystemMarker {
+    /// Parse a marker name ("start", "end", or anything else for none)
+    pub fn parse(text: &str) -> Self {
+        match text.trim().to_lowercase().as_str() {
+            "start" => SystemMarker::Start,
+            "end" => SystemMarker::End,
+            _ => SystemMarker::None,
+        }
+    }
+}
+
+/// Whitespace needed to separate one beat from the next
+///
+/// With `SingleSpace` any whitespace ends a beat, so "1 2" is two beats.
+/// With `DoubleSpace` a lone space between notes stays inside the beat and
+/// only two or more spaces (or any other separator) end it.
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+#[derive(serde_repr::Serialize_repr, serde_repr::Deserialize_repr)]
+pub enum BeatSeparator {
+    /// Any whitespace separates beats
+    #[default]
+    SingleSpace = 0,
+
+    /// Only runs of two or more spaces separate beats
+    DoubleSpace = 1,
+}
+
+impl BeatSeparator {
+    /// Parse a policy name ("double" for double-space, anything else for single)
+    pub fn parse(text: &str) -> Self {
+        match text.trim().to_lowercase().as_str() {
+            "double" | "double-space" | "2" => BeatSeparator::DoubleSpace,
+            _ => BeatSeparator::SingleSpace,
+        }
+    }
+
+    /// Shortest whitespace run that separates two beats
+    pub fn gap(self) -> usize {
+        match self {
+            BeatSeparator::SingleSpace => 1,
+            BeatSeparator::DoubleSpace => 2,
+        }
+    }
+}
+
+/// How octave displacement is written next to a note
+///
+/// Only affects display and text export; `Cell::octave` stays the source
+/// of truth.
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[derive(serde_repr::Serialize_repr, serde_repr::Deserialize_repr)]
+pub enum OctaveStyle {
+    /// One dot above (upper) or below (lower) per octave, as in sargam
+    #[default]
+    Dots = 0,
+
+    /// ABC-style `'` (upper) and `,` (lower) after the note
+    Abc = 1,
+
+    /// Superscript (upper) or subscript (lower) octave count
+    Numbers = 2,
+}
+
+impl OctaveStyle {
+    /// Parse a style name ("abc", "numbers"; anything else is dots)
+    pub fn parse(text: &str) -> Self {
+        match text.trim().to_lowercase().as_str() {
+            "abc" | "ticks" | "commas" => OctaveStyle::Abc,
+            "numbers" | "number" | "digits" => OctaveStyle::Numbers,
+            _ => OctaveStyle::Dots,
+        }
+    }
+
+    /// Text written after a note's glyph for `octave`
+    ///
+    /// Dots are combining characters so they sit on the glyph itself.
+    /// Octave 0 has no marker in any style.
+    pub fn text_marker(self, octave: i8) -> String {
+        const SUPERSCRIPT: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+        const SUBSCRIPT: [char; 10] = ['₀', '₁', '₂', '₃', '₄', '₅', '₆', '₇', '₈', '₉'];
+
+        let count = octave.unsigned_abs() as usize;
+        if count == 0 {
+            return String::new();
+        }
+        match (self, octave > 0) {
+            (OctaveStyle::Dots, true) => "\u{0307}".repeat(count),
+            (OctaveStyle::Dots, false) => "\u{0323}".repeat(count),
+            (OctaveStyle::Abc, true) => "'".repeat(count),
+            (OctaveStyle::Abc, false) => ",".repeat(count),
+            (OctaveStyle::Numbers, upper) => {
+                let digits = if upper { &SUPERSCRIPT } else { &SUBSCRIPT };
+                count.to_string().bytes().map(|b| digits[(b - b'0') as usize]).collect()
+            }
+        }
+    }
+}
+
+/// Preferred enharmonic spelling of chromatic number-system pitches
+///
+/// Chooses between equivalent spellings such as "1#" and "2b". `Auto`
+/// follows the key signature: flats in flat keys, sharps otherwise.
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+#[derive(serde_repr::Serialize_repr, serde_repr::Deserialize_repr)]
+pub enum AccidentalPreference {
+    /// Follow the key signature
+    #[default]
+    Auto = 0,
+
+    /// Always spell with sharps ("1#")
+    Sharps = 1,
+
+    /// Always spell with flats ("2b")
+    Flats = 2,
+}
+
+impl AccidentalPreference {
+    /// Parse a preference name ("sharps", "flats", or anything else for auto)
+    pub fn parse(text: &str) -> Self {
+        match text.trim().to_lowercase().as_str() {
+            "sharps" | "sharp" => AccidentalPreference::Sharps,
+            "flats" | "flat" => AccidentalPreference::Flats,
+            _ => AccidentalPreference::Auto,
+        }
+    }
+
+    /// Whether flats are preferred in a key with `fifths` sharps (negative for flats)
+    pub fn prefers_flats(&self, fifths: i8) -> bool {
+        match self {
+            AccidentalPreference::Sharps => false,
+            AccidentalPreference::Flats => true,
+            AccidentalPreference::Auto => fifths < 0,
+        }
+    }
+}
+
 /// Text token properties for non-musical text
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TextToken {
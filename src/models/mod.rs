@@ -10,6 +10,8 @@ pub mod pitch;
 pub mod pitch_systems;
 pub mod barlines;
 pub mod serde_helpers;
+pub mod binary;
+pub mod tuning;
 
 // Re-export commonly used types
 pub use core::*;
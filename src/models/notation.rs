@@ -348,7 +348,7 @@ impl From<Selection> for Range {
 /// Musical ornament types
 #[wasm_bindgen]
 #[repr(u8)]
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum OrnamentType {
     /// No ornament
     None = 0,
@@ -382,6 +382,29 @@ impl OrnamentType {
         }
     }
 
+    /// MusicXML `<ornaments>` child element for this ornament
+    ///
+    /// Appoggiaturas and acciaccaturas are grace notes rather than ornaments,
+    /// so they have no element here.
+    pub fn musicxml_element(&self) -> Option<&'static str> {
+        match self {
+            OrnamentType::Mordent => Some("mordent"),
+            OrnamentType::Trill => Some("trill-mark"),
+            OrnamentType::Turn => Some("turn"),
+            _ => None,
+        }
+    }
+
+    /// Whether a grace-note type is slashed: `Some(true)` for an
+    /// acciaccatura, `Some(false)` for an appoggiatura, None otherwise
+    pub fn grace_slash(&self) -> Option<bool> {
+        match self {
+            OrnamentType::Appoggiatura => Some(false),
+            OrnamentType::Acciaccatura => Some(true),
+            _ => None,
+        }
+    }
+
     /// Check if this ornament should be rendered above the note
     pub fn is_above(&self) -> bool {
         !matches!(self, OrnamentType::None)
@@ -394,6 +417,32 @@ impl Default for OrnamentType {
     }
 }
 
+/// Printed size of a note
+///
+/// Cue notes are small editorial notes that, unlike grace notes, keep their
+/// full duration.
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum NoteSize {
+    /// Regular note
+    #[default]
+    Normal = 0,
+
+    /// Reduced-size cue note
+    Cue = 1,
+}
+
+impl NoteSize {
+    /// Parse a size name ("cue", anything else for normal)
+    pub fn parse(text: &str) -> Self {
+        match text.trim().to_lowercase().as_str() {
+            "cue" | "small" => NoteSize::Cue,
+            _ => NoteSize::Normal,
+        }
+    }
+}
+
 /// Tala notation for rhythmic patterns
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Tala {
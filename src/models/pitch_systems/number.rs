@@ -4,6 +4,12 @@
 //! seven degrees of the musical scale.
 
 use crate::models::elements::Accidental;
+use crate::models::PitchSystem;
+use crate::utils::pitch_utils::{degree_semitones, parse_pitch_code, MAJOR_SCALE_SEMITONES};
+
+/// Typed accidental aliases accepted after a number: "x" is a double sharp
+/// and "n" is a natural that cancels the accidentals typed before it
+pub const ACCIDENTAL_ALIASES: [char; 2] = ['x', 'n'];
 
 /// Number system implementation
 pub struct NumberSystem;
@@ -64,4 +70,76 @@ impl NumberSystem {
             _ => "1",
         }.to_string()
     }
-}
\ No newline at end of file
+
+    /// Resolve accidental aliases into a canonical pitch code
+    ///
+    /// Accidentals apply left to right, so "1x" is "1##" and "1#n" is "1".
+    /// Codes without an alias are returned unchanged; None if the code is
+    /// not a number followed by accidentals.
+    pub fn resolve_accidental_aliases(pitch_code: &str) -> Option<String> {
+        if !pitch_code.contains(ACCIDENTAL_ALIASES) {
+            return Some(pitch_code.to_string());
+        }
+
+        let mut chars = pitch_code.chars();
+        let base = chars.next().filter(|c| ('1'..='7').contains(c))?;
+        let mut alter: i32 = 0;
+        for c in chars {
+            match c {
+                '#' => alter += 1,
+                'b' => alter -= 1,
+                'x' => alter += 2,
+                'n' => alter = 0,
+                _ => return None,
+            }
+        }
+
+        let accidental = if alter >= 0 { "#" } else { "b" };
+        Some(format!("{}{}", base, accidental.repeat(alter.unsigned_abs() as usize)))
+    }
+
+    /// Respell a chromatic pitch code with the preferred accidental
+    ///
+    /// Only pitches between two scale degrees have a sharp and a flat
+    /// spelling ("1#" / "2b"); naturals and other codes return None.
+    pub fn respell(pitch_code: &str, prefer_flats: bool) -> Option<String> {
+        let (degree, alter) = parse_pitch_code(pitch_code, PitchSystem::Number)?;
+        if alter.abs() != 1 {
+            return None;
+        }
+
+        let semitones = degree_semitones(degree, alter);
+        let target = if prefer_flats { semitones + 1 } else { semitones - 1 };
+        let new_degree = MAJOR_SCALE_SEMITONES.iter().position(|&s| s == target)? + 1;
+        let spelled = format!("{}{}", new_degree, if prefer_flats { "b" } else { "#" });
+
+        (spelled != pitch_code).then_some(spelled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_accidental_aliases() {
+        assert_eq!(NumberSystem::resolve_accidental_aliases("1x").as_deref(), Some("1##"));
+        assert_eq!(NumberSystem::resolve_accidental_aliases("1#n").as_deref(), Some("1"));
+        assert_eq!(NumberSystem::resolve_accidental_aliases("3bnb").as_deref(), Some("3b"));
+        assert_eq!(NumberSystem::resolve_accidental_aliases("4#").as_deref(), Some("4#"));
+        assert_eq!(NumberSystem::resolve_accidental_aliases("8x"), None);
+    }
+
+    #[test]
+    fn test_respell_between_sharps_and_flats() {
+        assert_eq!(NumberSystem::respell("1#", true).as_deref(), Some("2b"));
+        assert_eq!(NumberSystem::respell("7b", false).as_deref(), Some("6#"));
+        assert_eq!(NumberSystem::respell("2b", true), None, "already a flat");
+    }
+
+    #[test]
+    fn test_respell_leaves_naturals_alone() {
+        assert_eq!(NumberSystem::respell("3#", true), None);
+        assert_eq!(NumberSystem::respell("4", false), None);
+    }
+}
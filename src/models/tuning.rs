@@ -0,0 +1,161 @@
+//! Tuning: A4 reference and temperament
+//!
+//! Pitches are stored as scale degrees and exported as MIDI note numbers.
+//! `TuningSettings` turns those into sounding frequencies. Temperaments other
+//! than equal temperament are defined by 12 frequency ratios over the tonic.
+//! A MIDI note can only sound an equal-tempered pitch, so the difference is
+//! carried as a pitch-bend amount.
+
+use serde::{Deserialize, Serialize};
+
+/// Default concert pitch of A4 in Hz
+pub const DEFAULT_A4_HZ: f32 = 440.0;
+
+/// MIDI note number of A4
+const A4_MIDI: i32 = 69;
+
+/// Pitch-bend range assumed for bend messages (±2 semitones, the General MIDI default)
+pub const PITCH_BEND_RANGE_CENTS: f64 = 200.0;
+
+/// 5-limit just intonation ratios for the 12 semitones above the tonic
+pub const JUST_INTONATION_RATIOS: [f64; 12] = [
+    1.0,
+    16.0 / 15.0,
+    9.0 / 8.0,
+    6.0 / 5.0,
+    5.0 / 4.0,
+    4.0 / 3.0,
+    45.0 / 32.0,
+    3.0 / 2.0,
+    8.0 / 5.0,
+    5.0 / 3.0,
+    9.0 / 5.0,
+    15.0 / 8.0,
+];
+
+/// How the 12 semitones of an octave are tuned relative to the tonic
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub enum Temperament {
+    /// Every semitone is 100 cents
+    #[default]
+    EqualTemperament,
+
+    /// Pure 5-limit intervals over the tonic (`JUST_INTONATION_RATIOS`)
+    JustIntonation,
+
+    /// 12 frequency ratios over the tonic, starting with the unison
+    Custom(Vec<f64>),
+}
+
+impl Temperament {
+    /// Parse a temperament name ("equal", "just", "custom")
+    ///
+    /// A custom temperament needs exactly 12 positive ratios.
+    pub fn parse(name: &str, ratios: &[f64]) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "equal" | "equal-temperament" | "et" => Ok(Temperament::EqualTemperament),
+            "just" | "just-intonation" | "ji" => Ok(Temperament::JustIntonation),
+            "custom" => {
+                if ratios.len() != 12 || ratios.iter().any(|&ratio| ratio <= 0.0) {
+                    return Err("Custom temperament needs 12 positive ratios".to_string());
+                }
+                Ok(Temperament::Custom(ratios.to_vec()))
+            }
+            other => Err(format!("Unknown temperament '{}'", other)),
+        }
+    }
+
+    /// Ratio over the tonic for `semitones` (0-11) above it, or None for equal temperament
+    fn ratio(&self, semitones: usize) -> Option<f64> {
+        match self {
+            Temperament::EqualTemperament => None,
+            Temperament::JustIntonation => Some(JUST_INTONATION_RATIOS[semitones]),
+            Temperament::Custom(ratios) => ratios.get(semitones).copied(),
+        }
+    }
+}
+
+/// Document-level tuning used for frequency and pitch-bend output
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TuningSettings {
+    /// Concert pitch of A4 in Hz
+    pub a4_hz: f32,
+
+    pub temperament: Temperament,
+}
+
+impl Default for TuningSettings {
+    fn default() -> Self {
+        Self {
+            a4_hz: DEFAULT_A4_HZ,
+            temperament: Temperament::EqualTemperament,
+        }
+    }
+}
+
+impl TuningSettings {
+    /// Deviation in cents of a MIDI note from its equal-tempered pitch at A4 = 440
+    ///
+    /// `tonic_midi` is any MIDI note of the tonic; only its pitch class is used.
+    pub fn cents_offset(&self, midi: i32, tonic_midi: i32) -> f64 {
+        let reference = 1200.0 * (self.a4_hz as f64 / DEFAULT_A4_HZ as f64).log2();
+        let semitones = (midi - tonic_midi).rem_euclid(12) as usize;
+        let temperament = self
+            .temperament
+            .ratio(semitones)
+            .map_or(0.0, |ratio| 1200.0 * ratio.log2() - 100.0 * semitones as f64);
+        reference + temperament
+    }
+
+    /// Sounding frequency in Hz of a MIDI note
+    pub fn frequency(&self, midi: i32, tonic_midi: i32) -> f64 {
+        let equal = DEFAULT_A4_HZ as f64 * 2f64.powf((midi - A4_MIDI) as f64 / 12.0);
+        equal * 2f64.powf(self.cents_offset(midi, tonic_midi) / 1200.0)
+    }
+
+    /// 14-bit pitch-bend amount (-8192..=8191, 0 = none) that realizes the tuning on a MIDI note
+    pub fn pitch_bend(&self, midi: i32, tonic_midi: i32) -> i16 {
+        let bend = self.cents_offset(midi, tonic_midi) / PITCH_BEND_RANGE_CENTS * 8192.0;
+        bend.round().clamp(-8192.0, 8191.0) as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equal_temperament_has_no_bend() {
+        let tuning = TuningSettings::default();
+        assert!((tuning.frequency(69, 60) - 440.0).abs() < 1e-9);
+        assert!((tuning.frequency(60, 60) - 261.6256).abs() < 1e-3);
+        for midi in 60..72 {
+            assert_eq!(tuning.pitch_bend(midi, 60), 0);
+        }
+    }
+
+    #[test]
+    fn test_just_major_third_bends_flat() {
+        let tuning = TuningSettings { temperament: Temperament::JustIntonation, ..TuningSettings::default() };
+
+        // 5/4 is 386.31 cents, 13.69 cents below the tempered third
+        assert_eq!(tuning.pitch_bend(64, 60), -561);
+        assert!((tuning.frequency(64, 60) - tuning.frequency(60, 60) * 1.25).abs() < 1e-9);
+        assert_eq!(tuning.pitch_bend(60, 60), 0);
+    }
+
+    #[test]
+    fn test_a4_reference_shifts_every_note() {
+        let tuning = TuningSettings { a4_hz: 442.0, ..TuningSettings::default() };
+        assert!((tuning.frequency(69, 60) - 442.0).abs() < 1e-9);
+        assert_eq!(tuning.pitch_bend(60, 60), tuning.pitch_bend(67, 60));
+        assert!(tuning.pitch_bend(60, 60) > 0);
+    }
+
+    #[test]
+    fn test_parse_temperament() {
+        assert_eq!(Temperament::parse("just", &[]), Ok(Temperament::JustIntonation));
+        assert!(Temperament::parse("custom", &[1.0; 11]).is_err());
+        assert!(Temperament::parse("meantone", &[]).is_err());
+    }
+}
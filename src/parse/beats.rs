@@ -11,6 +11,7 @@ use crate::models::*;
 #[wasm_bindgen]
 pub struct BeatDeriver {
     config: BeatConfig,
+    separator: BeatSeparator,
 }
 
 #[wasm_bindgen]
@@ -34,6 +35,7 @@ impl BeatDeriver {
                 loop_offset_px: 20.0,
                 loop_height_px: 6.0,
             },
+            separator: BeatSeparator::SingleSpace,
         }
     }
 
@@ -55,6 +57,12 @@ impl BeatDeriver {
         self.config.breath_ends_beat = breath_ends_beat;
     }
 
+    /// Set how much whitespace separates beats
+    #[wasm_bindgen(js_name = setSeparator)]
+    pub fn set_separator(&mut self, separator: BeatSeparator) {
+        self.separator = separator;
+    }
+
     /// Get beat configuration
     #[wasm_bindgen(js_name = getConfig)]
     pub fn get_config(&self) -> JsValue {
@@ -63,9 +71,16 @@ impl BeatDeriver {
 }
 
 impl BeatDeriver {
+    /// Create a beat deriver using the given whitespace separation policy
+    pub fn with_separator(separator: BeatSeparator) -> BeatDeriver {
+        BeatDeriver { separator, ..BeatDeriver::new() }
+    }
+
     /// Extract implicit beats from cells based on line grammar rules
     /// Grammar: beat-element = pitched-element | unpitched-element | breath-mark
     /// Beats are separated by anything that is NOT a beat-element (whitespace, text, barline, etc.)
+    /// Under `BeatSeparator::DoubleSpace` a single space between beat-elements joins them instead
+    /// Upper annotations (fermatas) belong to the note before them and never end a beat
     pub fn extract_implicit_beats(&self, cells: &[Cell]) -> Vec<BeatSpan> {
         log::info!("🎵 BeatDeriver: extracting beats from {} cells", cells.len());
 
@@ -74,7 +89,9 @@ impl BeatDeriver {
         let mut current_duration = 1.0;
 
         for (index, cell) in cells.iter().enumerate() {
-            let is_beat = self.is_beat_element(cell);
+            let is_beat = self.is_beat_element(cell)
+                || self.joins_beat(cells, index)
+                || (beat_start.is_some() && cell.kind == ElementKind::UpperAnnotation);
             log::info!("  Cell {}: '{}' kind={:?} is_beat_element={}",
                 index, cell.glyph, cell.kind, is_beat);
 
@@ -107,6 +124,15 @@ impl BeatDeriver {
         beats
     }
 
+    /// Whether the whitespace cell at `index` is a lone space inside a beat
+    fn joins_beat(&self, cells: &[Cell], index: usize) -> bool {
+        self.separator == BeatSeparator::DoubleSpace
+            && cells[index].kind == ElementKind::Whitespace
+            && index > 0
+            && cells.get(index + 1).is_some_and(|next| self.is_beat_element(next))
+            && self.is_beat_element(&cells[index - 1])
+    }
+
     /// Check if element is a beat-element per grammar
     /// beat-element = pitched-element | unpitched-element | breath-mark
     fn is_beat_element(&self, cell: &Cell) -> bool {
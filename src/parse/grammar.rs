@@ -5,6 +5,8 @@
 //! 2. parse(before, char) - Look back combination (accidentals, text)
 //! 3. parse(char, after) - Look forward combination (barlines)
 
+use serde::{Deserialize, Serialize};
+use crate::models::pitch_systems::number::NumberSystem;
 use crate::models::{Cell, ElementKind, PitchSystem};
 use crate::parse::pitch_system::PitchSystemDispatcher;
 
@@ -13,15 +15,69 @@ fn get_dispatcher() -> PitchSystemDispatcher {
     PitchSystemDispatcher::new()
 }
 
+/// Accidentals allowed on one note unless a document raises the limit
+pub const DEFAULT_MAX_ACCIDENTAL_DEPTH: u8 = 2;
+
+/// Token that puts a fermata over the preceding note ("1^")
+pub const FERMATA_TOKEN: &str = "^";
+
+/// Dialect characters for duration extension and rests
+///
+/// "-" and "_" always extend the previous note. A document may add its own
+/// extension character (e.g. an en dash) and a rest character (e.g. "z").
+/// Both become `UnpitchedElement` cells, so beats treat them like dashes;
+/// rest cells are flagged (`Cell::is_rest`) and always sound as silence.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct NotationTokens {
+    /// Extra character that extends the previous note
+    pub dash: Option<char>,
+
+    /// Character that writes one subdivision of rest
+    pub rest: Option<char>,
+}
+
+impl NotationTokens {
+    /// Parse a single character, recognizing the dialect tokens first
+    pub fn parse_single(&self, c: char, pitch_system: PitchSystem, column: usize) -> Cell {
+        if Some(c) == self.rest {
+            let mut cell = Cell::new(c.to_string(), ElementKind::UnpitchedElement, column);
+            cell.set_rest(true);
+            cell
+        } else if Some(c) == self.dash {
+            Cell::new(c.to_string(), ElementKind::UnpitchedElement, column)
+        } else {
+            parse_single(c, pitch_system, column)
+        }
+    }
+
+    /// Check that `c` can serve as a token without shadowing other notation
+    pub fn validate(c: char, pitch_system: PitchSystem) -> Result<(), String> {
+        if parse_single(c, pitch_system, 0).kind == ElementKind::Text && !c.is_whitespace() {
+            Ok(())
+        } else {
+            Err(format!("'{}' already has a meaning in this pitch system", c))
+        }
+    }
+}
+
 /// Parse a string into a Cell (recursive descent entry point)
 /// Tries all production rules in order: MULTI-CHAR FIRST, then single-char
 pub fn parse(s: &str, pitch_system: PitchSystem, column: usize) -> Cell {
+    parse_with_depth(s, pitch_system, column, DEFAULT_MAX_ACCIDENTAL_DEPTH)
+}
+
+/// Parse a string into a Cell, allowing up to `max_accidental_depth`
+/// repeated accidentals on a note ("1###" needs 3)
+pub fn parse_with_depth(s: &str, pitch_system: PitchSystem, column: usize, max_accidental_depth: u8) -> Cell {
     log::info!("🔍 parse('{}', {:?}, {})", s, pitch_system, column);
 
     // MULTI-CHARACTER PATTERNS FIRST (greedy matching)
 
+    // Multi-character means more than one char, not more than one byte
+    let is_multi_char = s.chars().count() > 1;
+
     // Try multi-char barlines: "|:", ":|", "||"
-    if s.len() > 1 {
+    if is_multi_char {
         if let Some(cell) = parse_barline(s, column) {
             log::info!("  ✅ Parsed as multi-char barline");
             return cell;
@@ -29,8 +85,8 @@ pub fn parse(s: &str, pitch_system: PitchSystem, column: usize) -> Cell {
     }
 
     // Try notes with accidentals: "1#", "2bb", "c#", etc.
-    if s.len() > 1 {
-        if let Some(cell) = parse_note(s, pitch_system, column) {
+    if is_multi_char {
+        if let Some(cell) = parse_note(s, pitch_system, column, max_accidental_depth) {
             log::info!("  ✅ Parsed as multi-char note");
             return cell;
         }
@@ -39,7 +95,7 @@ pub fn parse(s: &str, pitch_system: PitchSystem, column: usize) -> Cell {
     // SINGLE-CHARACTER PATTERNS
 
     // Try single-char note: "1", "2", "c", etc.
-    if let Some(cell) = parse_note(s, pitch_system, column) {
+    if let Some(cell) = parse_note(s, pitch_system, column, max_accidental_depth) {
         log::info!("  ✅ Parsed as note");
         return cell;
     }
@@ -68,6 +124,12 @@ pub fn parse(s: &str, pitch_system: PitchSystem, column: usize) -> Cell {
         return cell;
     }
 
+    // Try fermata
+    if let Some(cell) = parse_fermata(s, column) {
+        log::info!("  ✅ Parsed as fermata");
+        return cell;
+    }
+
     // Fallback to text
     log::info!("  ℹ️ Parsed as text (fallback)");
     parse_text(s, column)
@@ -79,15 +141,60 @@ pub fn parse_single(c: char, pitch_system: PitchSystem, column: usize) -> Cell {
     parse(&c.to_string(), pitch_system, column)
 }
 
+/// Parse a whole string into cells, combining multi-character tokens
+///
+/// Each char becomes one cell (columns are char positions, never byte
+/// offsets), then adjacent cells are combined left to right.
+pub fn parse_cells(text: &str, pitch_system: PitchSystem) -> Vec<Cell> {
+    parse_cells_with_depth(text, pitch_system, DEFAULT_MAX_ACCIDENTAL_DEPTH)
+}
+
+/// Parse a whole string into cells with a custom accidental depth limit
+pub fn parse_cells_with_depth(text: &str, pitch_system: PitchSystem, max_accidental_depth: u8) -> Vec<Cell> {
+    parse_cells_with_tokens(text, pitch_system, max_accidental_depth, &NotationTokens::default())
+}
+
+/// Parse a whole string into cells, honoring a document's dialect tokens
+pub fn parse_cells_with_tokens(
+    text: &str,
+    pitch_system: PitchSystem,
+    max_accidental_depth: u8,
+    tokens: &NotationTokens,
+) -> Vec<Cell> {
+    let mut cells: Vec<Cell> = text
+        .chars()
+        .enumerate()
+        .map(|(column, c)| tokens.parse_single(c, pitch_system, column))
+        .collect();
+
+    let mut i = 1;
+    while i < cells.len() {
+        let prev_len = cells.len();
+        try_combine_tokens_with_depth(&mut cells, i, pitch_system, max_accidental_depth);
+
+        // If a combination happened, stay at the same position and try again
+        if cells.len() == prev_len {
+            i += 1;
+        }
+    }
+
+    cells
+}
+
 /// Try to combine previous cell with new character (Case 2: look back)
 /// Returns Some(new_cell) if combination is valid, None otherwise
 pub fn parse_with_before(prev: &Cell, c: char, pitch_system: PitchSystem) -> Option<Cell> {
+    parse_with_before_depth(prev, c, pitch_system, DEFAULT_MAX_ACCIDENTAL_DEPTH)
+}
+
+/// Look-back combination with a custom accidental depth limit
+pub fn parse_with_before_depth(prev: &Cell, c: char, pitch_system: PitchSystem, max_accidental_depth: u8) -> Option<Cell> {
     // Build combined string
     let combined_str = format!("{}{}", prev.glyph, c);
     log::info!("  ⬅️ parse_with_before: trying '{}'", combined_str);
 
     // Try to parse the combined string
-    let cell = parse(&combined_str, pitch_system, prev.col);
+    let cell = parse_with_depth(&combined_str, pitch_system, prev.col, max_accidental_depth);
 
     // Only combine if it's NOT just text (text is fallback, means parse failed)
     if cell.kind != ElementKind::Text {
@@ -114,12 +221,23 @@ pub fn parse_with_before(prev: &Cell, c: char, pitch_system: PitchSystem) -> Opt
 /// Try to combine current character with next character (Case 3: look forward)
 /// Returns Some(new_cell) if combination is valid, None otherwise
 pub fn parse_with_after(c: char, next: &Cell, pitch_system: PitchSystem, column: usize) -> Option<Cell> {
+    parse_with_after_depth(c, next, pitch_system, column, DEFAULT_MAX_ACCIDENTAL_DEPTH)
+}
+
+/// Look-forward combination with a custom accidental depth limit
+pub fn parse_with_after_depth(
+    c: char,
+    next: &Cell,
+    pitch_system: PitchSystem,
+    column: usize,
+    max_accidental_depth: u8,
+) -> Option<Cell> {
     // Build combined string
     let combined_str = format!("{}{}", c, next.glyph);
     log::info!("  ➡️ parse_with_after: trying '{}'", combined_str);
 
     // Try to parse the combined string
-    let cell = parse(&combined_str, pitch_system, column);
+    let cell = parse_with_depth(&combined_str, pitch_system, column, max_accidental_depth);
 
     // Only combine if it's NOT just text (text is fallback, means parse failed)
     if cell.kind != ElementKind::Text {
@@ -136,12 +254,18 @@ pub fn parse_with_after(c: char, next: &Cell, pitch_system: PitchSystem, column:
 // ============================================================================
 
 /// Parse note (includes accidentals: "1", "1#", "2bb", "c#", etc.)
-fn parse_note(s: &str, pitch_system: PitchSystem, column: usize) -> Option<Cell> {
+fn parse_note(s: &str, pitch_system: PitchSystem, column: usize, max_accidental_depth: u8) -> Option<Cell> {
     let dispatcher = get_dispatcher();
-    if dispatcher.lookup(s, pitch_system) {
+    if dispatcher.lookup_with_depth(s, pitch_system, max_accidental_depth) {
         let mut cell = Cell::new(s.to_string(), ElementKind::PitchedElement, column);
         cell.pitch_system = Some(pitch_system);
-        cell.pitch_code = Some(s.to_string());
+        // Systems parsed with the number table store aliased accidentals
+        // ("1x") in canonical form ("1##")
+        let pitch_code = match pitch_system {
+            PitchSystem::Western | PitchSystem::Sargam | PitchSystem::Bhatkhande => None,
+            _ => NumberSystem::resolve_accidental_aliases(s),
+        };
+        cell.pitch_code = Some(pitch_code.unwrap_or_else(|| s.to_string()));
         cell.set_head(true);
         Some(cell)
     } else {
@@ -151,7 +275,7 @@ fn parse_note(s: &str, pitch_system: PitchSystem, column: usize) -> Option<Cell>
 
 /// Parse barline (includes "|", "|:", ":|", "||", etc.)
 fn parse_barline(s: &str, column: usize) -> Option<Cell> {
-    if matches!(s, "|" | "|:" | ":|" | "||") {
+    if matches!(s, "|" | "|:" | ":|" | "||" | "|||") {
         let cell = Cell::new(s.to_string(), ElementKind::Barline, column);
         Some(cell)
     } else {
@@ -189,6 +313,16 @@ fn parse_breath_mark(s: &str, column: usize) -> Option<Cell> {
     }
 }
 
+/// Parse fermata (caret after the held note)
+fn parse_fermata(s: &str, column: usize) -> Option<Cell> {
+    if s == FERMATA_TOKEN {
+        let cell = Cell::new(s.to_string(), ElementKind::UpperAnnotation, column);
+        Some(cell)
+    } else {
+        None
+    }
+}
+
 /// Parse text (fallback)
 fn parse_text(s: &str, column: usize) -> Cell {
     Cell::new(s.to_string(), ElementKind::Text, column)
@@ -203,6 +337,16 @@ fn parse_text(s: &str, column: usize) -> Cell {
 /// 1. Look back: Can we combine prev + current?
 /// 2. Look forward: Can we combine current + next?
 pub fn try_combine_tokens(cells: &mut Vec<Cell>, insert_pos: usize, pitch_system: PitchSystem) {
+    try_combine_tokens_with_depth(cells, insert_pos, pitch_system, DEFAULT_MAX_ACCIDENTAL_DEPTH)
+}
+
+/// Token combination with a custom accidental depth limit
+pub fn try_combine_tokens_with_depth(
+    cells: &mut Vec<Cell>,
+    insert_pos: usize,
+    pitch_system: PitchSystem,
+    max_accidental_depth: u8,
+) {
     log::info!("🔄 try_combine_tokens called: insert_pos={}, cells.len()={}, pitch_system={:?}",
         insert_pos, cells.len(), pitch_system);
 
@@ -211,6 +355,12 @@ pub fn try_combine_tokens(cells: &mut Vec<Cell>, insert_pos: usize, pitch_system
         return;
     }
 
+    // Dashes and rests never combine; this also keeps dialect tokens intact
+    if cells.get(insert_pos).is_some_and(|cell| cell.kind == ElementKind::UnpitchedElement) {
+        log::info!("  ⏭️ Unpitched element does not combine");
+        return;
+    }
+
     // Log current state
     let cells_str: Vec<String> = cells.iter().map(|c| format!("'{}'[{}]", c.glyph, c.kind as u8)).collect();
     log::info!("  📋 Current cells: [{}]", cells_str.join(", "));
@@ -221,7 +371,7 @@ pub fn try_combine_tokens(cells: &mut Vec<Cell>, insert_pos: usize, pitch_system
         log::info!("  ⬅️ Case 2 (Look back): prev='{}', current_char='{}'",
             cells[insert_pos - 1].glyph, current_char);
 
-        if let Some(combined) = parse_with_before(&cells[insert_pos - 1], current_char, pitch_system) {
+        if let Some(combined) = parse_with_before_depth(&cells[insert_pos - 1], current_char, pitch_system, max_accidental_depth) {
             log::info!("  ✅ Combination succeeded: '{}'", combined.glyph);
             // Replace previous cell with combined cell
             cells[insert_pos - 1] = combined;
@@ -251,7 +401,7 @@ pub fn try_combine_tokens(cells: &mut Vec<Cell>, insert_pos: usize, pitch_system
         log::info!("  ➡️ Case 3 (Look forward): current_char='{}', next='{}'",
             current_char, cells[insert_pos + 1].glyph);
 
-        if let Some(combined) = parse_with_after(current_char, &cells[insert_pos + 1], pitch_system, cells[insert_pos].col) {
+        if let Some(combined) = parse_with_after_depth(current_char, &cells[insert_pos + 1], pitch_system, cells[insert_pos].col, max_accidental_depth) {
             log::info!("  ✅ Combination succeeded: '{}'", combined.glyph);
             // Replace current cell with combined cell
             cells[insert_pos] = combined;
@@ -278,6 +428,66 @@ pub fn try_combine_tokens(cells: &mut Vec<Cell>, insert_pos: usize, pitch_system
     log::info!("  🏁 No combination performed");
 }
 
+/// Reparse the note token around `index` with longest-match parsing
+///
+/// The maximal run of adjacent pitched and text cells containing `index` is
+/// joined and parsed again, so accidentals typed in any order or split
+/// across stray cells ("1", "#", "#") end up in one note ("1##"). Notes that
+/// start at the same offset keep their annotations (`Cell::copy_annotations_from`).
+/// Does nothing and returns false unless the run contains a pitched cell.
+pub fn reparse_token_at(
+    cells: &mut Vec<Cell>,
+    index: usize,
+    pitch_system: PitchSystem,
+    max_accidental_depth: u8,
+    tokens: &NotationTokens,
+) -> bool {
+    let in_token = |cell: &Cell| matches!(cell.kind, ElementKind::PitchedElement | ElementKind::Text);
+    if !cells.get(index).is_some_and(in_token) {
+        return false;
+    }
+
+    let start = (0..index).rev().take_while(|&i| in_token(&cells[i])).last().unwrap_or(index);
+    let end = (index..cells.len()).take_while(|&i| in_token(&cells[i])).last().unwrap_or(index) + 1;
+    if !cells[start..end].iter().any(|cell| cell.kind == ElementKind::PitchedElement) {
+        return false;
+    }
+
+    // Char offset within the run of each old note, to carry its attributes over
+    let mut offset = 0;
+    let mut old_notes = Vec::new();
+    for cell in &cells[start..end] {
+        if cell.kind == ElementKind::PitchedElement {
+            old_notes.push((offset, cell.clone()));
+        }
+        offset += cell.glyph.chars().count();
+    }
+
+    let text: String = cells[start..end].iter().map(|cell| cell.glyph.as_str()).collect();
+    let first_col = cells[start].col;
+    let mut reparsed = parse_cells_with_tokens(&text, pitch_system, max_accidental_depth, tokens);
+
+    let mut offset = 0;
+    for (i, cell) in reparsed.iter_mut().enumerate() {
+        cell.col = first_col + i;
+        if let Some((_, old)) = old_notes.iter().find(|(at, _)| *at == offset) {
+            if cell.kind == ElementKind::PitchedElement {
+                cell.copy_annotations_from(old);
+            }
+        }
+        offset += cell.glyph.chars().count();
+    }
+
+    let removed = end - start;
+    let added = reparsed.len();
+    cells.splice(start..end, reparsed);
+    for cell in &mut cells[start + added..] {
+        cell.col = (cell.col + added).saturating_sub(removed);
+    }
+    log::info!("  🔁 Reparsed '{}' into {} cell(s)", text, added);
+    true
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -328,6 +538,60 @@ mod tests {
         assert_eq!(combined.glyph, "c#");
     }
 
+    #[test]
+    fn test_accidental_depth_limit() {
+        let glyphs = |cells: Vec<Cell>| cells.into_iter().map(|c| c.glyph).collect::<Vec<_>>();
+
+        assert_eq!(glyphs(parse_cells("1###", PitchSystem::Number)), vec!["1##", "#"]);
+
+        let cells = parse_cells_with_depth("1###", PitchSystem::Number, 3);
+        assert_eq!(cells.len(), 1);
+        assert_eq!(cells[0].kind, ElementKind::PitchedElement);
+        assert_eq!(cells[0].pitch_code.as_deref(), Some("1###"));
+    }
+
+    #[test]
+    fn test_number_accidental_aliases_longest_match() {
+        let double_sharp = parse_cells("1x", PitchSystem::Number);
+        assert_eq!(double_sharp.len(), 1);
+        assert_eq!(double_sharp[0].glyph, "1x");
+        assert_eq!(double_sharp[0].pitch_code.as_deref(), Some("1##"));
+
+        let natural = parse_cells("1#n 2", PitchSystem::Number);
+        assert_eq!(natural.len(), 3);
+        assert_eq!(natural[0].pitch_code.as_deref(), Some("1"));
+
+        let plain = parse_cells("2#", PitchSystem::Number);
+        assert_eq!(plain[0].pitch_code.as_deref(), Some("2#"), "defaults are unchanged");
+    }
+
+    #[test]
+    fn test_custom_rest_and_dash_tokens() {
+        let tokens = NotationTokens { dash: Some('–'), rest: Some('z') };
+        let cells = parse_cells_with_tokens("1– zz x", PitchSystem::Number, DEFAULT_MAX_ACCIDENTAL_DEPTH, &tokens);
+
+        let kinds: Vec<ElementKind> = cells.iter().map(|cell| cell.kind).collect();
+        assert_eq!(kinds[..5], [
+            ElementKind::PitchedElement,
+            ElementKind::UnpitchedElement,
+            ElementKind::Whitespace,
+            ElementKind::UnpitchedElement,
+            ElementKind::UnpitchedElement,
+        ]);
+        assert!(cells[3].is_rest() && cells[4].is_rest());
+        assert!(!cells[1].is_rest());
+        assert_eq!(cells[3].glyph, "z", "the typed character is kept");
+
+        // Without the dialect the same text is not notation
+        let plain = parse_cells("zz", PitchSystem::Number);
+        assert_eq!(plain.len(), 1);
+        assert_eq!(plain[0].kind, ElementKind::Text);
+
+        assert!(NotationTokens::validate('z', PitchSystem::Number).is_ok());
+        assert!(NotationTokens::validate('3', PitchSystem::Number).is_err());
+        assert!(NotationTokens::validate('c', PitchSystem::Western).is_err());
+    }
+
     #[test]
     fn test_try_combine_tokens() {
         let mut cells = vec![
@@ -341,4 +605,18 @@ mod tests {
         assert_eq!(cells[0].glyph, "1#");
         assert_eq!(cells[0].kind, ElementKind::PitchedElement);
     }
+
+    #[test]
+    fn test_reparse_keeps_chord_tones() {
+        let mut cells = parse_cells("1", PitchSystem::Number);
+        cells[0].extra_pitches = vec![("3".to_string(), 0), ("5".to_string(), 0)];
+        cells[0].octave = 1;
+        cells.push(parse_single('#', PitchSystem::Number, 1));
+
+        assert!(reparse_token_at(&mut cells, 1, PitchSystem::Number, DEFAULT_MAX_ACCIDENTAL_DEPTH, &NotationTokens::default()));
+        assert_eq!(cells.len(), 1);
+        assert_eq!(cells[0].glyph, "1#");
+        assert_eq!(cells[0].octave, 1);
+        assert_eq!(cells[0].extra_pitches, vec![("3".to_string(), 0), ("5".to_string(), 0)]);
+    }
 }
@@ -3,6 +3,7 @@
 //! This module provides the lookup tables for multi-character musical tokens
 //! across different pitch systems (Number, Western, Sargam).
 
+use crate::models::pitch_systems::number::{NumberSystem, ACCIDENTAL_ALIASES};
 use crate::models::PitchSystem;
 
 /// Trait for pitch system implementations
@@ -19,12 +20,16 @@ pub trait PitchSystemHandler {
     fn get_pitch_chars(&self) -> Vec<char>;
 }
 
-/// Number notation pitch system (1-7 with #/b accidentals)
+/// Number notation pitch system (1-7 with #/b accidentals, plus the
+/// x/n aliases in `ACCIDENTAL_ALIASES`)
 #[derive(Debug, Clone)]
 pub struct NumberPitchSystem;
 
 impl PitchSystemHandler for NumberPitchSystem {
     fn lookup(&self, symbol: &str) -> bool {
+        if symbol.contains(ACCIDENTAL_ALIASES) {
+            return NumberSystem::resolve_accidental_aliases(symbol).is_some_and(|code| self.lookup(&code));
+        }
         matches!(symbol,
             "1" | "2" | "3" | "4" | "5" | "6" | "7" |
             "1#" | "1b" | "2#" | "2b" | "3#" | "3b" |
@@ -35,7 +40,7 @@ impl PitchSystemHandler for NumberPitchSystem {
     }
 
     fn get_valid_chars(&self) -> Vec<char> {
-        vec!['1', '2', '3', '4', '5', '6', '7', '#', 'b']
+        vec!['1', '2', '3', '4', '5', '6', '7', '#', 'b', 'x', 'n']
     }
 
     fn get_pitch_chars(&self) -> Vec<char> {
@@ -128,6 +133,30 @@ impl PitchSystemDispatcher {
         self.get_handler(system).lookup(symbol)
     }
 
+    /// Check if a symbol is valid allowing up to `max_depth` repeated accidentals
+    ///
+    /// The lookup tables stop at double sharps/flats; deeper accidentals are
+    /// valid when the double form is and every accidental is the same sign.
+    pub fn lookup_with_depth(&self, symbol: &str, system: PitchSystem, max_depth: u8) -> bool {
+        let Some(base) = symbol.chars().next() else {
+            return false;
+        };
+        let accidentals = &symbol[base.len_utf8()..];
+        let depth = accidentals.chars().count();
+
+        if depth > max_depth as usize {
+            return false;
+        }
+        if depth <= 2 {
+            return self.lookup(symbol, system);
+        }
+
+        let sign = accidentals.chars().next().unwrap_or('#');
+        matches!(sign, '#' | 'b')
+            && accidentals.chars().all(|c| c == sign)
+            && self.lookup(&symbol[..base.len_utf8() + 2], system)
+    }
+
     /// Check if a character is a valid pitch character (not accidental) for the system
     pub fn is_pitch_char(&self, c: char, system: PitchSystem) -> bool {
         self.get_handler(system).get_pitch_chars().contains(&c)
@@ -201,6 +230,13 @@ mod tests {
         assert!(!dispatcher.lookup("X", PitchSystem::Number));
         assert!(!dispatcher.lookup("1", PitchSystem::Western));
 
+        // Test accidental depth limits
+        assert!(!dispatcher.lookup_with_depth("1###", PitchSystem::Number, 2));
+        assert!(dispatcher.lookup_with_depth("1###", PitchSystem::Number, 3));
+        assert!(dispatcher.lookup_with_depth("bbbb", PitchSystem::Western, 3));
+        assert!(!dispatcher.lookup_with_depth("1##b", PitchSystem::Number, 3));
+        assert!(!dispatcher.lookup_with_depth("1#", PitchSystem::Number, 0));
+
         // Test pitch char detection
         assert!(dispatcher.is_pitch_char('1', PitchSystem::Number));
         assert!(!dispatcher.is_pitch_char('#', PitchSystem::Number));
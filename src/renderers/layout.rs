@@ -3,8 +3,22 @@
 //! This module provides position calculation and layout algorithms
 //! for rendering Cell elements in the correct positions.
 
+use std::ops::Range;
 use wasm_bindgen::prelude::*;
+use crate::ir::build_export_measures_with_separator;
 use crate::models::*;
+use crate::parse::beats::BeatDeriver;
+use crate::models::tuning::TuningSettings;
+use crate::utils::pitch_utils::{degree_semitones, parse_pitch_code, split_quarter_tone};
+
+/// Glyph scale of cue-sized notes relative to the font size
+pub const CUE_SIZE_FACTOR: f32 = 0.7;
+
+/// Fermata glyph (U+1D110 MUSICAL SYMBOL FERMATA)
+const FERMATA_GLYPH: &str = "\u{1D110}";
+
+/// Shuddha sargam letters by scale degree
+const SHUDDHA_SARGAM: [&str; 7] = ["S", "R", "G", "m", "P", "D", "N"];
 
 /// Layout renderer for calculating Cell positions
 #[wasm_bindgen]
@@ -12,6 +26,24 @@ pub struct LayoutRenderer {
     font_size: f32,
     char_width: f32,
     line_height: f32,
+    /// Annotate notes with their rhythmic duration (teaching aid)
+    show_durations: bool,
+    /// Draw a counting tick under each subdivision of a beat (teaching aid)
+    show_subdivisions: bool,
+    /// Label half-flat and half-sharp notes with their deviation in cents
+    show_cents_deviation: bool,
+    /// Pad beats so beats and barlines line up across the lines of a system
+    smart_spacing: bool,
+    /// Mark komal and tivra notes of sargam lines with traditional strokes
+    traditional_accidentals: bool,
+    /// How octave markers are drawn
+    octave_style: OctaveStyle,
+    /// Show komal sargam notes as "R♭" rather than "r"
+    sargam_flat_signs: bool,
+    /// Whitespace between beats, for subdivision ticks
+    beat_separator: BeatSeparator,
+    /// Tuning used for cents labels in `line_marks`
+    tuning: TuningSettings,
 }
 
 #[wasm_bindgen]
@@ -23,9 +55,45 @@ impl LayoutRenderer {
             font_size,
             char_width: font_size * 0.6,  // Approximate character width
             line_height: font_size * 1.2, // Line height with spacing
+            show_durations: false,
+            show_subdivisions: false,
+            show_cents_deviation: false,
+            smart_spacing: false,
+            traditional_accidentals: false,
+            octave_style: OctaveStyle::Dots,
+            sargam_flat_signs: false,
+            beat_separator: BeatSeparator::SingleSpace,
+            tuning: TuningSettings::default(),
         }
     }
 
+    /// Take the display settings of a document
+    ///
+    /// Reads octave style, traditional accidentals, sargam flat signs, beat
+    /// separator and tuning. Call before laying out the document's lines.
+    #[wasm_bindgen(js_name = applyDocumentSettings)]
+    pub fn apply_document_settings(&mut self, document: &JsValue) -> Result<(), JsValue> {
+        let document: Document = serde_wasm_bindgen::from_value(document.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        self.use_document_settings(&document);
+        Ok(())
+    }
+
+    /// Calculate every mark drawn around the cells of a line
+    ///
+    /// Returns a `LineMarks` object: the displayed glyph of each cell plus
+    /// the accidental, octave, fingering, stacked pitch, grace slash,
+    /// fermata, duration, subdivision and cents marks.
+    #[wasm_bindgen(js_name = calculateLineMarks)]
+    pub fn calculate_line_marks(&self, line: &JsValue) -> Result<JsValue, JsValue> {
+        let line: Line = serde_wasm_bindgen::from_value(line.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        serde_wasm_bindgen::to_value(&self.line_marks(&line))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
     /// Calculate positions for Cell array
     #[wasm_bindgen(js_name = calculatePositions)]
     pub fn calculate_positions(&self, char_cells: &JsValue) -> Result<JsValue, JsValue> {
@@ -46,6 +114,41 @@ impl LayoutRenderer {
             .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
     }
 
+    /// Calculate cell positions for every line of a document
+    ///
+    /// With smart spacing enabled, beats and barlines of lines grouped in a
+    /// system share x-coordinates.
+    #[wasm_bindgen(js_name = calculateSystemPositions)]
+    pub fn calculate_system_positions(&self, lines: &JsValue) -> Result<JsValue, JsValue> {
+        let mut lines: Vec<Line> = serde_wasm_bindgen::from_value(lines.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        self.layout_lines(&mut lines);
+
+        serde_wasm_bindgen::to_value(&lines)
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Calculate cell positions for the lines `start_line..end_line` only
+    ///
+    /// Lets the UI lay out the visible viewport of a long document. Returns
+    /// just the requested lines, positioned exactly as in a full layout.
+    #[wasm_bindgen(js_name = calculateSystemPositionsRange)]
+    pub fn calculate_system_positions_range(
+        &self,
+        lines: &JsValue,
+        start_line: usize,
+        end_line: usize,
+    ) -> Result<JsValue, JsValue> {
+        let lines: Vec<Line> = serde_wasm_bindgen::from_value(lines.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        let laid_out = self.layout_line_range(&lines, start_line, end_line);
+
+        serde_wasm_bindgen::to_value(&laid_out)
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
     /// Calculate beat loop positions
     #[wasm_bindgen(js_name = calculateBeatLoopPositions)]
     pub fn calculate_beat_loop_positions(&self, beats: &JsValue, char_cells: &JsValue) -> Result<JsValue, JsValue> {
@@ -120,6 +223,164 @@ impl LayoutRenderer {
             .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
     }
 
+    /// Calculate duration label positions for a line
+    ///
+    /// Returns an empty array unless duration display is enabled.
+    #[wasm_bindgen(js_name = calculateDurationLabels)]
+    pub fn calculate_duration_labels(&self, line: &JsValue) -> Result<JsValue, JsValue> {
+        let line: Line = serde_wasm_bindgen::from_value(line.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        serde_wasm_bindgen::to_value(&self.duration_labels(&line))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Calculate subdivision tick positions for a line
+    ///
+    /// Returns an empty array unless subdivision display is enabled.
+    #[wasm_bindgen(js_name = calculateSubdivisionTicks)]
+    pub fn calculate_subdivision_ticks(&self, line: &JsValue) -> Result<JsValue, JsValue> {
+        let line: Line = serde_wasm_bindgen::from_value(line.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        serde_wasm_bindgen::to_value(&self.subdivision_ticks(&line))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Calculate the cents labels of microtonal notes in a line
+    ///
+    /// `tuning` is the document's tuning settings. Returns an empty array
+    /// unless cents display is enabled.
+    #[wasm_bindgen(js_name = calculateCentsLabels)]
+    pub fn calculate_cents_labels(&self, line: &JsValue, tuning: &JsValue) -> Result<JsValue, JsValue> {
+        let line: Line = serde_wasm_bindgen::from_value(line.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+        let tuning: TuningSettings = serde_wasm_bindgen::from_value(tuning.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        serde_wasm_bindgen::to_value(&self.cents_labels(&line, &tuning))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Calculate fermata glyph positions for a line
+    #[wasm_bindgen(js_name = calculateFermataPositions)]
+    pub fn calculate_fermata_positions(&self, line: &JsValue) -> Result<JsValue, JsValue> {
+        let line: Line = serde_wasm_bindgen::from_value(line.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        serde_wasm_bindgen::to_value(&self.fermata_marks(&line))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Calculate positions of pitches stacked on notes for a line
+    #[wasm_bindgen(js_name = calculateStackedPitchPositions)]
+    pub fn calculate_stacked_pitch_positions(&self, line: &JsValue) -> Result<JsValue, JsValue> {
+        let line: Line = serde_wasm_bindgen::from_value(line.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        serde_wasm_bindgen::to_value(&self.stacked_pitch_marks(&line))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Calculate fingering label positions for a line
+    #[wasm_bindgen(js_name = calculateFingeringPositions)]
+    pub fn calculate_fingering_positions(&self, line: &JsValue) -> Result<JsValue, JsValue> {
+        let line: Line = serde_wasm_bindgen::from_value(line.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        serde_wasm_bindgen::to_value(&self.fingering_marks(&line))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Calculate the slash strokes of acciaccaturas in a line
+    #[wasm_bindgen(js_name = calculateGraceSlashes)]
+    pub fn calculate_grace_slashes(&self, line: &JsValue) -> Result<JsValue, JsValue> {
+        let line: Line = serde_wasm_bindgen::from_value(line.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        serde_wasm_bindgen::to_value(&self.grace_slashes(&line))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Calculate komal/tivra marker positions for a line
+    #[wasm_bindgen(js_name = calculateAccidentalMarkers)]
+    pub fn calculate_accidental_markers(&self, line: &JsValue) -> Result<JsValue, JsValue> {
+        let line: Line = serde_wasm_bindgen::from_value(line.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        serde_wasm_bindgen::to_value(&self.accidental_markers(&line))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Calculate octave marker positions for a line
+    #[wasm_bindgen(js_name = calculateOctaveMarks)]
+    pub fn calculate_octave_marks(&self, line: &JsValue) -> Result<JsValue, JsValue> {
+        let line: Line = serde_wasm_bindgen::from_value(line.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        serde_wasm_bindgen::to_value(&self.octave_marks(&line))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Set how octave markers are drawn
+    #[wasm_bindgen(js_name = setOctaveStyle)]
+    pub fn set_octave_style(&mut self, style: OctaveStyle) {
+        self.octave_style = style;
+    }
+
+    /// Enable or disable traditional komal/tivra markers
+    #[wasm_bindgen(js_name = setTraditionalAccidentals)]
+    pub fn set_traditional_accidentals(&mut self, enabled: bool) {
+        self.traditional_accidentals = enabled;
+    }
+
+    /// Enable or disable flat signs for komal sargam notes
+    #[wasm_bindgen(js_name = setSargamFlatSigns)]
+    pub fn set_sargam_flat_signs(&mut self, enabled: bool) {
+        self.sargam_flat_signs = enabled;
+    }
+
+    /// Calculate the displayed glyph of every cell of a line
+    #[wasm_bindgen(js_name = calculateDisplayGlyphs)]
+    pub fn calculate_display_glyphs(&self, line: &JsValue) -> Result<JsValue, JsValue> {
+        let line: Line = serde_wasm_bindgen::from_value(line.clone())
+            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+
+        let glyphs: Vec<String> = line.cells.iter().map(|cell| self.display_glyph(cell)).collect();
+        serde_wasm_bindgen::to_value(&glyphs)
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Enable or disable duration labels
+    #[wasm_bindgen(js_name = setShowDurations)]
+    pub fn set_show_durations(&mut self, show_durations: bool) {
+        self.show_durations = show_durations;
+    }
+
+    /// Check if duration labels are enabled
+    #[wasm_bindgen(js_name = getShowDurations)]
+    pub fn get_show_durations(&self) -> bool {
+        self.show_durations
+    }
+
+    /// Enable or disable subdivision ticks
+    #[wasm_bindgen(js_name = setShowSubdivisions)]
+    pub fn set_show_subdivisions(&mut self, show_subdivisions: bool) {
+        self.show_subdivisions = show_subdivisions;
+    }
+
+    /// Enable or disable cents labels on microtonal notes
+    #[wasm_bindgen(js_name = setShowCentsDeviation)]
+    pub fn set_show_cents_deviation(&mut self, show_cents_deviation: bool) {
+        self.show_cents_deviation = show_cents_deviation;
+    }
+
+    /// Enable or disable beat alignment across system lines
+    #[wasm_bindgen(js_name = setSmartSpacing)]
+    pub fn set_smart_spacing(&mut self, smart_spacing: bool) {
+        self.smart_spacing = smart_spacing;
+    }
+
     /// Set font size
     #[wasm_bindgen(js_name = setFontSize)]
     pub fn set_font_size(&mut self, font_size: f32) {
@@ -168,13 +429,533 @@ pub struct SlurPosition {
     pub direction: f32, // -1.0 for upward, 1.0 for downward
 }
 
+/// Duration label shown under a note or rest
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct DurationLabel {
+    /// Index of the cell that starts the event
+    pub cell_index: usize,
+    /// Written duration ("1/4"), with the tuplet count for tuplets ("1/8·3")
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Counting tick drawn under one subdivision of a beat
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct SubdivisionTick {
+    /// Index of the beat in the line
+    pub beat_index: usize,
+    /// Index of the note or dash cell
+    pub cell_index: usize,
+    /// Centre of the tick
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Cents deviation printed above a half-flat or half-sharp note
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct CentsLabel {
+    /// Index of the note cell
+    pub cell_index: usize,
+    /// Deviation from the equal-tempered note, e.g. "−50¢"
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Fermata glyph drawn above a held note
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct FermataMark {
+    /// Index of the note cell the fermata holds
+    pub cell_index: usize,
+    pub glyph: String,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// A chord tone stacked above its note
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct StackedPitchMark {
+    /// Index of the note cell carrying the stack
+    pub cell_index: usize,
+    pub glyph: String,
+    pub octave: i8,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Slash stroke drawn through an acciaccatura
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct GraceSlash {
+    /// Index of the grace note cell
+    pub cell_index: usize,
+    /// Lower-left end of the stroke
+    pub x1: f32,
+    pub y1: f32,
+    /// Upper-right end of the stroke
+    pub x2: f32,
+    pub y2: f32,
+}
+
+/// Fingering label drawn small above a note
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct FingeringMark {
+    /// Index of the note cell
+    pub cell_index: usize,
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Octave marker drawn with a displaced note
+///
+/// Dots style gives one mark per octave, stacked away from the note; the
+/// other styles give a single mark after the glyph.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct OctaveMark {
+    /// Index of the note cell
+    pub cell_index: usize,
+    pub glyph: String,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Traditional sargam accidental stroke
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccidentalMarkerKind {
+    /// Underline below a flattened note
+    Komal,
+    /// Vertical stroke above a sharpened note
+    Tivra,
+}
+
+/// Komal or tivra stroke drawn with a sargam note
+///
+/// `base_glyph` is the shuddha letter to draw in place of the cell glyph, so
+/// the stroke alone carries the alteration.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct AccidentalMarker {
+    /// Index of the note cell
+    pub cell_index: usize,
+    pub kind: AccidentalMarkerKind,
+    pub base_glyph: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Everything drawn around the cells of one line
+///
+/// Mark positions are relative to the line origin, with cell `i` at
+/// `i * char_width`; y is relative to the top of the cells.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LineMarks {
+    /// Displayed text of each cell
+    pub glyphs: Vec<String>,
+    pub accidentals: Vec<AccidentalMarker>,
+    pub octaves: Vec<OctaveMark>,
+    pub fingerings: Vec<FingeringMark>,
+    pub stacked_pitches: Vec<StackedPitchMark>,
+    pub grace_slashes: Vec<GraceSlash>,
+    pub fermatas: Vec<FermataMark>,
+    pub durations: Vec<DurationLabel>,
+    pub subdivisions: Vec<SubdivisionTick>,
+    pub cents: Vec<CentsLabel>,
+}
+
+/// Cell ranges of a line in alignment order
+///
+/// Each beat contributes four slots: the separator cells before it up to the
+/// first barline, that barline, the rest of the separator, and the beat
+/// itself. Cells after the last beat form three final separator slots.
+fn alignment_slots(cells: &[Cell], beats: &[BeatSpan]) -> Vec<Range<usize>> {
+    let mut slots = Vec::new();
+    let mut position = 0;
+
+    let push_separator = |slots: &mut Vec<Range<usize>>, start: usize, end: usize| {
+        match (start..end).find(|&index| cells[index].kind == ElementKind::Barline) {
+            Some(bar) => slots.extend([start..bar, bar..bar + 1, bar + 1..end]),
+            None => slots.extend([start..end, end..end, end..end]),
+        }
+    };
+
+    for beat in beats {
+        push_separator(&mut slots, position, beat.start);
+        slots.push(beat.start..beat.end + 1);
+        position = beat.end + 1;
+    }
+    push_separator(&mut slots, position, cells.len());
+    slots
+}
+
 impl LayoutRenderer {
+    /// Take the display settings of a document (see `applyDocumentSettings`)
+    pub fn use_document_settings(&mut self, document: &Document) {
+        self.octave_style = document.octave_style;
+        self.traditional_accidentals = document.traditional_accidentals;
+        self.sargam_flat_signs = document.sargam_flat_signs;
+        self.beat_separator = document.beat_separator;
+        self.tuning = document.tuning.clone();
+    }
+
+    /// Every mark of a line under the current settings
+    pub fn line_marks(&self, line: &Line) -> LineMarks {
+        LineMarks {
+            glyphs: line.cells.iter().map(|cell| self.display_glyph(cell)).collect(),
+            accidentals: self.accidental_markers(line),
+            octaves: self.octave_marks(line),
+            fingerings: self.fingering_marks(line),
+            stacked_pitches: self.stacked_pitch_marks(line),
+            grace_slashes: self.grace_slashes(line),
+            fermatas: self.fermata_marks(line),
+            durations: self.duration_labels(line),
+            subdivisions: self.subdivision_ticks(line),
+            cents: self.cents_labels(line, &self.tuning),
+        }
+    }
+
+    /// Position every cell of `lines`, aligning systems when smart spacing is on
+    pub fn layout_lines(&self, lines: &mut [Line]) {
+        for line in lines.iter_mut() {
+            for (index, cell) in line.cells.iter_mut().enumerate() {
+                cell.update_layout(index as f32 * self.char_width, 0.0, self.char_width, self.font_size);
+            }
+        }
+        if self.smart_spacing {
+            self.align_beats_across_systems(lines);
+        }
+    }
+
+    /// Lay out `start..end` of `lines` without positioning the whole document
+    ///
+    /// Smart spacing aligns all lines of a system together, so the range is
+    /// widened to whole systems before layout; the context lines are dropped
+    /// from the result.
+    pub fn layout_line_range(&self, lines: &[Line], start: usize, end: usize) -> Vec<Line> {
+        let end = end.min(lines.len());
+        let start = start.min(end);
+        if start == end {
+            return Vec::new();
+        }
+
+        let ids = compute_system_and_part_ids(lines);
+        let context_start = (0..start).rev().take_while(|&i| ids[i].0 == ids[start].0).last().unwrap_or(start);
+        let context_end = (end..lines.len()).take_while(|&i| ids[i].0 == ids[end - 1].0).last().map_or(end, |i| i + 1);
+
+        let mut context = lines[context_start..context_end].to_vec();
+        self.layout_lines(&mut context);
+        context.drain(..start - context_start);
+        context.truncate(end - start);
+        context
+    }
+
+    /// Re-position cells so that beat N and each barline start at the same x
+    /// on every line of a multi-staff system
+    ///
+    /// Every slot (see `alignment_slots`) is as wide as its widest instance
+    /// across the system; shorter lines are padded. Lines outside a grouped
+    /// system keep their positions.
+    pub fn align_beats_across_systems(&self, lines: &mut [Line]) {
+        let ids = compute_system_and_part_ids(lines);
+        let deriver = BeatDeriver::new();
+
+        let mut start = 0;
+        while start < lines.len() {
+            let end = (start..lines.len()).find(|&i| ids[i].0 != ids[start].0).unwrap_or(lines.len());
+            let system = &mut lines[start..end];
+            start = end;
+            if system.len() < 2 {
+                continue;
+            }
+
+            let slots: Vec<Vec<Range<usize>>> = system
+                .iter()
+                .map(|line| alignment_slots(&line.cells, &deriver.extract_implicit_beats(&line.cells)))
+                .collect();
+
+            let slot_count = slots.iter().map(Vec::len).max().unwrap_or(0);
+            let widths: Vec<usize> = (0..slot_count)
+                .map(|slot| slots.iter().filter_map(|line| line.get(slot)).map(Range::len).max().unwrap_or(0))
+                .collect();
+
+            for (line, line_slots) in system.iter_mut().zip(&slots) {
+                let mut slot_start = 0;
+                for (range, width) in line_slots.iter().zip(&widths) {
+                    for (offset, index) in range.clone().enumerate() {
+                        let cell = &mut line.cells[index];
+                        let x = (slot_start + offset) as f32 * self.char_width;
+                        cell.update_layout(x, cell.y, self.char_width, self.font_size);
+                    }
+                    slot_start += width;
+                }
+            }
+        }
+    }
+
+    /// Counting ticks under the beats of a line, one per subdivision
+    ///
+    /// Each note or dash of a beat is one subdivision; its tick is centred
+    /// under the cell. Beats are split by the document's beat separator. Empty unless subdivision display is enabled.
+    pub fn subdivision_ticks(&self, line: &Line) -> Vec<SubdivisionTick> {
+        if !self.show_subdivisions {
+            return Vec::new();
+        }
+
+        let mut ticks = Vec::new();
+        for (beat_index, beat) in BeatDeriver::with_separator(self.beat_separator).extract_implicit_beats(&line.cells).iter().enumerate() {
+            let end = beat.end.min(line.cells.len().saturating_sub(1));
+            for index in beat.start..=end {
+                let cell = &line.cells[index];
+                if matches!(cell.kind, ElementKind::PitchedElement | ElementKind::UnpitchedElement) {
+                    ticks.push(SubdivisionTick {
+                        beat_index,
+                        cell_index: index,
+                        x: (index as f32 + 0.5) * self.char_width,
+                        y: self.line_height * 0.75,
+                    });
+                }
+            }
+        }
+        ticks
+    }
+
+    /// Cents labels for the half-flat and half-sharp notes of a line
+    ///
+    /// The deviation is the quarter tone plus the temperament's offset of
+    /// the underlying semitone from equal temperament, rounded to whole
+    /// cents. Empty unless cents display is enabled.
+    pub fn cents_labels(&self, line: &Line, tuning: &TuningSettings) -> Vec<CentsLabel> {
+        if !self.show_cents_deviation {
+            return Vec::new();
+        }
+
+        let mut labels = Vec::new();
+        for (index, cell) in line.cells.iter().enumerate() {
+            if cell.kind != ElementKind::PitchedElement {
+                continue;
+            }
+            let pitch_code = cell.pitch_code.as_deref().unwrap_or(&cell.glyph);
+            let (_, quarter_tone) = split_quarter_tone(pitch_code);
+            let system = cell.pitch_system.unwrap_or_default();
+            let Some((degree, alter)) = parse_pitch_code(pitch_code, system).filter(|_| quarter_tone != 0) else {
+                continue;
+            };
+            let semitones = degree_semitones(degree, alter) as i32;
+            let temperament = tuning.cents_offset(semitones, 0) - tuning.cents_offset(0, 0);
+            let cents = (quarter_tone as f64 + temperament).round() as i32;
+            labels.push(CentsLabel {
+                cell_index: index,
+                text: format!("{}{}¢", if cents < 0 { "\u{2212}" } else { "+" }, cents.unsigned_abs()),
+                x: index as f32 * self.char_width,
+                y: -self.font_size * 0.75,
+            });
+        }
+        labels
+    }
+
+    /// Duration labels for every note and rest of a line, placed below the cells
+    ///
+    /// Beats are split by the document's beat separator, as on screen.
+    pub fn duration_labels(&self, line: &Line) -> Vec<DurationLabel> {
+        if !self.show_durations {
+            return Vec::new();
+        }
+
+        let mut labels = Vec::new();
+        for measure in build_export_measures_with_separator(line, self.beat_separator) {
+            for beat in &measure.beats {
+                for event in &beat.events {
+                    let written = beat.written_duration(event);
+                    let text = match beat.tuplet {
+                        Some(tuplet) => format!("{}·{}", written, tuplet.actual),
+                        None => written.to_string(),
+                    };
+                    labels.push(DurationLabel {
+                        cell_index: event.cell_index,
+                        text,
+                        x: event.cell_index as f32 * self.char_width,
+                        y: self.line_height,
+                    });
+                }
+            }
+        }
+        labels
+    }
+
+    /// Fermata glyphs for every held note of a line, placed above the note
+    ///
+    /// Notes are read with the document's beat separator, as in `duration_labels`.
+    pub fn fermata_marks(&self, line: &Line) -> Vec<FermataMark> {
+        build_export_measures_with_separator(line, self.beat_separator)
+            .iter()
+            .flat_map(|measure| measure.events())
+            .filter(|event| event.fermata)
+            .map(|event| FermataMark {
+                cell_index: event.cell_index,
+                glyph: FERMATA_GLYPH.to_string(),
+                x: event.cell_index as f32 * self.char_width,
+                y: -self.font_size * 0.75,
+            })
+            .collect()
+    }
+
+    /// Komal and tivra strokes for the sargam notes of a line
+    ///
+    /// Empty unless traditional markers are enabled. Only single alterations
+    /// have a traditional stroke; other notes keep their glyph.
+    pub fn accidental_markers(&self, line: &Line) -> Vec<AccidentalMarker> {
+        if !self.traditional_accidentals {
+            return Vec::new();
+        }
+
+        let mut markers = Vec::new();
+        for (index, cell) in line.cells.iter().enumerate() {
+            let system = cell.pitch_system.unwrap_or_default();
+            if cell.kind != ElementKind::PitchedElement || !matches!(system, PitchSystem::Sargam | PitchSystem::Bhatkhande) {
+                continue;
+            }
+            let Some((degree, alter)) = parse_pitch_code(cell.pitch_code.as_deref().unwrap_or(&cell.glyph), system) else {
+                continue;
+            };
+            let x = index as f32 * self.char_width;
+            let (kind, y, width, height) = match alter {
+                -1 => (AccidentalMarkerKind::Komal, self.font_size * 1.05, self.char_width, 1.0),
+                1 => (AccidentalMarkerKind::Tivra, -self.font_size * 0.45, 1.0, self.font_size * 0.35),
+                _ => continue,
+            };
+            markers.push(AccidentalMarker {
+                cell_index: index,
+                kind,
+                base_glyph: SHUDDHA_SARGAM[(degree.clamp(1, 7) - 1) as usize].to_string(),
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+        markers
+    }
+
+    /// Text drawn for a cell
+    ///
+    /// Normally the cell's glyph. With sargam flat signs enabled, a flattened
+    /// sargam note is drawn as its shuddha letter followed by one "♭" per
+    /// flat ("r" becomes "R♭"); the cell's pitch code is not affected.
+    pub fn display_glyph(&self, cell: &Cell) -> String {
+        let system = cell.pitch_system.unwrap_or_default();
+        let sargam = matches!(system, PitchSystem::Sargam | PitchSystem::Bhatkhande);
+        if !self.sargam_flat_signs || cell.kind != ElementKind::PitchedElement || !sargam {
+            return cell.glyph.clone();
+        }
+        match parse_pitch_code(cell.pitch_code.as_deref().unwrap_or(&cell.glyph), system) {
+            Some((degree, alter)) if alter < 0 => format!(
+                "{}{}",
+                SHUDDHA_SARGAM[(degree.clamp(1, 7) - 1) as usize],
+                "♭".repeat(alter.unsigned_abs() as usize)
+            ),
+            _ => cell.glyph.clone(),
+        }
+    }
+
+    /// Octave markers for the notes of a line in the current octave style
+    pub fn octave_marks(&self, line: &Line) -> Vec<OctaveMark> {
+        let mut marks = Vec::new();
+        for (index, cell) in line.cells.iter().enumerate() {
+            if cell.kind != ElementKind::PitchedElement || cell.octave == 0 {
+                continue;
+            }
+            let x = index as f32 * self.char_width;
+            let upper = cell.octave > 0;
+
+            match self.octave_style {
+                OctaveStyle::Dots => {
+                    for level in 0..cell.octave.unsigned_abs() {
+                        let offset = level as f32 * self.font_size * 0.25;
+                        marks.push(OctaveMark {
+                            cell_index: index,
+                            glyph: "•".to_string(),
+                            x,
+                            y: if upper { -self.font_size * 0.3 - offset } else { self.font_size * 1.1 + offset },
+                        });
+                    }
+                }
+                OctaveStyle::Abc | OctaveStyle::Numbers => {
+                    marks.push(OctaveMark {
+                        cell_index: index,
+                        glyph: self.octave_style.text_marker(cell.octave),
+                        x: x + self.char_width,
+                        y: if upper { -self.font_size * 0.3 } else { self.font_size * 0.3 },
+                    });
+                }
+            }
+        }
+        marks
+    }
+
+    /// Stacked chord tones of a line, one row per pitch above each note
+    pub fn stacked_pitch_marks(&self, line: &Line) -> Vec<StackedPitchMark> {
+        let mut marks = Vec::new();
+        for (index, cell) in line.cells.iter().enumerate() {
+            for (level, (pitch_code, octave)) in cell.extra_pitches.iter().enumerate() {
+                marks.push(StackedPitchMark {
+                    cell_index: index,
+                    glyph: pitch_code.clone(),
+                    octave: *octave,
+                    x: index as f32 * self.char_width,
+                    y: -((level + 1) as f32) * self.line_height,
+                });
+            }
+        }
+        marks
+    }
+
+    /// Slash strokes through the slashed grace notes of a line
+    ///
+    /// Grace notes are drawn at `CUE_SIZE_FACTOR`; the stroke crosses the
+    /// small glyph from its lower left to its upper right.
+    pub fn grace_slashes(&self, line: &Line) -> Vec<GraceSlash> {
+        let height = self.font_size * CUE_SIZE_FACTOR;
+        line.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.kind == ElementKind::PitchedElement && cell.ornament.grace_slash() == Some(true))
+            .map(|(index, _)| {
+                let x = index as f32 * self.char_width;
+                GraceSlash { cell_index: index, x1: x, y1: 0.0, x2: x + self.char_width, y2: -height }
+            })
+            .collect()
+    }
+
+    /// Fingering labels of a line, one row above any stacked pitches
+    pub fn fingering_marks(&self, line: &Line) -> Vec<FingeringMark> {
+        line.cells
+            .iter()
+            .enumerate()
+            .filter_map(|(index, cell)| {
+                Some(FingeringMark {
+                    cell_index: index,
+                    text: cell.fingering.clone()?,
+                    x: index as f32 * self.char_width,
+                    y: -((cell.extra_pitches.len() + 1) as f32) * self.line_height,
+                })
+            })
+            .collect()
+    }
+
     /// Calculate position for a single Cell
+    ///
+    /// Cue notes keep their column width but are drawn at
+    /// `CUE_SIZE_FACTOR` of the font size.
     pub fn calculate_cell_position(&self, cell: &Cell) -> (f32, f32, f32, f32) {
         let x = cell.col as f32 * self.char_width;
         let y = 0.0; // All cells on the same baseline now
+        let height = match cell.size {
+            NoteSize::Cue => self.font_size * CUE_SIZE_FACTOR,
+            NoteSize::Normal => self.font_size,
+        };
 
-        (x, y, self.char_width, self.font_size)
+        (x, y, self.char_width, height)
     }
 
     /// Calculate cursor position for rendering
@@ -204,4 +985,289 @@ impl Default for LayoutRenderer {
     fn default() -> Self {
         Self::new(16.0)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::grammar::parse_cells;
+
+    fn labels_for(text: &str) -> Vec<(usize, String)> {
+        let mut renderer = LayoutRenderer::default();
+        renderer.set_show_durations(true);
+        let mut line = Line::new();
+        line.cells = parse_cells(text, PitchSystem::Number);
+        renderer
+            .duration_labels(&line)
+            .into_iter()
+            .map(|label| (label.cell_index, label.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_quarter_and_extended_durations() {
+        assert_eq!(labels_for("1"), vec![(0, "1/4".to_string())]);
+        assert_eq!(labels_for("1--2"), vec![(0, "3/16".to_string()), (3, "1/16".to_string())]);
+    }
+
+    #[test]
+    fn test_duration_labels_follow_beat_separator() {
+        let mut renderer = LayoutRenderer::default();
+        renderer.set_show_durations(true);
+        let mut document = Document::new();
+        document.beat_separator = BeatSeparator::DoubleSpace;
+        renderer.use_document_settings(&document);
+
+        let mut line = Line::new();
+        line.cells = parse_cells("1 2  3", PitchSystem::Number);
+        let labels: Vec<String> = renderer.duration_labels(&line).into_iter().map(|label| label.text).collect();
+        assert_eq!(labels, vec!["1/8", "1/8", "1/4"]);
+    }
+
+    #[test]
+    fn test_grouped_lines_share_barline_positions() {
+        let renderer = LayoutRenderer::default();
+        let mut lines: Vec<Line> = ["1 2 | 3", "123 4 | 56"]
+            .iter()
+            .map(|text| {
+                let mut line = Line::new();
+                line.cells = parse_cells(text, PitchSystem::Number);
+                line
+            })
+            .collect();
+        lines[0].system_marker = SystemMarker::Start;
+        lines[1].system_marker = SystemMarker::End;
+
+        renderer.align_beats_across_systems(&mut lines);
+
+        let barline_x = |line: &Line| line.cells.iter().find(|c| c.kind == ElementKind::Barline).unwrap().x;
+        assert_eq!(barline_x(&lines[0]), barline_x(&lines[1]));
+        // The second beat starts at the same x on both lines
+        assert_eq!(lines[0].cells[2].x, lines[1].cells[4].x);
+        assert!(lines[0].cells[2].x > lines[0].cells[0].x + renderer.get_char_width());
+    }
+
+    #[test]
+    fn test_tuplet_labels_and_disabled_display() {
+        assert_eq!(labels_for("123").iter().map(|(_, text)| text.as_str()).collect::<Vec<_>>(), vec!["1/8·3"; 3]);
+
+        let mut line = Line::new();
+        line.cells = parse_cells("1 2", PitchSystem::Number);
+        assert!(LayoutRenderer::default().duration_labels(&line).is_empty());
+    }
+
+    #[test]
+    fn test_range_layout_matches_full_layout() {
+        let mut renderer = LayoutRenderer::default();
+        renderer.set_smart_spacing(true);
+        let texts = ["123 4 | 56", "1 2 | 3", "1-- 2", "5"];
+        let mut lines: Vec<Line> = (0..100)
+            .map(|index| {
+                let mut line = Line::new();
+                line.cells = parse_cells(texts[index % texts.len()], PitchSystem::Number);
+                // Pair the lines into two-staff systems
+                line.system_marker = if index % 2 == 0 { SystemMarker::Start } else { SystemMarker::End };
+                line
+            })
+            .collect();
+
+        let range = renderer.layout_line_range(&lines, 5, 11);
+        renderer.layout_lines(&mut lines);
+
+        assert_eq!(range.len(), 6);
+        let positions = |line: &Line| line.cells.iter().map(|cell| (cell.x, cell.y, cell.w)).collect::<Vec<_>>();
+        for (offset, line) in range.iter().enumerate() {
+            assert_eq!(positions(line), positions(&lines[5 + offset]), "line {}", 5 + offset);
+        }
+        // Line 5 is aligned with line 4, which lies outside the range
+        assert_ne!(range[0].cells[2].x, 2.0 * renderer.get_char_width());
+    }
+
+    #[test]
+    fn test_cue_notes_are_drawn_smaller() {
+        let renderer = LayoutRenderer::default();
+        let mut cells = parse_cells("1 2", PitchSystem::Number);
+        cells[2].size = NoteSize::Cue;
+
+        let (_, _, normal_width, normal_height) = renderer.calculate_cell_position(&cells[0]);
+        let (_, _, cue_width, cue_height) = renderer.calculate_cell_position(&cells[2]);
+        assert_eq!(cue_width, normal_width);
+        assert_eq!(cue_height, normal_height * CUE_SIZE_FACTOR);
+    }
+
+    #[test]
+    fn test_stacked_pitches_rise_above_the_note() {
+        let renderer = LayoutRenderer::default();
+        let mut line = Line::new();
+        line.cells = parse_cells("2 1", PitchSystem::Number);
+        line.cells[2].extra_pitches = vec![("3".to_string(), 0), ("5".to_string(), 0)];
+
+        let marks = renderer.stacked_pitch_marks(&line);
+        assert_eq!(marks.len(), 2);
+        assert!(marks.iter().all(|mark| mark.cell_index == 2 && mark.x == 2.0 * renderer.get_char_width()));
+        assert!(marks[1].y < marks[0].y && marks[0].y < 0.0);
+    }
+
+    #[test]
+    fn test_fingering_labels_sit_above_stacked_pitches() {
+        let renderer = LayoutRenderer::default();
+        let mut line = Line::new();
+        line.cells = parse_cells("1 2", PitchSystem::Number);
+        line.cells[0].fingering = Some("3".to_string());
+        line.cells[2].fingering = Some("1-2".to_string());
+        line.cells[2].extra_pitches = vec![("4".to_string(), 0)];
+
+        let marks = renderer.fingering_marks(&line);
+        assert_eq!(marks.iter().map(|m| (m.cell_index, m.text.as_str())).collect::<Vec<_>>(), vec![(0, "3"), (2, "1-2")]);
+        assert_eq!(marks[0].y, -renderer.line_height);
+        assert!(marks[1].y < renderer.stacked_pitch_marks(&line)[0].y);
+    }
+
+    #[test]
+    fn test_octave_marks_follow_style() {
+        let mut renderer = LayoutRenderer::default();
+        let mut line = Line::new();
+        line.cells = parse_cells("S R", PitchSystem::Sargam);
+        line.cells[0].octave = 2;
+        line.cells[2].octave = -1;
+
+        let dots = renderer.octave_marks(&line);
+        assert_eq!(dots.iter().map(|m| m.cell_index).collect::<Vec<_>>(), vec![0, 0, 2]);
+        assert!(dots[1].y < dots[0].y && dots[0].y < 0.0, "upper dots stack upwards");
+        assert!(dots[2].y > 0.0);
+
+        renderer.set_octave_style(OctaveStyle::Abc);
+        let ticks = renderer.octave_marks(&line);
+        assert_eq!(ticks.iter().map(|m| m.glyph.as_str()).collect::<Vec<_>>(), vec!["''", ","]);
+    }
+
+    #[test]
+    fn test_komal_and_tivra_markers() {
+        let mut renderer = LayoutRenderer::default();
+        let mut line = Line::new();
+        line.cells = parse_cells("S r M P", PitchSystem::Sargam);
+        assert!(renderer.accidental_markers(&line).is_empty(), "the mode is off by default");
+
+        renderer.set_traditional_accidentals(true);
+        let markers = renderer.accidental_markers(&line);
+
+        let found: Vec<(usize, AccidentalMarkerKind, &str)> =
+            markers.iter().map(|m| (m.cell_index, m.kind, m.base_glyph.as_str())).collect();
+        assert_eq!(
+            found,
+            vec![(2, AccidentalMarkerKind::Komal, "R"), (4, AccidentalMarkerKind::Tivra, "m")]
+        );
+        assert!(markers[0].y > 0.0 && markers[1].y < 0.0, "komal below, tivra above");
+
+        line.cells = parse_cells("1b 4#", PitchSystem::Number);
+        assert!(renderer.accidental_markers(&line).is_empty(), "number lines keep their accidentals");
+    }
+
+    #[test]
+    fn test_fermata_drawn_above_held_note() {
+        let renderer = LayoutRenderer::default();
+        let mut line = Line::new();
+        line.cells = parse_cells("1 2^", PitchSystem::Number);
+
+        let marks = renderer.fermata_marks(&line);
+        assert_eq!(marks.len(), 1);
+        assert_eq!(marks[0].cell_index, 2);
+        assert_eq!(marks[0].x, 2.0 * renderer.get_char_width());
+        assert!(marks[0].y < 0.0);
+    }
+
+    #[test]
+    fn test_komal_display_glyph_with_flat_signs() {
+        let mut renderer = LayoutRenderer::default();
+        let cells = parse_cells("S r M", PitchSystem::Sargam);
+        let shown = |renderer: &LayoutRenderer| cells.iter().map(|c| renderer.display_glyph(c)).collect::<Vec<_>>();
+        assert_eq!(shown(&renderer), vec!["S", " ", "r", " ", "M"]);
+
+        renderer.set_sargam_flat_signs(true);
+        assert_eq!(shown(&renderer), vec!["S", " ", "R♭", " ", "M"], "tivra Ma keeps its letter");
+        assert_eq!(cells[2].glyph, "r");
+
+        let number = parse_cells("3b", PitchSystem::Number);
+        assert_eq!(renderer.display_glyph(&number[0]), "3b");
+    }
+
+    #[test]
+    fn test_slash_drawn_through_acciaccatura_only() {
+        let renderer = LayoutRenderer::default();
+        let mut line = Line::new();
+        line.cells = parse_cells("1 2 3", PitchSystem::Number);
+        line.cells[0].ornament = OrnamentType::Appoggiatura;
+        line.cells[2].ornament = OrnamentType::Acciaccatura;
+
+        let slashes = renderer.grace_slashes(&line);
+        assert_eq!(slashes.len(), 1);
+        assert_eq!(slashes[0].cell_index, 2);
+        assert!(slashes[0].x2 > slashes[0].x1 && slashes[0].y2 < slashes[0].y1);
+    }
+
+    #[test]
+    fn test_cents_labels_follow_temperament() {
+        let mut renderer = LayoutRenderer::new(20.0);
+        let mut line = Line::new();
+        line.cells = parse_cells("1 3", PitchSystem::Number);
+        line.cells[2].pitch_code = Some("3hf".to_string());
+
+        let mut tuning = TuningSettings::default();
+        assert!(renderer.cents_labels(&line, &tuning).is_empty(), "off by default");
+
+        renderer.set_show_cents_deviation(true);
+        let labels = renderer.cents_labels(&line, &tuning);
+        assert_eq!(labels.len(), 1);
+        assert_eq!((labels[0].cell_index, labels[0].text.as_str()), (2, "\u{2212}50¢"));
+
+        // The just major third is 14 cents flat of equal temperament
+        tuning.temperament = crate::models::tuning::Temperament::JustIntonation;
+        assert_eq!(renderer.cents_labels(&line, &tuning)[0].text, "\u{2212}64¢");
+    }
+
+    #[test]
+    fn test_subdivision_ticks_under_a_beat_of_four() {
+        let mut renderer = LayoutRenderer::default();
+        let mut line = Line::new();
+        line.cells = parse_cells("1 2-34", PitchSystem::Number);
+        assert!(renderer.subdivision_ticks(&line).is_empty(), "off by default");
+
+        renderer.set_show_subdivisions(true);
+        let ticks = renderer.subdivision_ticks(&line);
+        let width = renderer.get_char_width();
+
+        let second_beat: Vec<f32> = ticks.iter().filter(|t| t.beat_index == 1).map(|t| t.x).collect();
+        assert_eq!(second_beat, vec![2.5 * width, 3.5 * width, 4.5 * width, 5.5 * width]);
+        assert_eq!(ticks.iter().filter(|t| t.beat_index == 0).count(), 1);
+        assert!(ticks.iter().all(|t| t.y > 0.0), "ticks sit below the cells");
+    }
+
+    #[test]
+    fn test_line_marks_follow_document_settings() {
+        let mut document = Document::new();
+        document.sargam_flat_signs = true;
+        document.traditional_accidentals = true;
+        document.octave_style = OctaveStyle::Numbers;
+        document.beat_separator = BeatSeparator::DoubleSpace;
+
+        let mut line = Line::new();
+        line.cells = parse_cells("S r  P", PitchSystem::Sargam);
+        line.cells[5].octave = 1;
+        line.cells[5].fingering = Some("3".to_string());
+
+        let mut renderer = LayoutRenderer::default();
+        renderer.set_show_subdivisions(true);
+        assert_eq!(renderer.line_marks(&line).glyphs[2], "r", "renderer defaults before the document is applied");
+
+        renderer.use_document_settings(&document);
+        let marks = renderer.line_marks(&line);
+        assert_eq!(marks.glyphs, vec!["S", " ", "R♭", " ", " ", "P"]);
+        assert_eq!(marks.accidentals.len(), 1);
+        assert_eq!(marks.octaves.iter().map(|m| m.glyph.as_str()).collect::<Vec<_>>(), vec!["¹"]);
+        assert_eq!(marks.fingerings[0].cell_index, 5);
+
+        // "S r" is one beat under the double-space separator
+        let beats: Vec<(usize, usize)> = marks.subdivisions.iter().map(|t| (t.beat_index, t.cell_index)).collect();
+        assert_eq!(beats, vec![(0, 0), (0, 2), (1, 5)]);
+    }
+}
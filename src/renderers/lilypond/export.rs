@@ -1,11 +1,230 @@
 //! LilyPond export functionality
 //!
-//! This module provides LilyPond export functionality.
+//! This module provides LilyPond export functionality. Each document line
+//! becomes one staff; rhythm comes from the export IR in `crate::ir`.
+//! Music is written in relative mode from `c'`, so a bare expression can be
+//! pasted into any `\relative c' { ... }` block.
+
+use crate::ir::{build_export_measures_for, ExportBeat, ExportEvent, ExportMeasure};
+use crate::models::{Document, PitchSystem};
+use crate::transposition::{parse_tonic, to_western_pitch, WesternPitch};
+use crate::utils::pitch_utils::parse_pitch_code;
+use super::notation::{LilyPondNotation, RELATIVE_REFERENCE};
+
+/// LilyPond language version written to exported files
+pub const LILYPOND_VERSION: &str = "2.24.0";
 
 pub struct LilyPondExport;
 
 impl LilyPondExport {
-    pub fn export_document(_document: &crate::models::Document) -> String {
-        "LilyPond export not implemented in POC".to_string()
+    /// Export a document as a complete LilyPond file
+    pub fn export_document(document: &Document) -> String {
+        let mut ly = String::new();
+        ly.push_str(&format!("\\version \"{}\"\n", LILYPOND_VERSION));
+
+        if document.title.is_some() || document.composer.is_some() {
+            ly.push_str("\\header {\n");
+            if let Some(title) = &document.title {
+                ly.push_str(&format!("  title = \"{}\"\n", escape_string(title)));
+            }
+            if let Some(composer) = &document.composer {
+                ly.push_str(&format!("  composer = \"{}\"\n", escape_string(composer)));
+            }
+            ly.push_str("}\n");
+        }
+
+        ly.push_str("\\score {\n  <<\n");
+        for line in &document.lines {
+            let tonic = document.effective_tonic(line).map(|t| t.as_str());
+            let measures = build_export_measures_for(document, line);
+
+            ly.push_str("    \\new Staff \\relative c' {\n");
+            ly.push_str(&format!("      \\key {} \\major\n", key_name(tonic)));
+            if let Some((beats, beat_type)) = line.time_signature.split_once('/') {
+                ly.push_str(&format!("      \\time {}/{}\n", beats.trim(), beat_type.trim()));
+            }
+            let music = music_expression(&measures, tonic);
+            if !music.is_empty() {
+                ly.push_str(&format!("      {}\n", music));
+            }
+            ly.push_str("    }\n");
+        }
+        ly.push_str("  >>\n  \\layout { }\n}\n");
+        ly
+    }
+}
+
+/// Escape text for a LilyPond string literal
+fn escape_string(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Written value of a grace note (an eighth)
+const GRACE_NOTE_VALUE: &str = "8";
+
+/// LilyPond note name of the key tonic ("d", "bes")
+fn key_name(tonic: Option<&str>) -> String {
+    let (step, alter) = parse_tonic(tonic.unwrap_or("C"));
+    let tonic = WesternPitch { step: ['C', 'D', 'E', 'F', 'G', 'A', 'B'][step], alter, octave: 4 };
+    LilyPondNotation::pitch_name(&tonic)
+}
+
+/// Bare relative-mode music expression for a list of measures
+///
+/// No `\score`, `\relative` or header is written; the first note is placed
+/// relative to `c'`.
+pub fn music_expression(measures: &[ExportMeasure], tonic: Option<&str>) -> String {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut previous = RELATIVE_REFERENCE;
+
+    for measure in measures {
+        for beat in &measure.beats {
+            write_beat(&mut tokens, beat, tonic, &mut previous);
+        }
+
+        match measure.barline.as_deref() {
+            Some("|") => tokens.push("|".to_string()),
+            Some("||") => tokens.push("\\bar \"||\"".to_string()),
+            Some("|||") => tokens.push("\\bar \"|.\"".to_string()),
+            Some(":|") => tokens.push("\\bar \":|.\"".to_string()),
+            Some("|:") => tokens.push("\\bar \".|:\"".to_string()),
+            _ => {}
+        }
+    }
+
+    tokens.join(" ")
+}
+
+/// Write the notes of one beat, wrapping tuplets in `\tuplet`
+fn write_beat(tokens: &mut Vec<String>, beat: &ExportBeat, tonic: Option<&str>, previous: &mut i32) {
+    let notes: Vec<String> = beat
+        .events
+        .iter()
+        .map(|event| note_token(event, beat, tonic, previous))
+        .collect();
+
+    match beat.tuplet {
+        Some(tuplet) if notes.len() > 1 => {
+            tokens.push(format!("\\tuplet {}/{} {{ {} }}", tuplet.actual, tuplet.normal, notes.join(" ")));
+        }
+        _ => tokens.extend(notes),
+    }
+}
+
+/// A single note or rest with duration, tie and slur marks
+///
+/// Grace notes take no time in the IR, so they are written as eighths under
+/// `\acciaccatura` (slashed) or `\appoggiatura`.
+fn note_token(event: &ExportEvent, beat: &ExportBeat, tonic: Option<&str>, previous: &mut i32) -> String {
+    let duration = match event.grace {
+        Some(_) => GRACE_NOTE_VALUE.to_string(),
+        None => LilyPondNotation::duration(beat.written_duration(event)),
+    };
+
+    let pitch = event.pitch.as_ref().and_then(|pitch| {
+        let (degree, alter) = parse_pitch_code(&pitch.pitch_code, pitch.pitch_system)?;
+        // Western pitch codes are absolute, everything else is relative to the tonic
+        let tonic = if pitch.pitch_system == PitchSystem::Western { None } else { tonic };
+        Some(to_western_pitch(degree, alter, pitch.octave, tonic))
+    });
+
+    let mut token = match pitch {
+        Some(pitch) => {
+            let marks = LilyPondNotation::relative_octave_marks(&pitch, *previous);
+            *previous = LilyPondNotation::diatonic_number(&pitch);
+            format!("{}{}{}", LilyPondNotation::pitch_name(&pitch), marks, duration)
+        }
+        None => format!("r{}", duration),
+    };
+
+    match event.grace {
+        Some(true) => token.insert_str(0, "\\acciaccatura "),
+        Some(false) => token.insert_str(0, "\\appoggiatura "),
+        None => {}
+    }
+    if pitch.is_some() && event.tie_start {
+        token.push('~');
+    }
+    if event.fermata {
+        token.push_str("\\fermata");
+    }
+    if event.slur_start {
+        token.push('(');
+    }
+    if event.slur_stop {
+        token.push(')');
+    }
+    if event.breath_mark {
+        token.push_str(" \\breathe");
+    }
+    token
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::build_export_measures_from_line;
+    use crate::models::{Document, Line, OrnamentType};
+    use crate::parse::grammar::parse_cells;
+
+    fn measures_from(text: &str) -> Vec<ExportMeasure> {
+        let mut line = Line::new();
+        line.cells = parse_cells(text, PitchSystem::Number);
+        build_export_measures_from_line(&line)
+    }
+
+    #[test]
+    fn test_music_expression_in_c() {
+        assert_eq!(music_expression(&measures_from("1 2 3"), Some("C")), "c4 d4 e4");
+    }
+
+    #[test]
+    fn test_selection_snippet_has_no_headers() {
+        let mut line = Line::new();
+        line.cells = parse_cells("5 1 2 3 |", PitchSystem::Number);
+
+        let snippet = music_expression(&crate::ir::build_export_measures_from_range(&Document::new(), &line, 2, 7), None);
+
+        assert_eq!(snippet, "c4 d4 e4");
+        assert!(!snippet.contains("\\version") && !snippet.contains("\\score"));
+    }
+
+    #[test]
+    fn test_ties_tuplets_and_barlines() {
+        let music = music_expression(&measures_from("1-2 | -"), None);
+        assert_eq!(music, "\\tuplet 3/2 { c4 d8~ } | d4");
+    }
+
+    #[test]
+    fn test_fermata_and_breath() {
+        assert_eq!(music_expression(&measures_from("1^ 2' 3"), Some("C")), "c4\\fermata d4 \\breathe e4");
+    }
+
+    #[test]
+    fn test_grace_notes_have_a_written_value() {
+        let mut line = Line::new();
+        line.cells = parse_cells("12 34", PitchSystem::Number);
+        line.cells[0].ornament = OrnamentType::Acciaccatura;
+        line.cells[4].ornament = OrnamentType::Appoggiatura;
+
+        let music = music_expression(&build_export_measures_from_line(&line), Some("C"));
+        assert_eq!(music, "\\acciaccatura c8 d4 e4 \\appoggiatura f8");
+    }
+
+    #[test]
+    fn test_export_document_wraps_staves() {
+        let mut document = Document::new();
+        document.title = Some("Bhairav".to_string());
+        document.tonic = Some("D".to_string());
+        let mut line = Line::new();
+        line.cells = parse_cells("1 5", PitchSystem::Number);
+        document.add_line(line);
+
+        let ly = LilyPondExport::export_document(&document);
+
+        assert!(ly.starts_with("\\version"));
+        assert!(ly.contains("title = \"Bhairav\""));
+        assert!(ly.contains("\\key d \\major"));
+        assert!(ly.contains("d4 a'4"));
     }
-}
\ No newline at end of file
+}
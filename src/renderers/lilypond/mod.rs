@@ -1,4 +1,4 @@
-//! LilyPond export (stub for POC)
+//! LilyPond export
 //!
 //! This module provides LilyPond export functionality.
 
@@ -12,7 +12,8 @@ pub use notation::*;
 pub struct LilyPondExporter;
 
 impl LilyPondExporter {
-    pub fn export(_document: &crate::models::Document) -> Result<String, String> {
-        Ok("LilyPond export not implemented in POC".to_string())
+    /// Export a document as a complete LilyPond file
+    pub fn export(document: &crate::models::Document) -> Result<String, String> {
+        Ok(LilyPondExport::export_document(document))
     }
-}
\ No newline at end of file
+}
@@ -1,11 +1,94 @@
 //! LilyPond notation mapping
 //!
-//! This module provides LilyPond notation mapping.
+//! This module maps spelled pitches and written durations to LilyPond
+//! note names ("cis", "bes"), relative octave marks and duration strings.
+
+use crate::ir::Fraction;
+use crate::transposition::WesternPitch;
+
+/// Step letters in order, used for relative octave distances
+const STEP_NAMES: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+
+/// Reference pitch of a `\relative c'` block (C4), as a diatonic step number
+pub const RELATIVE_REFERENCE: i32 = 4 * 7;
 
 pub struct LilyPondNotation;
 
 impl LilyPondNotation {
-    pub fn convert_to_lilypond(_pitch: &str) -> String {
-        "LilyPond notation conversion not implemented in POC".to_string()
+    /// LilyPond note name without octave marks (Dutch names: "cis", "ees")
+    pub fn pitch_name(pitch: &WesternPitch) -> String {
+        let accidental = match pitch.alter {
+            2 => "isis",
+            1 => "is",
+            -1 => "es",
+            -2 => "eses",
+            _ => "",
+        };
+        format!("{}{}", pitch.step.to_ascii_lowercase(), accidental)
+    }
+
+    /// Diatonic step number of a pitch (octave * 7 + step index)
+    pub fn diatonic_number(pitch: &WesternPitch) -> i32 {
+        let step = STEP_NAMES.iter().position(|&s| s == pitch.step).unwrap_or(0) as i32;
+        pitch.octave as i32 * 7 + step
+    }
+
+    /// Octave marks for `pitch` in relative mode after a note at `previous`
+    ///
+    /// Without marks LilyPond picks the octave closest to the previous note
+    /// (within a fourth); each `'` or `,` moves one octave from there.
+    pub fn relative_octave_marks(pitch: &WesternPitch, previous: i32) -> String {
+        let shift = (Self::diatonic_number(pitch) - previous + 3).div_euclid(7);
+        if shift >= 0 {
+            "'".repeat(shift as usize)
+        } else {
+            ",".repeat(shift.unsigned_abs() as usize)
+        }
+    }
+
+    /// LilyPond duration for a written value ("4", "8.", "2..")
+    ///
+    /// Values that cannot be written with dots fall back to the longest
+    /// plain note that fits.
+    pub fn duration(value: Fraction) -> String {
+        let (numerator, denominator) = (value.numerator, value.denominator.max(1));
+        match numerator {
+            1 => denominator.to_string(),
+            3 if denominator >= 2 => format!("{}.", denominator / 2),
+            7 if denominator >= 4 => format!("{}..", denominator / 4),
+            _ => denominator.div_ceil(numerator.max(1)).next_power_of_two().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pitch(step: char, alter: i8, octave: i8) -> WesternPitch {
+        WesternPitch { step, alter, octave }
+    }
+
+    #[test]
+    fn test_pitch_names() {
+        assert_eq!(LilyPondNotation::pitch_name(&pitch('C', 1, 4)), "cis");
+        assert_eq!(LilyPondNotation::pitch_name(&pitch('B', -1, 4)), "bes");
+        assert_eq!(LilyPondNotation::pitch_name(&pitch('E', 0, 4)), "e");
+    }
+
+    #[test]
+    fn test_relative_octave_marks() {
+        let c4 = RELATIVE_REFERENCE;
+        assert_eq!(LilyPondNotation::relative_octave_marks(&pitch('F', 0, 4), c4), "");
+        assert_eq!(LilyPondNotation::relative_octave_marks(&pitch('G', 0, 4), c4), "'");
+        assert_eq!(LilyPondNotation::relative_octave_marks(&pitch('G', 0, 3), c4), "");
+        assert_eq!(LilyPondNotation::relative_octave_marks(&pitch('C', 0, 3), c4), ",");
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(LilyPondNotation::duration(Fraction::new(1, 4)), "4");
+        assert_eq!(LilyPondNotation::duration(Fraction::new(3, 8)), "4.");
+        assert_eq!(LilyPondNotation::duration(Fraction::new(1, 2)), "2");
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,535 @@
+//! MIDI note events from the export IR
+//!
+//! Events are laid out on a tick grid from their exact durations. Each note
+//! is released before its full written length (`note_length_ratio`), so
+//! repeated notes are heard as separate attacks rather than one organ-like
+//! tone; staccato notes use the shorter `staccato_ratio`. Tied notes sound as
+//! one note and are shortened only at the end of the tie.
+//!
+//! With `meend_from_slur` a slur joining two different pitches is played as
+//! a meend (glide): the first note bends up or down to the second over its
+//! own duration and holds it, instead of two separate attacks. Bends use the
+//! General MIDI range of ±2 semitones, so wider slurs keep both attacks.
+//!
+//! With `pedal_from_slurs` each slur is also played with the sustain pedal:
+//! CC64 goes down (127) where the slur starts and up (0) at the written end
+//! of its last note. Overlapping or touching slurs share one pedal press.
+//!
+//! The document's tuning is played with pitch bends: before each attack the
+//! channel is bent by the note's deviation from equal temperament (its
+//! `TuningSettings::pitch_bend` over the line's tonic), so a just major third
+//! sounds about 14 cents flat. A bend is only sent when the amount changes;
+//! chords share the channel and take the bend of their first note.
+//!
+//! A note under a fermata is held `fermata_factor` times its written length
+//! and everything after it moves later by the extra time.
+//!
+//! Grace notes take no time in the IR. Each sounds for a thirty-second note
+//! taken from the start of the note after it, which is delayed to match (at
+//! most half of its length goes to its grace notes). Grace notes with no
+//! attack after them in the beat flow, before a rest, a tie or the end of the
+//! line, are not played.
+//!
+//! A line with a free-time direction ("rubato", "ad lib.") is still laid out
+//! on the strict grid; the score is flagged `free_time` so playback can
+//! choose between that straight reading and its own timing.
+
+use serde::{Deserialize, Serialize};
+use crate::ir::{build_export_measures_for, unfold_measure_repeats, ExportPitch, Fraction};
+use crate::models::tuning::{TuningSettings, PITCH_BEND_RANGE_CENTS};
+use crate::models::{Document, Line, PitchSystem};
+use crate::transposition::to_western_pitch;
+use crate::utils::pitch_utils::parse_pitch_code;
+
+/// Default ticks per quarter note
+pub const DEFAULT_TICKS_PER_QUARTER: u32 = 480;
+
+/// Default sounding share of a note's written length
+pub const DEFAULT_NOTE_LENGTH_RATIO: f64 = 0.9;
+
+/// Default sounding share of a staccato note's written length
+pub const DEFAULT_STACCATO_RATIO: f64 = 0.5;
+
+/// Default lengthening of a note under a fermata
+pub const DEFAULT_FERMATA_FACTOR: f64 = 1.75;
+
+/// Pitch-bend messages sent over the first note of a meend
+pub const MEEND_STEPS: u32 = 8;
+
+/// MIDI controller number of the sustain pedal
+pub const SUSTAIN_PEDAL: u8 = 64;
+
+/// Sounding length of a grace note, as a fraction of a whole note
+const GRACE_NOTE_LENGTH: (u32, u32) = (1, 32);
+
+/// Settings controlling MIDI note timing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct MidiOptions {
+    /// Ticks per quarter note
+    pub ticks_per_quarter: u32,
+
+    /// Sounding share of each note's written length (0.0-1.0)
+    pub note_length_ratio: f64,
+
+    /// Sounding share of a staccato note's written length (0.0-1.0)
+    pub staccato_ratio: f64,
+
+    /// Play a slur between two different pitches as a pitch-bend glide
+    pub meend_from_slur: bool,
+
+    /// Hold the sustain pedal (CC64) over each slur
+    pub pedal_from_slurs: bool,
+
+    /// Written length multiplier for a note under a fermata (1.0 or more)
+    pub fermata_factor: f64,
+}
+
+impl Default for MidiOptions {
+    fn default() -> Self {
+        Self {
+            ticks_per_quarter: DEFAULT_TICKS_PER_QUARTER,
+            note_length_ratio: DEFAULT_NOTE_LENGTH_RATIO,
+            staccato_ratio: DEFAULT_STACCATO_RATIO,
+            meend_from_slur: false,
+            pedal_from_slurs: false,
+            fermata_factor: DEFAULT_FERMATA_FACTOR,
+        }
+    }
+}
+
+/// One sounding note
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MidiNote {
+    /// MIDI key number (60 = middle C)
+    pub key: u8,
+    pub on_tick: u32,
+    pub off_tick: u32,
+}
+
+/// A pitch-bend message (14-bit signed amount, 0 = no bend)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MidiPitchBend {
+    pub tick: u32,
+    pub value: i16,
+}
+
+/// A control-change message
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MidiControlChange {
+    pub tick: u32,
+    pub controller: u8,
+    pub value: u8,
+}
+
+/// Timed MIDI output of a line
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MidiScore {
+    /// Notes in attack order
+    pub notes: Vec<MidiNote>,
+
+    /// Pitch bends in time order
+    pub pitch_bends: Vec<MidiPitchBend>,
+
+    /// Control changes (sustain pedal) in time order
+    pub control_changes: Vec<MidiControlChange>,
+
+    /// The line is marked free time ("rubato"); the ticks are a straight
+    /// reading that playback may stretch
+    pub free_time: bool,
+}
+
+/// A note whose release may still be pushed back by a tie or meend
+struct HeldNote {
+    keys: Vec<u8>,
+    on_tick: u32,
+    end_tick: u32,
+    staccato: bool,
+    /// Starts a slur that may turn into a meend
+    slur_start: bool,
+    /// Bent away from its key, so the bend is reset at release
+    bent: bool,
+    /// Pitch bend the tuning gives the first key
+    tuning_bend: i16,
+}
+
+/// Tuning pitch bends for one line
+struct Tuner<'a> {
+    tuning: &'a TuningSettings,
+    tonic_midi: i32,
+}
+
+impl Tuner<'_> {
+    fn bend(&self, key: u8) -> i16 {
+        self.tuning.pitch_bend(key as i32, self.tonic_midi)
+    }
+
+    /// Bend the channel for `key` at `tick`, unless it is already bent that far
+    fn tune(&self, key: u8, tick: u32, score: &mut MidiScore) -> i16 {
+        let value = self.bend(key);
+        if score.pitch_bends.last().map_or(0, |bend| bend.value) != value {
+            score.pitch_bends.push(MidiPitchBend { tick, value });
+        }
+        value
+    }
+}
+
+/// Note and pitch-bend events for one line of `document`
+///
+/// Measures are played in performed order, each as many times as its repeat
+/// count. Chord tones sound with their note. Rests and unspellable pitches
+/// are silent but still take their time.
+pub fn ir_to_midi_score(document: &Document, line: &Line, options: &MidiOptions) -> MidiScore {
+    let tonic = document.effective_tonic(line).map(String::as_str);
+    let ticks_per_whole = options.ticks_per_quarter as u64 * 4;
+    let tuner = Tuner {
+        tuning: &document.tuning,
+        tonic_midi: to_western_pitch(1, 0, 0, tonic).midi_number(),
+    };
+    let tick_at = |time: Fraction| (time.numerator as u64 * ticks_per_whole / time.denominator as u64) as u32;
+
+    let measures = unfold_measure_repeats(&build_export_measures_for(document, line));
+    let mut score = MidiScore { free_time: line.is_free_time(), ..MidiScore::default() };
+    let mut held: Option<HeldNote> = None;
+    let mut graces: Vec<Vec<u8>> = Vec::new();
+    let mut pedal: Vec<(u32, u32)> = Vec::new();
+    let mut pedal_start: Option<u32> = None;
+    let mut elapsed = Fraction::zero();
+    // Extra ticks added by fermatas so far
+    let mut held_over = 0u32;
+    let grace_ticks = tick_at(Fraction::new(GRACE_NOTE_LENGTH.0, GRACE_NOTE_LENGTH.1)).max(1);
+
+    for event in measures.iter().flat_map(|measure| &measure.beats).flat_map(|beat| &beat.events) {
+        let mut on_tick = tick_at(elapsed) + held_over;
+        elapsed = elapsed + event.duration;
+        if event.fermata {
+            let written = tick_at(elapsed) + held_over - on_tick;
+            held_over += (written as f64 * (options.fermata_factor.max(1.0) - 1.0)).round() as u32;
+        }
+        let end_tick = tick_at(elapsed) + held_over;
+
+        if event.slur_start {
+            pedal_start = pedal_start.or(Some(on_tick));
+        }
+        if let Some(start) = pedal_start.filter(|_| event.slur_stop) {
+            pedal.push((start, end_tick));
+            pedal_start = None;
+        }
+
+        let keys: Vec<u8> = event.pitch.iter().chain(&event.chord).filter_map(|pitch| midi_key(pitch, tonic)).collect();
+        if event.grace.is_some() {
+            if !keys.is_empty() {
+                graces.push(keys);
+            }
+            continue;
+        }
+        let pending = std::mem::take(&mut graces);
+
+        if let Some(note) = held.as_mut().filter(|_| event.tie_stop && event.pitch.is_some()) {
+            note.end_tick = end_tick;
+            note.staccato = event.staccato;
+            continue;
+        }
+        if options.meend_from_slur && event.slur_stop {
+            if let Some(note) = held.as_mut() {
+                if let Some(target) = meend_bend(note, &keys) {
+                    // Glide over the first note, then hold the second pitch
+                    // (in tune)
+                    let span = on_tick - note.on_tick;
+                    let from = note.tuning_bend as i32;
+                    let to = target as i32 + tuner.bend(keys[0]) as i32;
+                    for step in 1..=MEEND_STEPS {
+                        let tick = note.on_tick + span * step / MEEND_STEPS;
+                        let value = (from + (to - from) * step as i32 / MEEND_STEPS as i32).clamp(-8192, 8191) as i16;
+                        score.pitch_bends.push(MidiPitchBend { tick, value });
+                    }
+                    note.end_tick = end_tick;
+                    note.staccato = event.staccato;
+                    note.slur_start = false;
+                    note.bent = true;
+                    continue;
+                }
+            }
+        }
+
+        if let Some(note) = held.take() {
+            release(note, options, &mut score);
+        }
+        if !keys.is_empty() && !pending.is_empty() {
+            // The grace notes take the start of this note
+            let each = grace_ticks.min((end_tick - on_tick) / 2 / pending.len() as u32).max(1);
+            for keys in pending {
+                tuner.tune(keys[0], on_tick, &mut score);
+                for key in keys {
+                    score.notes.push(MidiNote { key, on_tick, off_tick: on_tick + each });
+                }
+                on_tick += each;
+            }
+        }
+        if !keys.is_empty() {
+            let tuning_bend = tuner.tune(keys[0], on_tick, &mut score);
+            held = Some(HeldNote {
+                keys,
+                on_tick,
+                end_tick,
+                staccato: event.staccato,
+                slur_start: event.slur_start,
+                bent: false,
+                tuning_bend,
+            });
+        }
+    }
+    if let Some(note) = held.take() {
+        release(note, options, &mut score);
+    }
+    if options.pedal_from_slurs {
+        score.control_changes = pedal_changes(pedal);
+    }
+
+    score
+}
+
+/// Sustain-pedal presses for `(start, end)` tick regions, merging overlaps
+fn pedal_changes(mut regions: Vec<(u32, u32)>) -> Vec<MidiControlChange> {
+    regions.sort_unstable();
+    let mut merged: Vec<(u32, u32)> = Vec::new();
+    for (start, end) in regions {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+        .into_iter()
+        .flat_map(|(start, end)| {
+            [
+                MidiControlChange { tick: start, controller: SUSTAIN_PEDAL, value: 127 },
+                MidiControlChange { tick: end, controller: SUSTAIN_PEDAL, value: 0 },
+            ]
+        })
+        .collect()
+}
+
+/// Bend amount that takes a slurred note to `keys`, if the pair can glide
+///
+/// Both sides must be single notes a different pitch apart, within the
+/// pitch-bend range.
+fn meend_bend(note: &HeldNote, keys: &[u8]) -> Option<i16> {
+    let (&[from], &[to]) = (note.keys.as_slice(), keys) else {
+        return None;
+    };
+    let cents = (to as f64 - from as f64) * 100.0;
+    if !note.slur_start || note.bent || from == to || cents.abs() > PITCH_BEND_RANGE_CENTS {
+        return None;
+    }
+    Some((cents / PITCH_BEND_RANGE_CENTS * 8192.0).round().clamp(-8192.0, 8191.0) as i16)
+}
+
+/// Emit a held note, shortened by the ratio for its articulation
+///
+/// A bent note resets the bend when it is released.
+fn release(note: HeldNote, options: &MidiOptions, score: &mut MidiScore) {
+    let ratio = if note.staccato { options.staccato_ratio } else { options.note_length_ratio };
+    let length = ((note.end_tick - note.on_tick) as f64 * ratio.clamp(0.0, 1.0)).round() as u32;
+    let off_tick = note.on_tick + length.max(1);
+
+    for key in note.keys {
+        score.notes.push(MidiNote { key, on_tick: note.on_tick, off_tick });
+    }
+    if note.bent {
+        score.pitch_bends.push(MidiPitchBend { tick: off_tick, value: 0 });
+    }
+}
+
+/// MIDI key of an exported pitch, or None if it cannot be spelled
+fn midi_key(pitch: &ExportPitch, tonic: Option<&str>) -> Option<u8> {
+    let (degree, alter) = parse_pitch_code(&pitch.pitch_code, pitch.pitch_system)?;
+    // Western pitch codes are absolute, everything else is relative to the tonic
+    let tonic = if pitch.pitch_system == PitchSystem::Western { None } else { tonic };
+    u8::try_from(to_western_pitch(degree, alter, pitch.octave, tonic).midi_number())
+        .ok()
+        .filter(|key| *key < 128)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{OrnamentType, SlurIndicator};
+    use crate::models::tuning::Temperament;
+    use crate::parse::grammar::parse_cells;
+
+    fn document_from(text: &str) -> Document {
+        document_in(text, PitchSystem::Number)
+    }
+
+    fn document_in(text: &str, pitch_system: PitchSystem) -> Document {
+        let mut document = Document::new();
+        document.pitch_system = Some(pitch_system);
+        let mut line = Line::new();
+        line.cells = parse_cells(text, pitch_system);
+        document.lines.push(line);
+        document
+    }
+
+    #[test]
+    fn test_notes_are_released_early_by_the_ratio() {
+        let document = document_from("1 2 3");
+        let notes = ir_to_midi_score(&document, &document.lines[0], &MidiOptions::default()).notes;
+
+        let ticks: Vec<(u8, u32, u32)> = notes.iter().map(|n| (n.key, n.on_tick, n.off_tick)).collect();
+        assert_eq!(ticks, vec![(60, 0, 432), (62, 480, 912), (64, 960, 1392)]);
+
+        let legato = MidiOptions { note_length_ratio: 1.0, ..MidiOptions::default() };
+        let full = ir_to_midi_score(&document, &document.lines[0], &legato).notes;
+        assert!(full.windows(2).all(|pair| pair[0].off_tick == pair[1].on_tick));
+    }
+
+    #[test]
+    fn test_staccato_note_is_released_earlier() {
+        let mut document = document_from("1 1");
+        document.lines[0].cells[2].staccato = true;
+
+        let notes = ir_to_midi_score(&document, &document.lines[0], &MidiOptions::default()).notes;
+        let normal = notes[0].off_tick - notes[0].on_tick;
+        let staccato = notes[1].off_tick - notes[1].on_tick;
+        assert_eq!((normal, staccato), (432, 240));
+    }
+
+    #[test]
+    fn test_tied_note_is_shortened_only_at_its_end() {
+        let document = document_from("1 -- 2");
+        let notes = ir_to_midi_score(&document, &document.lines[0], &MidiOptions::default()).notes;
+
+        assert_eq!(notes.len(), 2);
+        assert_eq!((notes[0].on_tick, notes[0].off_tick), (0, 864));
+        assert_eq!(notes[1].on_tick, 960);
+    }
+
+    #[test]
+    fn test_slur_between_two_pitches_glides_as_meend() {
+        let mut document = document_in("S R", PitchSystem::Sargam);
+        document.lines[0].cells[0].slur_indicator = SlurIndicator::SlurStart;
+        document.lines[0].cells[2].slur_indicator = SlurIndicator::SlurEnd;
+
+        let plain = ir_to_midi_score(&document, &document.lines[0], &MidiOptions::default());
+        assert_eq!(plain.notes.len(), 2);
+        assert!(plain.pitch_bends.is_empty());
+
+        let options = MidiOptions { meend_from_slur: true, ..MidiOptions::default() };
+        let score = ir_to_midi_score(&document, &document.lines[0], &options);
+        assert_eq!(score.notes, vec![MidiNote { key: 60, on_tick: 0, off_tick: 864 }], "one attack held through R");
+
+        let bends: Vec<(u32, i16)> = score.pitch_bends.iter().map(|b| (b.tick, b.value)).collect();
+        assert_eq!(bends.len(), MEEND_STEPS as usize + 1);
+        assert_eq!(bends[0], (60, 1023));
+        assert_eq!(bends[MEEND_STEPS as usize - 1], (480, 8191), "R reached when it would have sounded");
+        assert!(bends.windows(2).take(MEEND_STEPS as usize - 1).all(|pair| pair[0].1 < pair[1].1));
+        assert_eq!(bends[MEEND_STEPS as usize], (864, 0), "bend reset at release");
+    }
+
+    #[test]
+    fn test_grace_note_takes_the_start_of_the_next_note() {
+        let mut document = document_from("12 34");
+        document.lines[0].cells[0].ornament = OrnamentType::Acciaccatura;
+        document.lines[0].cells[4].ornament = OrnamentType::Appoggiatura;
+
+        let notes = ir_to_midi_score(&document, &document.lines[0], &MidiOptions::default()).notes;
+        let ticks: Vec<(u8, u32, u32)> = notes.iter().map(|n| (n.key, n.on_tick, n.off_tick)).collect();
+        // The trailing grace note has no attack after it and is not played
+        assert_eq!(ticks, vec![(60, 0, 60), (62, 60, 438), (64, 480, 912)]);
+        assert!(notes.iter().all(|note| note.off_tick > note.on_tick));
+    }
+
+    #[test]
+    fn test_measure_repeat_count_is_played() {
+        let mut document = document_from("1 | 2");
+        document.lines[0].set_measure_repeat_count(0, 3);
+
+        let notes = ir_to_midi_score(&document, &document.lines[0], &MidiOptions::default()).notes;
+        let played: Vec<(u8, u32)> = notes.iter().map(|n| (n.key, n.on_tick)).collect();
+        assert_eq!(played, vec![(60, 0), (60, 480), (60, 960), (62, 1440)]);
+    }
+
+    #[test]
+    fn test_just_intonation_bends_the_major_third() {
+        let mut document = document_from("1 3 1");
+        document.tuning.temperament = Temperament::JustIntonation;
+
+        let score = ir_to_midi_score(&document, &document.lines[0], &MidiOptions::default());
+        let bends: Vec<(u32, i16)> = score.pitch_bends.iter().map(|b| (b.tick, b.value)).collect();
+        assert_eq!(bends, vec![(480, -561), (960, 0)]);
+
+        let equal = document_from("1 3 1");
+        assert!(ir_to_midi_score(&equal, &equal.lines[0], &MidiOptions::default()).pitch_bends.is_empty());
+    }
+
+    #[test]
+    fn test_fermata_lengthens_its_note_and_shifts_the_rest() {
+        let document = document_from("1^ 2 3");
+        let notes = |options: &MidiOptions| -> Vec<(u8, u32, u32)> {
+            ir_to_midi_score(&document, &document.lines[0], options)
+                .notes
+                .iter()
+                .map(|n| (n.key, n.on_tick, n.off_tick))
+                .collect()
+        };
+
+        assert_eq!(notes(&MidiOptions::default()), vec![(60, 0, 756), (62, 840, 1272), (64, 1320, 1752)]);
+
+        let options = MidiOptions { fermata_factor: 2.0, ..MidiOptions::default() };
+        assert_eq!(notes(&options), vec![(60, 0, 864), (62, 960, 1392), (64, 1440, 1872)]);
+    }
+
+    #[test]
+    fn test_slurs_hold_the_sustain_pedal() {
+        let mut document = document_from("1 2 3 4 5");
+        let cells = &mut document.lines[0].cells;
+        cells[0].slur_indicator = SlurIndicator::SlurStart;
+        cells[2].slur_indicator = SlurIndicator::SlurEnd;
+        cells[6].slur_indicator = SlurIndicator::SlurStart;
+        cells[8].slur_indicator = SlurIndicator::SlurEnd;
+
+        let plain = ir_to_midi_score(&document, &document.lines[0], &MidiOptions::default());
+        assert!(plain.control_changes.is_empty());
+
+        let options = MidiOptions { pedal_from_slurs: true, ..MidiOptions::default() };
+        let changes: Vec<(u32, u8, u8)> = ir_to_midi_score(&document, &document.lines[0], &options)
+            .control_changes
+            .iter()
+            .map(|c| (c.tick, c.controller, c.value))
+            .collect();
+        assert_eq!(changes, vec![(0, 64, 127), (960, 64, 0), (1440, 64, 127), (2400, 64, 0)]);
+    }
+
+    #[test]
+    fn test_overlapping_pedal_regions_merge() {
+        let changes = pedal_changes(vec![(480, 1440), (0, 960), (1440, 1920), (2400, 2880)]);
+        let ticks: Vec<(u32, u8)> = changes.iter().map(|c| (c.tick, c.value)).collect();
+        assert_eq!(ticks, vec![(0, 127), (1920, 0), (2400, 127), (2880, 0)]);
+    }
+
+    #[test]
+    fn test_rubato_line_is_flagged_free_time() {
+        let mut document = document_from("1 2");
+        let straight = ir_to_midi_score(&document, &document.lines[0], &MidiOptions::default());
+        assert!(!straight.free_time);
+
+        document.lines[0].direction = "Ad lib.".to_string();
+        let free = ir_to_midi_score(&document, &document.lines[0], &MidiOptions::default());
+        assert!(free.free_time);
+        assert_eq!(free.notes, straight.notes, "played straight by default");
+    }
+
+    #[test]
+    fn test_wide_slur_keeps_both_attacks() {
+        let mut document = document_in("S P", PitchSystem::Sargam);
+        document.lines[0].cells[0].slur_indicator = SlurIndicator::SlurStart;
+        document.lines[0].cells[2].slur_indicator = SlurIndicator::SlurEnd;
+
+        let options = MidiOptions { meend_from_slur: true, ..MidiOptions::default() };
+        let score = ir_to_midi_score(&document, &document.lines[0], &options);
+        assert_eq!(score.notes.len(), 2);
+        assert!(score.pitch_bends.is_empty());
+    }
+}
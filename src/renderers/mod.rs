@@ -6,6 +6,11 @@
 pub mod layout;
 pub mod curves;
 pub mod svg;
+pub mod musicxml;
+pub mod lilypond;
+pub mod csv;
+pub mod text;
+pub mod midi;
 
 // Re-export commonly used types
 pub use layout::*;
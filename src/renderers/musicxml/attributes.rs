@@ -2,10 +2,56 @@
 //!
 //! This module provides MusicXML attribute handling.
 
+use crate::transposition::key_signature_fifths;
+
 pub struct MusicXMLAttributes;
 
+/// Clef written at the start of a part
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clef {
+    Treble,
+    /// Neutral clef of unpitched (percussion) parts
+    Percussion,
+}
+
+impl Clef {
+    /// MusicXML `<clef>` element
+    pub fn to_xml(self) -> &'static str {
+        match self {
+            Clef::Treble => "<clef><sign>G</sign><line>2</line></clef>",
+            Clef::Percussion => "<clef><sign>percussion</sign></clef>",
+        }
+    }
+}
+
 impl MusicXMLAttributes {
-    pub fn generate_attributes(_document: &crate::models::Document) -> String {
-        "<!-- MusicXML attributes not implemented in POC -->".to_string()
+    /// Key signature (number of fifths) of the major key on a tonic
+    pub fn key_fifths(tonic: Option<&str>) -> i8 {
+        key_signature_fifths(tonic)
+    }
+
+    /// Generate the `<attributes>` element for the first measure of a part
+    ///
+    /// `time_signature` is written as "beats/beat-type"; an empty or
+    /// malformed value omits the `<time>` element.
+    pub fn generate_attributes(divisions: u32, fifths: i8, time_signature: &str, clef: Clef) -> String {
+        let mut xml = String::new();
+        xml.push_str("      <attributes>\n");
+        xml.push_str(&format!("        <divisions>{}</divisions>\n", divisions));
+        xml.push_str(&format!("        <key><fifths>{}</fifths></key>\n", fifths));
+
+        if let Some((beats, beat_type)) = time_signature.split_once('/') {
+            let (beats, beat_type) = (beats.trim(), beat_type.trim());
+            if !beats.is_empty() && !beat_type.is_empty() {
+                xml.push_str(&format!(
+                    "        <time><beats>{}</beats><beat-type>{}</beat-type></time>\n",
+                    beats, beat_type
+                ));
+            }
+        }
+
+        xml.push_str(&format!("        {}\n", clef.to_xml()));
+        xml.push_str("      </attributes>\n");
+        xml
     }
-}
\ No newline at end of file
+}
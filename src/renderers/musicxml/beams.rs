@@ -0,0 +1,45 @@
+//! MusicXML beam computation
+//!
+//! Notes shorter than a quarter are beamed together within a beat. Rests
+//! and quarter-or-longer notes break the beam.
+
+use crate::ir::{BeamState, ExportBeat, ExportEvent, BEAT_DURATION};
+
+/// Check if an event can carry a beam
+fn is_beamable(event: &ExportEvent, beat: &ExportBeat) -> bool {
+    if event.is_rest() {
+        return false;
+    }
+    beat.written_duration(event) < BEAT_DURATION
+}
+
+/// Compute the level-1 beam state of every event in a beat
+pub fn compute_beam_states(beat: &ExportBeat) -> Vec<Option<BeamState>> {
+    let mut states = vec![None; beat.events.len()];
+    let mut run_start: Option<usize> = None;
+
+    for index in 0..=beat.events.len() {
+        let beamable = beat
+            .events
+            .get(index)
+            .map(|event| is_beamable(event, beat))
+            .unwrap_or(false);
+
+        match (beamable, run_start) {
+            (true, None) => run_start = Some(index),
+            (false, Some(start)) => {
+                if index - start >= 2 {
+                    states[start] = Some(BeamState::Begin);
+                    for state in states.iter_mut().take(index - 1).skip(start + 1) {
+                        *state = Some(BeamState::Continue);
+                    }
+                    states[index - 1] = Some(BeamState::End);
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    states
+}
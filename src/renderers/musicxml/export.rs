@@ -1,11 +1,883 @@
 //! MusicXML export functionality
 //!
-//! This module provides MusicXML export functionality.
+//! This module provides MusicXML export functionality. Each document line
+//! becomes one part; rhythm comes from the export IR in `crate::ir`.
+
+use crate::ir::{
+    build_export_measures_for, build_export_measures_from_range, distribute_lyrics, gcd, lcm, repeated_measures, ExportBeat, ExportEvent,
+    ExportMeasure, Fraction, LyricSyllable,
+};
+use crate::models::{compute_system_and_part_ids, Document, Line, PitchSystem};
+use crate::transposition::{spell_western_pitch, SpellingStrategy};
+use crate::utils::pitch_utils::parse_pitch_code;
+use super::attributes::{Clef, MusicXMLAttributes};
+use super::beams::compute_beam_states;
+use super::options::MusicXMLOptions;
 
 pub struct MusicXMLExport;
 
+/// XML declaration, doctype and opening tag of a partwise score
+const SCORE_HEADER: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n",
+    "<!DOCTYPE score-partwise PUBLIC \"-//Recordare//DTD MusicXML 4.0 Partwise//EN\" \"http://www.musicxml.org/dtds/partwise.dtd\">\n",
+    "<score-partwise version=\"4.0\">\n",
+);
+
 impl MusicXMLExport {
-    pub fn export_document(_document: &crate::models::Document) -> String {
-        "MusicXML export not implemented in POC".to_string()
+    /// Export a whole document as a MusicXML 4.0 partwise score
+    pub fn export_document(document: &Document) -> String {
+        Self::export_document_with_options(document, &MusicXMLOptions::default())
+    }
+
+    /// Export a whole document with explicit export settings
+    pub fn export_document_with_options(document: &Document, options: &MusicXMLOptions) -> String {
+        let ids = compute_system_and_part_ids(&document.lines);
+
+        let mut xml = String::from(SCORE_HEADER);
+
+        if let Some(title) = document.title.as_deref().filter(|t| !t.is_empty()) {
+            xml.push_str(&format!("  <work><work-title>{}</work-title></work>\n", escape_xml(title)));
+        }
+        if let Some(composer) = document.composer.as_deref().filter(|c| !c.is_empty()) {
+            xml.push_str(&format!(
+                "  <identification><creator type=\"composer\">{}</creator></identification>\n",
+                escape_xml(composer)
+            ));
+        }
+
+        xml.push_str(&part_list(document, &ids, options));
+
+        for (index, line) in document.lines.iter().enumerate() {
+            xml.push_str(&export_part(document, line, &ids[index].1, options));
+        }
+
+        xml.push_str("</score-partwise>\n");
+        xml
+    }
+
+    /// Export the cells from `start` to `end` as a single-part score
+    ///
+    /// Positions are (line, column) with `end` exclusive. Each selected line
+    /// adds its measures in order, cut to the selection with rhythm taken
+    /// from the whole line, so a multi-line selection becomes one run of
+    /// measures. The first selected line supplies the key and time
+    /// signature; lyrics are left out.
+    pub fn export_selection(
+        document: &Document,
+        start: (usize, usize),
+        end: (usize, usize),
+        options: &MusicXMLOptions,
+    ) -> Result<String, String> {
+        let (start, end) = if end < start { (end, start) } else { (start, end) };
+        if end.0 >= document.lines.len() {
+            return Err(format!("Line index {} out of bounds", end.0));
+        }
+
+        let lines = &document.lines[start.0..=end.0];
+        let mut measures = Vec::new();
+        for (offset, line) in lines.iter().enumerate() {
+            let from = if offset == 0 { start.1 } else { 0 };
+            let to = if offset == lines.len() - 1 { end.1 } else { line.cells.len() };
+            measures.extend(build_export_measures_from_range(document, line, from, to));
+        }
+
+        let first = &lines[0];
+        let mut xml = String::from(SCORE_HEADER);
+        xml.push_str("  <part-list>\n");
+        xml.push_str("    <score-part id=\"P1\">\n");
+        xml.push_str(&format!("      <part-name>{}</part-name>\n", escape_xml(&part_name(first, start.0))));
+        xml.push_str("    </score-part>\n");
+        xml.push_str("  </part-list>\n");
+        xml.push_str(&write_part(document, first, "P1", options, &measures, LyricCursor::empty()));
+        xml.push_str("</score-partwise>\n");
+        Ok(xml)
+    }
+}
+
+/// Write the `<part-list>`, wrapping multi-staff systems in part groups
+fn part_list(document: &Document, ids: &[(usize, String)], options: &MusicXMLOptions) -> String {
+    let mut xml = String::from("  <part-list>\n");
+    let mut group_number = 0;
+    let mut index = 0;
+
+    while index < document.lines.len() {
+        let system_id = ids[index].0;
+        let system_end = (index..document.lines.len())
+            .find(|&i| ids[i].0 != system_id)
+            .unwrap_or(document.lines.len());
+        let grouped = options.part_groups && system_end - index > 1;
+
+        if grouped {
+            group_number += 1;
+            xml.push_str(&format!("    <part-group type=\"start\" number=\"{}\">\n", group_number));
+            xml.push_str(&format!("      <group-symbol>{}</group-symbol>\n", options.group_symbol.as_str()));
+            xml.push_str("      <group-barline>yes</group-barline>\n");
+            xml.push_str("    </part-group>\n");
+        }
+
+        for (line_index, (line, (_, part_id))) in document.lines[index..system_end]
+            .iter()
+            .zip(&ids[index..system_end])
+            .enumerate()
+            .map(|(offset, pair)| (index + offset, pair))
+        {
+            xml.push_str(&format!("    <score-part id=\"{}\">\n", part_id));
+            xml.push_str(&format!("      <part-name>{}</part-name>\n", escape_xml(&part_name(line, line_index))));
+            xml.push_str("    </score-part>\n");
+        }
+
+        if grouped {
+            xml.push_str(&format!("    <part-group type=\"stop\" number=\"{}\"/>\n", group_number));
+        }
+        index = system_end;
+    }
+
+    xml.push_str("  </part-list>\n");
+    xml
+}
+
+/// Display name of a part (the line label, if set)
+fn part_name(line: &Line, line_index: usize) -> String {
+    if line.label.is_empty() {
+        format!("Part {}", line_index + 1)
+    } else {
+        line.label.clone()
+    }
+}
+
+/// Escape text for use in XML content and attribute values
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Divisions per quarter note that express every event duration exactly
+pub fn divisions_for(measures: &[ExportMeasure]) -> u32 {
+    measures
+        .iter()
+        .flat_map(|measure| measure.events())
+        .map(|event| event.duration.denominator / gcd(event.duration.denominator, 4))
+        .fold(1, lcm)
+}
+
+/// Duration of an event in MusicXML divisions
+fn duration_in_divisions(duration: Fraction, divisions: u32) -> u32 {
+    duration.numerator * 4 * divisions / duration.denominator
+}
+
+/// Written note type and dot count for a note spanning `subdivisions` of a
+/// beat written in `division` equal parts
+pub fn note_type(subdivisions: u32, division: u32) -> (&'static str, u32) {
+    note_type_for(Fraction::new(subdivisions, 4 * division.max(1)))
+}
+
+/// Written note type and dot count for a written value (fraction of a whole note)
+pub fn note_type_for(value: Fraction) -> (&'static str, u32) {
+    let (numerator, denominator) = (value.numerator, value.denominator);
+
+    match numerator {
+        1 => (type_name(denominator), 0),
+        3 if denominator >= 2 => (type_name(denominator / 2), 1),
+        7 if denominator >= 4 => (type_name(denominator / 4), 2),
+        _ => {
+            // Not expressible with dots: use the longest type that fits
+            let fitting = denominator.div_ceil(numerator).next_power_of_two();
+            (type_name(fitting), 0)
+        }
+    }
+}
+
+/// MusicXML type name for a note of duration 1/denominator
+fn type_name(denominator: u32) -> &'static str {
+    match denominator {
+        0 | 1 => "whole",
+        2 => "half",
+        4 => "quarter",
+        8 => "eighth",
+        16 => "16th",
+        32 => "32nd",
+        64 => "64th",
+        128 => "128th",
+        _ => "256th",
+    }
+}
+
+/// Tonic and spelling strategy used to spell the pitches of a part
+#[derive(Clone, Copy)]
+struct Spelling<'a> {
+    tonic: Option<&'a str>,
+    strategy: SpellingStrategy,
+    /// Unpitched part: notes are placed on the staff by degree with x noteheads
+    percussion: bool,
+}
+
+/// Lyric syllables of every verse, handed out one per sung note
+struct LyricCursor {
+    verses: Vec<Vec<LyricSyllable>>,
+    next: usize,
+}
+
+impl LyricCursor {
+    fn new(line: &Line) -> Self {
+        let verses = line.verses().into_iter().map(distribute_lyrics).collect();
+        Self { verses, next: 0 }
+    }
+
+    /// A cursor with no verses, for exports without lyrics
+    fn empty() -> Self {
+        Self { verses: Vec::new(), next: 0 }
+    }
+
+    /// Syllables sung on `event`, as (verse number, syllable) pairs
+    ///
+    /// Rests and tied continuations take no syllable.
+    fn take(&mut self, event: &ExportEvent) -> Vec<(usize, &LyricSyllable)> {
+        if event.is_rest() || event.tie_stop {
+            return Vec::new();
+        }
+        let index = self.next;
+        self.next += 1;
+        self.verses
+            .iter()
+            .enumerate()
+            .filter_map(|(verse, syllables)| Some((verse + 1, syllables.get(index)?)))
+            .collect()
+    }
+}
+
+/// Export one line as a `<part>`
+fn export_part(document: &Document, line: &Line, part_id: &str, options: &MusicXMLOptions) -> String {
+    let measures = build_export_measures_for(document, line);
+    write_part(document, line, part_id, options, &measures, LyricCursor::new(line))
+}
+
+/// Write measures as a `<part>`, with key, time and pitches taken from `line`
+fn write_part(
+    document: &Document,
+    line: &Line,
+    part_id: &str,
+    options: &MusicXMLOptions,
+    measures: &[ExportMeasure],
+    mut lyrics: LyricCursor,
+) -> String {
+    let divisions = divisions_for(measures);
+    // Tabla lines have no pitches, so they become unpitched percussion parts
+    let percussion = document.effective_pitch_system(line) == PitchSystem::Tabla;
+    let tonic = document.effective_tonic(line).map(|t| t.as_str()).filter(|_| !percussion);
+    let fifths = MusicXMLAttributes::key_fifths(tonic);
+    let spelling = Spelling { tonic, strategy: options.spelling, percussion };
+    let clef = if percussion { Clef::Percussion } else { Clef::Treble };
+
+    let mut xml = String::new();
+    xml.push_str(&format!("  <part id=\"{}\">\n", part_id));
+
+    if measures.is_empty() {
+        xml.push_str("    <measure number=\"1\">\n");
+        xml.push_str(&MusicXMLAttributes::generate_attributes(divisions, fifths, &line.time_signature, clef));
+        xml.push_str(&format!(
+            "      <note><rest measure=\"yes\"/><duration>{}</duration></note>\n",
+            4 * divisions
+        ));
+        xml.push_str("    </measure>\n");
+    }
+
+    let repeated = if options.collapse_repeats { repeated_measures(measures) } else { vec![false; measures.len()] };
+    let mut forward_repeat = false;
+    for (measure_index, measure) in measures.iter().enumerate() {
+        xml.push_str(&format!("    <measure number=\"{}\">\n", measure_index + 1));
+        if measure_index == 0 {
+            xml.push_str(&MusicXMLAttributes::generate_attributes(divisions, fifths, &line.time_signature, clef));
+            if !line.direction.trim().is_empty() {
+                xml.push_str(&format!(
+                    "      <direction placement=\"above\"><direction-type><words>{}</words></direction-type></direction>\n",
+                    escape_xml(line.direction.trim())
+                ));
+            }
+        }
+        let repeat_sign = repeated[measure_index];
+        let after_repeat_sign = measure_index > 0 && repeated[measure_index - 1];
+        if repeat_sign && !after_repeat_sign {
+            xml.push_str("      <attributes><measure-style><measure-repeat type=\"start\">1</measure-repeat></measure-style></attributes>\n");
+        } else if !repeat_sign && after_repeat_sign {
+            xml.push_str("      <attributes><measure-style><measure-repeat type=\"stop\"/></measure-style></attributes>\n");
+        }
+        if measure.repeat_count > 1 {
+            xml.push_str(&format!(
+                "      <direction placement=\"above\"><direction-type><words>×{}</words></direction-type></direction>\n",
+                measure.repeat_count
+            ));
+        }
+        if forward_repeat {
+            xml.push_str("      <barline location=\"left\"><bar-style>heavy-light</bar-style><repeat direction=\"forward\"/></barline>\n");
+            forward_repeat = false;
+        }
+
+        if repeat_sign {
+            // The sign stands for the notes (and their lyrics); a measure rest keeps the timing
+            for event in measure.events() {
+                lyrics.take(event);
+            }
+            xml.push_str(&format!(
+                "      <note><rest measure=\"yes\"/><duration>{}</duration></note>\n",
+                duration_in_divisions(measure.duration(), divisions)
+            ));
+        } else {
+            for beat in &measure.beats {
+                write_beat(&mut xml, beat, divisions, spelling, &mut lyrics);
+            }
+        }
+
+        match measure.barline.as_deref() {
+            Some("||") => {
+                xml.push_str("      <barline location=\"right\"><bar-style>light-light</bar-style></barline>\n");
+            }
+            Some("|||") => {
+                xml.push_str("      <barline location=\"right\"><bar-style>light-heavy</bar-style></barline>\n");
+            }
+            Some(":|") => {
+                xml.push_str("      <barline location=\"right\"><bar-style>light-heavy</bar-style><repeat direction=\"backward\"/></barline>\n");
+            }
+            Some("|:") => forward_repeat = true,
+            _ => {}
+        }
+
+        xml.push_str("    </measure>\n");
+    }
+
+    xml.push_str("  </part>\n");
+    xml
+}
+
+/// Write all notes of a beat
+fn write_beat(xml: &mut String, beat: &ExportBeat, divisions: u32, spelling: Spelling, lyrics: &mut LyricCursor) {
+    let beams = compute_beam_states(beat);
+    let last = beat.events.len().saturating_sub(1);
+
+    for (index, event) in beat.events.iter().enumerate() {
+        let tuplet_edge = match beat.tuplet {
+            Some(_) if beat.events.len() > 1 && index == 0 => Some("start"),
+            Some(_) if beat.events.len() > 1 && index == last => Some("stop"),
+            _ => None,
+        };
+        let beam = beams[index].map(|state| state.as_str());
+        let syllables = lyrics.take(event);
+        let marks = NoteMarks { beam, tuplet_edge, syllables: &syllables, chord: false };
+        write_note(xml, event, beat, divisions, spelling, marks);
+
+        // Stacked pitches follow as <chord/> notes carrying only rhythm and ties
+        for pitch in &event.chord {
+            let tone = ExportEvent {
+                pitch: Some(pitch.clone()),
+                slur_start: false,
+                slur_stop: false,
+                ornament: None,
+                fingering: None,
+                fermata: false,
+                breath_mark: false,
+                staccato: false,
+                chord: Vec::new(),
+                ..event.clone()
+            };
+            let marks = NoteMarks { beam: None, tuplet_edge: None, syllables: &[], chord: true };
+            write_note(xml, &tone, beat, divisions, spelling, marks);
+        }
+    }
+}
+
+/// Per-note markings decided by the surrounding beat
+struct NoteMarks<'a> {
+    beam: Option<&'a str>,
+    tuplet_edge: Option<&'a str>,
+    syllables: &'a [(usize, &'a LyricSyllable)],
+    /// Sounds together with the previous note
+    chord: bool,
+}
+
+/// Write a single `<note>` element
+fn write_note(
+    xml: &mut String,
+    event: &ExportEvent,
+    beat: &ExportBeat,
+    divisions: u32,
+    spelling: Spelling,
+    marks: NoteMarks,
+) {
+    xml.push_str("      <note>\n");
+    match event.grace {
+        Some(true) => xml.push_str("        <grace slash=\"yes\"/>\n"),
+        Some(false) => xml.push_str("        <grace/>\n"),
+        None => {}
+    }
+    if event.cue {
+        xml.push_str("        <cue/>\n");
+    }
+    if marks.chord {
+        xml.push_str("        <chord/>\n");
+    }
+
+    let pitch = event.pitch.as_ref().and_then(|pitch| {
+        let (degree, alter) = parse_pitch_code(&pitch.pitch_code, pitch.pitch_system)?;
+        // Western pitch codes are absolute and keep their written spelling;
+        // everything else is relative to the tonic
+        if pitch.pitch_system == PitchSystem::Western || spelling.percussion {
+            Some(spell_western_pitch(degree, alter, pitch.octave, None, SpellingStrategy::Degree))
+        } else {
+            Some(spell_western_pitch(degree, alter, pitch.octave, spelling.tonic, spelling.strategy))
+        }
+    });
+    match pitch {
+        Some(pitch) if spelling.percussion => {
+            xml.push_str("        <unpitched>\n");
+            xml.push_str(&format!("          <display-step>{}</display-step>\n", pitch.step));
+            xml.push_str(&format!("          <display-octave>{}</display-octave>\n", pitch.octave));
+            xml.push_str("        </unpitched>\n");
+        }
+        Some(pitch) => {
+            xml.push_str("        <pitch>\n");
+            xml.push_str(&format!("          <step>{}</step>\n", pitch.step));
+            if pitch.alter != 0 {
+                xml.push_str(&format!("          <alter>{}</alter>\n", pitch.alter));
+            }
+            xml.push_str(&format!("          <octave>{}</octave>\n", pitch.octave));
+            xml.push_str("        </pitch>\n");
+        }
+        None => xml.push_str("        <rest/>\n"),
+    }
+
+    // Grace notes take no time and are written as eighths
+    if event.grace.is_none() {
+        let duration = duration_in_divisions(event.duration, divisions);
+        xml.push_str(&format!("        <duration>{}</duration>\n", duration));
+    }
+
+    let tied = pitch.is_some();
+    if tied && event.tie_stop {
+        xml.push_str("        <tie type=\"stop\"/>\n");
+    }
+    if tied && event.tie_start {
+        xml.push_str("        <tie type=\"start\"/>\n");
+    }
+
+    let (type_name, dots) = match event.grace {
+        Some(_) => ("eighth", 0),
+        None => note_type_for(beat.written_duration(event)),
+    };
+    let size = if event.cue { " size=\"cue\"" } else { "" };
+    xml.push_str(&format!("        <type{}>{}</type>\n", size, type_name));
+    for _ in 0..dots {
+        xml.push_str("        <dot/>\n");
+    }
+
+    if let Some(tuplet) = beat.tuplet.filter(|_| event.grace.is_none()) {
+        xml.push_str(&format!(
+            "        <time-modification><actual-notes>{}</actual-notes><normal-notes>{}</normal-notes></time-modification>\n",
+            tuplet.actual, tuplet.normal
+        ));
+    }
+
+    if spelling.percussion && pitch.is_some() {
+        xml.push_str("        <notehead>x</notehead>\n");
+    }
+
+    if let Some(beam) = marks.beam {
+        xml.push_str(&format!("        <beam number=\"1\">{}</beam>\n", beam));
+    }
+
+    let mut notations = Vec::new();
+    if tied && event.tie_stop {
+        notations.push("<tied type=\"stop\"/>".to_string());
+    }
+    if tied && event.tie_start {
+        notations.push("<tied type=\"start\"/>".to_string());
+    }
+    if event.slur_start {
+        notations.push("<slur type=\"start\" number=\"1\"/>".to_string());
+    }
+    if event.slur_stop {
+        notations.push("<slur type=\"stop\" number=\"1\"/>".to_string());
+    }
+    if let Some(edge) = marks.tuplet_edge {
+        notations.push(format!("<tuplet type=\"{}\"/>", edge));
+    }
+    if let Some((ornament, element)) = event.ornament.and_then(|o| Some((o, o.musicxml_element()?))) {
+        let placement = if ornament.is_above() { "above" } else { "below" };
+        notations.push(format!("<ornaments><{} placement=\"{}\"/></ornaments>", element, placement));
+    }
+    if event.fermata {
+        notations.push("<fermata type=\"upright\"/>".to_string());
+    }
+    if event.staccato {
+        notations.push("<articulations><staccato/></articulations>".to_string());
+    }
+    if event.breath_mark {
+        notations.push("<articulations><breath-mark/></articulations>".to_string());
+    }
+    if let Some(fingering) = &event.fingering {
+        notations.push(format!("<technical><fingering>{}</fingering></technical>", escape_xml(fingering)));
+    }
+    if !notations.is_empty() {
+        xml.push_str(&format!("        <notations>{}</notations>\n", notations.join("")));
+    }
+
+    for (number, syllable) in marks.syllables {
+        let parts: Vec<String> = syllable
+            .elided_parts()
+            .into_iter()
+            .map(|(syllabic, text)| format!("<syllabic>{}</syllabic><text>{}</text>", syllabic.as_str(), escape_xml(text)))
+            .collect();
+        xml.push_str(&format!("        <lyric number=\"{}\">{}</lyric>\n", number, parts.join("<elision/>")));
+    }
+
+    xml.push_str("      </note>\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::edit::{set_note_size, toggle_grace_slash};
+    use crate::models::{NoteSize, OrnamentType, SystemMarker};
+    use crate::parse::grammar::parse_cells;
+
+    fn document_from(text: &str, pitch_system: PitchSystem) -> Document {
+        let mut document = Document::new();
+        document.pitch_system = Some(pitch_system);
+        let mut line = Line::new();
+        line.cells = parse_cells(text, pitch_system);
+        document.lines.push(line);
+        document
+    }
+
+    fn beam_values(xml: &str) -> Vec<&str> {
+        xml.split("<beam number=\"1\">")
+            .skip(1)
+            .map(|rest| &rest[..rest.find("</beam>").unwrap()])
+            .collect()
+    }
+
+    #[test]
+    fn test_four_sixteenths_are_beamed() {
+        let xml = MusicXMLExport::export_document(&document_from("1234", PitchSystem::Number));
+
+        assert_eq!(beam_values(&xml), vec!["begin", "continue", "continue", "end"]);
+        assert_eq!(xml.matches("<type>16th</type>").count(), 4);
+    }
+
+    #[test]
+    fn test_quarters_and_rests_are_not_beamed() {
+        // The breath mark stops the dash from continuing "2", so it is a rest
+        let xml = MusicXMLExport::export_document(&document_from("1 2 '-3", PitchSystem::Number));
+
+        assert!(beam_values(&xml).is_empty());
+        assert!(xml.contains("<rest/>"));
+    }
+
+    #[test]
+    fn test_beams_restart_per_beat() {
+        let xml = MusicXMLExport::export_document(&document_from("12 34", PitchSystem::Number));
+        assert_eq!(beam_values(&xml), vec!["begin", "end", "begin", "end"]);
+    }
+
+    #[test]
+    fn test_pitches_are_spelled_from_tonic() {
+        let mut document = document_from("1 7", PitchSystem::Number);
+        document.tonic = Some("D".to_string());
+        let xml = MusicXMLExport::export_document(&document);
+
+        assert!(xml.contains("<step>D</step>"));
+        assert!(xml.contains("<step>C</step>\n          <alter>1</alter>\n          <octave>5</octave>"));
+        assert!(xml.contains("<fifths>2</fifths>"));
+    }
+
+    #[test]
+    fn test_key_signature_spelling_option() {
+        let mut document = document_from("r", PitchSystem::Sargam);
+        document.tonic = Some("Gb".to_string());
+
+        let by_degree = MusicXMLExport::export_document(&document);
+        assert!(by_degree.contains("<step>A</step>\n          <alter>-2</alter>"));
+
+        let options = MusicXMLOptions { spelling: SpellingStrategy::KeySignature, ..Default::default() };
+        let by_key = MusicXMLExport::export_document_with_options(&document, &options);
+        assert!(by_key.contains("<step>G</step>\n          <octave>4</octave>"));
+    }
+
+    #[test]
+    fn test_base_duration_changes_written_values() {
+        let mut document = document_from("1-", PitchSystem::Number);
+        document.lines[0].base_duration = 4;
+        let xml = MusicXMLExport::export_document(&document);
+        assert!(xml.contains("<type>half</type>"));
+
+        document.lines[0].base_duration = 8;
+        let xml = MusicXMLExport::export_document(&document);
+        assert!(xml.contains("<type>quarter</type>"));
+    }
+
+    #[test]
+    fn test_ornaments_are_exported() {
+        let mut document = document_from("1 2 3", PitchSystem::Number);
+        document.lines[0].cells[0].ornament = OrnamentType::Mordent;
+        document.lines[0].cells[2].ornament = OrnamentType::Trill;
+        document.lines[0].cells[4].ornament = OrnamentType::Acciaccatura;
+
+        let xml = MusicXMLExport::export_document(&document);
+
+        assert!(xml.contains("<notations><ornaments><mordent placement=\"above\"/></ornaments></notations>"));
+        assert!(xml.contains("<ornaments><trill-mark placement=\"above\"/></ornaments>"));
+        assert_eq!(xml.matches("<ornaments>").count(), 2, "grace-note types are not ornaments");
+    }
+
+    #[test]
+    fn test_fermata_and_breath_mark_are_exported() {
+        let xml = MusicXMLExport::export_document(&document_from("1^ 2' 3", PitchSystem::Number));
+
+        assert!(xml.contains("<notations><fermata type=\"upright\"/></notations>"));
+        assert!(xml.contains("<notations><articulations><breath-mark/></articulations></notations>"));
+        assert_eq!(xml.matches("<note>").count(), 3);
+    }
+
+    fn steps(xml: &str) -> Vec<&str> {
+        xml.split("<step>").skip(1).map(|rest| &rest[..rest.find("</step>").unwrap()]).collect()
+    }
+
+    #[test]
+    fn test_export_four_note_selection() {
+        let document = document_from("1 2 3 4 | 5 6 7 1", PitchSystem::Number);
+
+        // "3 4 | 5 6": two notes either side of the barline
+        let xml = MusicXMLExport::export_selection(&document, (0, 4), (0, 13), &MusicXMLOptions::default()).unwrap();
+
+        assert_eq!(steps(&xml), vec!["E", "F", "G", "A"]);
+        assert_eq!(xml.matches("<note>").count(), 4);
+        assert_eq!(xml.matches("<measure ").count(), 2);
+        assert_eq!(xml.matches("<score-part ").count(), 1);
+        assert!(xml.ends_with("</score-partwise>\n"));
+    }
+
+    #[test]
+    fn test_export_selection_across_lines() {
+        let mut document = document_from("1 2 3", PitchSystem::Number);
+        let mut second = Line::new();
+        second.cells = parse_cells("4 5", PitchSystem::Number);
+        document.lines.push(second);
+
+        let xml = MusicXMLExport::export_selection(&document, (1, 1), (0, 2), &MusicXMLOptions::default()).unwrap();
+
+        assert_eq!(steps(&xml), vec!["D", "E", "F"], "reversed positions are normalized");
+        assert_eq!(xml.matches("<measure ").count(), 2, "each line adds its own measures");
+        assert!(MusicXMLExport::export_selection(&document, (0, 0), (2, 0), &MusicXMLOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_staccato_is_exported() {
+        let mut document = document_from("1 2", PitchSystem::Number);
+        document.lines[0].cells[2].staccato = true;
+
+        let xml = MusicXMLExport::export_document(&document);
+        let notes: Vec<&str> = xml.split("<note>").skip(1).collect();
+        assert!(!notes[0].contains("<staccato/>"));
+        assert!(notes[1].contains("<notations><articulations><staccato/></articulations></notations>"));
+    }
+
+    #[test]
+    fn test_fingering_is_exported() {
+        let mut document = document_from("1 2", PitchSystem::Number);
+        document.lines[0].cells[0].fingering = Some("3".to_string());
+        document.lines[0].cells[2].fingering = Some("1<2".to_string());
+
+        let xml = MusicXMLExport::export_document(&document);
+
+        assert!(xml.contains("<notations><technical><fingering>3</fingering></technical></notations>"));
+        assert!(xml.contains("<fingering>1&lt;2</fingering>"));
+    }
+
+    #[test]
+    fn test_stacked_pitches_export_as_chord() {
+        let mut document = document_from("1", PitchSystem::Number);
+        document.lines[0].cells[0].extra_pitches = vec![("3".to_string(), 0), ("5".to_string(), 0)];
+
+        let xml = MusicXMLExport::export_document(&document);
+
+        assert_eq!(xml.matches("<note>").count(), 3);
+        assert_eq!(xml.matches("<chord/>").count(), 2);
+        let steps: Vec<&str> = xml
+            .split("<step>")
+            .skip(1)
+            .map(|rest| &rest[..rest.find("</step>").unwrap()])
+            .collect();
+        assert_eq!(steps, vec!["C", "E", "G"]);
+        assert!(!xml.split("<note>").nth(1).unwrap().contains("<chord/>"), "the root has no <chord/>");
+    }
+
+    #[test]
+    fn test_cue_notes_are_exported() {
+        let mut document = document_from("1 2 | 3 4", PitchSystem::Number);
+        set_note_size(&mut document.lines[0].cells, 0, 3, NoteSize::Cue);
+        let xml = MusicXMLExport::export_document(&document);
+
+        assert_eq!(xml.matches("<cue/>").count(), 2);
+        assert_eq!(xml.matches("<type size=\"cue\">quarter</type>").count(), 2);
+        assert_eq!(xml.matches("<type>quarter</type>").count(), 2);
+        assert!(xml.contains("<note>\n        <cue/>\n        <pitch>"));
+    }
+
+    #[test]
+    fn test_grouped_system_exports_part_group() {
+        let mut document = document_from("1", PitchSystem::Number);
+        let mut second = Line::new();
+        second.cells = parse_cells("5", PitchSystem::Number);
+        second.system_marker = SystemMarker::End;
+        document.lines[0].system_marker = SystemMarker::Start;
+        document.lines.push(second);
+
+        let xml = MusicXMLExport::export_document(&document);
+        let part_list = &xml[xml.find("<part-list>").unwrap()..xml.find("</part-list>").unwrap()];
+
+        let start = part_list.find("<part-group type=\"start\" number=\"1\">").unwrap();
+        let stop = part_list.find("<part-group type=\"stop\" number=\"1\"/>").unwrap();
+        let p1 = part_list.find("<score-part id=\"P1\">").unwrap();
+        let p2 = part_list.find("<score-part id=\"P2\">").unwrap();
+        assert!(start < p1 && p1 < p2 && p2 < stop);
+        assert!(part_list.contains("<group-symbol>brace</group-symbol>"));
+    }
+
+    #[test]
+    fn test_ungrouped_lines_have_no_part_group() {
+        let mut document = document_from("1", PitchSystem::Number);
+        document.lines.push(document.lines[0].clone());
+
+        let xml = MusicXMLExport::export_document(&document);
+        assert!(!xml.contains("<part-group"));
+        assert!(xml.contains("<part id=\"P2\">"));
+    }
+
+    #[test]
+    fn test_note_types() {
+        assert_eq!(note_type(1, 1), ("quarter", 0));
+        assert_eq!(note_type(3, 4), ("eighth", 1));
+        assert_eq!(note_type(1, 2), ("eighth", 0));
+        assert_eq!(note_type(5, 8), ("eighth", 0));
+    }
+
+    #[test]
+    fn test_lyric_verses_are_numbered() {
+        let mut document = document_from("1 2- 3", PitchSystem::Number);
+        document.lines[0].set_lyric_verse(0, "Twin-kle star");
+        document.lines[0].set_lyric_verse(1, "How I");
+
+        let xml = MusicXMLExport::export_document(&document);
+
+        assert!(xml.contains("<lyric number=\"1\"><syllabic>begin</syllabic><text>Twin</text></lyric>"));
+        assert!(xml.contains("<lyric number=\"1\"><syllabic>end</syllabic><text>kle</text></lyric>"));
+        assert!(xml.contains("<lyric number=\"1\"><syllabic>single</syllabic><text>star</text></lyric>"));
+        assert!(xml.contains("<lyric number=\"2\"><syllabic>single</syllabic><text>How</text></lyric>"));
+        assert!(xml.contains("<lyric number=\"2\"><syllabic>single</syllabic><text>I</text></lyric>"));
+        assert_eq!(xml.matches("<lyric ").count(), 5);
+    }
+
+    #[test]
+    fn test_lyric_elision_and_short_verse() {
+        let mut document = document_from("1 2 3", PitchSystem::Number);
+        document.lines[0].set_lyric_verse(0, "Sing me_and you");
+        document.lines[0].set_lyric_verse(1, "Oh");
+
+        let xml = MusicXMLExport::export_document(&document);
+        let notes: Vec<&str> = xml.split("<note>").skip(1).collect();
+
+        assert!(notes[1].contains(
+            "<lyric number=\"1\"><syllabic>single</syllabic><text>me</text><elision/><syllabic>single</syllabic><text>and</text></lyric>"
+        ));
+        assert!(notes[0].contains("<lyric number=\"2\"><syllabic>single</syllabic><text>Oh</text></lyric>"));
+        assert!(!notes[1].contains("number=\"2\""), "verse 2 has no syllable left");
+        assert!(!notes[2].contains("number=\"2\""));
+        assert!(notes[2].contains("<lyric number=\"1\"><syllabic>single</syllabic><text>you</text></lyric>"));
+
+        document.lines[0].set_lyric_verse(1, "");
+        document.lines[0].set_lyric_verse(2, "La");
+        let xml = MusicXMLExport::export_document(&document);
+        assert!(xml.contains("<lyric number=\"3\">"), "an empty verse keeps later verse numbers");
+        assert!(!xml.contains("<lyric number=\"2\">"));
+    }
+
+    #[test]
+    fn test_measure_repeat_count_direction() {
+        let mut document = document_from("1 2 | 3 4", PitchSystem::Number);
+        document.lines[0].set_measure_repeat_count(1, 3);
+
+        let xml = MusicXMLExport::export_document(&document);
+
+        assert_eq!(xml.matches("<words>×3</words>").count(), 1);
+        let second_measure = &xml[xml.find("<measure number=\"2\">").unwrap()..];
+        assert!(second_measure.contains("<words>×3</words>"));
+    }
+
+    #[test]
+    fn test_line_direction_exported_as_words() {
+        let mut document = document_from("1 2 | 3 4", PitchSystem::Number);
+        document.lines[0].direction = "rubato".to_string();
+
+        let xml = MusicXMLExport::export_document(&document);
+
+        assert_eq!(xml.matches("<words>rubato</words>").count(), 1);
+        let first_measure = &xml[xml.find("<measure number=\"1\">").unwrap()..xml.find("<measure number=\"2\">").unwrap()];
+        let words = first_measure.find("<words>rubato</words>").unwrap();
+        assert!(words > first_measure.find("</attributes>").unwrap());
+        assert!(words < first_measure.find("<note>").unwrap(), "above the first note");
+    }
+
+    #[test]
+    fn test_identical_measures_collapse_to_measure_repeat() {
+        let document = document_from("1 2 3 4 | 1 2 3 4 | 5", PitchSystem::Number);
+
+        let plain = MusicXMLExport::export_document(&document);
+        assert_eq!(steps(&plain).len(), 9);
+        assert!(!plain.contains("<measure-repeat"));
+
+        let options = MusicXMLOptions { collapse_repeats: true, ..Default::default() };
+        let xml = MusicXMLExport::export_document_with_options(&document, &options);
+        assert_eq!(steps(&xml), vec!["C", "D", "E", "F", "G"]);
+
+        let second_measure = &xml[xml.find("<measure number=\"2\">").unwrap()..xml.find("<measure number=\"3\">").unwrap()];
+        assert!(second_measure.contains("<measure-repeat type=\"start\">1</measure-repeat>"));
+        assert!(second_measure.contains("<rest measure=\"yes\"/>"));
+        let third_measure = &xml[xml.find("<measure number=\"3\">").unwrap()..];
+        assert!(third_measure.contains("<measure-repeat type=\"stop\"/>"));
+    }
+
+    #[test]
+    fn test_tabla_line_exports_as_unpitched_percussion() {
+        let mut document = document_from("1 2", PitchSystem::Tabla);
+        document.tonic = Some("D".to_string());
+        let xml = MusicXMLExport::export_document(&document);
+
+        assert!(xml.contains("<clef><sign>percussion</sign></clef>"));
+        assert!(xml.contains("<fifths>0</fifths>"));
+        assert_eq!(xml.matches("<unpitched>").count(), 2);
+        assert!(xml.contains("<unpitched>\n          <display-step>C</display-step>\n          <display-octave>4</display-octave>"));
+        assert_eq!(xml.matches("<notehead>x</notehead>").count(), 2);
+        assert!(!xml.contains("<pitch>"));
+
+        let pitched = MusicXMLExport::export_document(&document_from("1 2", PitchSystem::Number));
+        assert!(pitched.contains("<clef><sign>G</sign><line>2</line></clef>"));
+        assert!(!pitched.contains("<unpitched>") && !pitched.contains("<notehead>"));
+    }
+
+    #[test]
+    fn test_grace_note_slash_is_exported() {
+        let mut document = document_from("12 3", PitchSystem::Number);
+        document.lines[0].cells[0].ornament = OrnamentType::Appoggiatura;
+
+        let plain = MusicXMLExport::export_document(&document);
+        assert!(plain.contains("<note>\n        <grace/>\n        <pitch>"));
+        assert!(!plain.contains("slash=\"yes\""));
+
+        toggle_grace_slash(&mut document.lines[0].cells, 0).unwrap();
+        let xml = MusicXMLExport::export_document(&document);
+        assert!(xml.contains("<note>\n        <grace slash=\"yes\"/>\n        <pitch>"));
+        assert_eq!(xml.matches("<duration>").count(), 2, "the grace note has no duration");
+        assert_eq!(xml.matches("<type>quarter</type>").count(), 2, "its time goes to the next note");
     }
-}
\ No newline at end of file
+}
@@ -1,18 +1,29 @@
-//! MusicXML export (stub for POC)
+//! MusicXML export
 //!
 //! This module provides MusicXML export functionality.
 
 pub mod export;
 pub mod attributes;
+pub mod beams;
+pub mod options;
 
 pub use export::*;
 pub use attributes::*;
+pub use beams::*;
+pub use options::*;
 
 /// MusicXML exporter
 pub struct MusicXMLExporter;
 
 impl MusicXMLExporter {
-    pub fn export(_document: &crate::models::Document) -> Result<String, String> {
-        Ok("MusicXML export not implemented in POC".to_string())
+    pub fn export(document: &crate::models::Document) -> Result<String, String> {
+        Self::export_with_options(document, &MusicXMLOptions::default())
     }
-}
\ No newline at end of file
+
+    pub fn export_with_options(
+        document: &crate::models::Document,
+        options: &MusicXMLOptions,
+    ) -> Result<String, String> {
+        Ok(MusicXMLExport::export_document_with_options(document, options))
+    }
+}
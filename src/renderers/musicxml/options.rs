@@ -0,0 +1,50 @@
+//! MusicXML export options
+
+use serde::{Deserialize, Serialize};
+use crate::transposition::SpellingStrategy;
+
+/// Symbol joining the staves of a multi-staff system
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum GroupSymbol {
+    #[default]
+    Brace,
+    Bracket,
+}
+
+impl GroupSymbol {
+    /// MusicXML `<group-symbol>` value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupSymbol::Brace => "brace",
+            GroupSymbol::Bracket => "bracket",
+        }
+    }
+}
+
+/// Settings controlling MusicXML export
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct MusicXMLOptions {
+    /// Wrap the parts of each multi-staff system in a `<part-group>`
+    pub part_groups: bool,
+
+    /// Symbol drawn for part groups
+    pub group_symbol: GroupSymbol,
+
+    /// Enharmonic spelling of tonic-relative (number, sargam) pitches
+    pub spelling: SpellingStrategy,
+
+    /// Draw measures identical to the one before as measure-repeat signs
+    pub collapse_repeats: bool,
+}
+
+impl Default for MusicXMLOptions {
+    fn default() -> Self {
+        Self {
+            part_groups: true,
+            group_symbol: GroupSymbol::Brace,
+            spelling: SpellingStrategy::Degree,
+            collapse_repeats: false,
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Plain-text export
+//!
+//! Writes each line back as the text a user would type, with octave
+//! markers in the document's `octave_style`. One output line per
+//! document line.
+
+use crate::models::{Document, Line, OctaveStyle};
+
+/// Export a document as plain text
+pub fn export_text(document: &Document) -> String {
+    document
+        .lines
+        .iter()
+        .map(|line| line_to_text(line, document.octave_style))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// One line as typed text, octave markers following each note
+pub fn line_to_text(line: &Line, style: OctaveStyle) -> String {
+    line.cells
+        .iter()
+        .map(|cell| cell.to_source_char() + &style.text_marker(cell.octave))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PitchSystem;
+    use crate::parse::grammar::parse_cells;
+
+    fn document_with(text: &str, system: PitchSystem, octave: i8) -> Document {
+        let mut line = Line::new();
+        line.cells = parse_cells(text, system);
+        line.cells[0].octave = octave;
+        let mut document = Document::new();
+        document.add_line(line);
+        document
+    }
+
+    #[test]
+    fn test_upper_octave_as_dot_in_sargam_style() {
+        let document = document_with("S R", PitchSystem::Sargam, 1);
+        assert_eq!(export_text(&document), "S\u{0307} R");
+    }
+
+    #[test]
+    fn test_upper_octave_as_tick_in_abc_style() {
+        let mut document = document_with("c d", PitchSystem::Western, 1);
+        document.octave_style = OctaveStyle::Abc;
+        assert_eq!(export_text(&document), "c' d");
+
+        document.lines[0].cells[0].octave = -2;
+        assert_eq!(export_text(&document), "c,, d");
+    }
+
+    #[test]
+    fn test_numbers_style_uses_super_and_subscripts() {
+        let mut document = document_with("1 2", PitchSystem::Number, 2);
+        document.octave_style = OctaveStyle::Numbers;
+        document.lines[0].cells[2].octave = -1;
+        assert_eq!(export_text(&document), "1² 2₁");
+    }
+}
@@ -0,0 +1,203 @@
+//! Diatonic transposition by scale steps
+//!
+//! Chromatic transposition moves every note by the same number of semitones.
+//! Diatonic transposition instead moves each note to the next member of a
+//! scale, so the semitone pattern of the scale decides each interval: a step
+//! up in major moves 1 to 2 (a whole tone) but 3 to 4 (a semitone).
+
+use crate::api::edit::MAX_OCTAVE_OFFSET;
+use crate::models::{Cell, ElementKind, PitchSystem};
+use crate::utils::pitch_utils::{degree_semitones, parse_pitch_code, pitch_class, pitch_code_for};
+
+/// The major scale as (degree, alteration) pairs
+pub const MAJOR_SCALE: [(u8, i8); 7] = [(1, 0), (2, 0), (3, 0), (4, 0), (5, 0), (6, 0), (7, 0)];
+
+/// Parse a scale written as space-separated number pitch codes ("1 2 3b 5 6")
+///
+/// An empty string is the major scale. Members are sorted by pitch and
+/// duplicates removed.
+pub fn parse_scale(text: &str) -> Result<Vec<(u8, i8)>, String> {
+    if text.trim().is_empty() {
+        return Ok(MAJOR_SCALE.to_vec());
+    }
+
+    let mut scale = text
+        .split_whitespace()
+        .map(|code| parse_pitch_code(code, PitchSystem::Number).ok_or_else(|| format!("'{}' is not a scale degree", code)))
+        .collect::<Result<Vec<_>, _>>()?;
+    scale.sort_by_key(|&(degree, alter)| pitch_class(degree, alter));
+    scale.dedup_by_key(|&mut (degree, alter)| pitch_class(degree, alter));
+    Ok(scale)
+}
+
+/// Look up a scale by name ("major", "minor", "harmonic-minor",
+/// "melodic-minor", "major-pentatonic", "minor-pentatonic"), or parse it as
+/// pitch codes
+///
+/// Melodic minor is its ascending form; see `descending_scale_by_name`.
+pub fn scale_by_name(name: &str) -> Result<Vec<(u8, i8)>, String> {
+    let codes = match name.trim().to_lowercase().as_str() {
+        "major" => "",
+        "minor" => "1 2 3b 4 5 6b 7b",
+        "harmonic-minor" => "1 2 3b 4 5 6b 7",
+        "melodic-minor" => "1 2 3b 4 5 6 7",
+        "major-pentatonic" => "1 2 3 5 6",
+        "minor-pentatonic" => "1 3b 4 5 7b",
+        _ => name,
+    };
+    parse_scale(codes)
+}
+
+/// The form of a named scale used on the way down
+///
+/// Melodic minor raises 6 and 7 only ascending and descends as the natural
+/// minor; every other scale is the same in both directions.
+pub fn descending_scale_by_name(name: &str) -> Result<Vec<(u8, i8)>, String> {
+    match name.trim().to_lowercase().as_str() {
+        "melodic-minor" => scale_by_name("minor"),
+        _ => scale_by_name(name),
+    }
+}
+
+/// Whether a pitch belongs to a named scale, given the pitch before it
+///
+/// Pitches are (degree, alter, octave). A pitch above `previous` is checked
+/// against the ascending form, one below it against the descending form, and
+/// a repeated pitch or a first note (no `previous`) against either.
+pub fn is_pitch_allowed(scale_name: &str, pitch: (u8, i8, i8), previous: Option<(u8, i8, i8)>) -> Result<bool, String> {
+    let height = |(degree, alter, octave): (u8, i8, i8)| degree_semitones(degree, alter) as i32 + 12 * octave as i32;
+    let contains = |scale: Vec<(u8, i8)>| scale.iter().any(|&(d, a)| pitch_class(d, a) == pitch_class(pitch.0, pitch.1));
+
+    let ascending = contains(scale_by_name(scale_name)?);
+    let descending = contains(descending_scale_by_name(scale_name)?);
+    Ok(match previous.map(|previous| height(pitch).cmp(&height(previous))) {
+        Some(std::cmp::Ordering::Greater) => ascending,
+        Some(std::cmp::Ordering::Less) => descending,
+        _ => ascending || descending,
+    })
+}
+
+/// Move a pitch `steps` scale members up (or down), returning (degree, alter, octave)
+///
+/// Returns None when the pitch is not a member of the scale.
+pub fn transpose_degree(degree: u8, alter: i8, octave: i8, steps: i32, scale: &[(u8, i8)]) -> Option<(u8, i8, i8)> {
+    let class = pitch_class(degree, alter);
+    let position = scale.iter().position(|&(d, a)| pitch_class(d, a) == class)? as i32;
+
+    let target = position + steps;
+    let len = scale.len() as i32;
+    let (new_degree, new_alter) = scale[target.rem_euclid(len) as usize];
+    let octave = octave as i32 + target.div_euclid(len);
+    Some((new_degree, new_alter, i8::try_from(octave).ok()?))
+}
+
+/// Transpose the notes in `start..end` by `steps` members of `scale`
+///
+/// Notes outside the scale, and notes that would leave the ±`MAX_OCTAVE_OFFSET`
+/// octave range, are left alone. Returns the number of notes moved.
+pub fn transpose_cells_diatonic(cells: &mut [Cell], start: usize, end: usize, steps: i32, scale: &[(u8, i8)]) -> usize {
+    let range = start.min(cells.len())..end.min(cells.len());
+    let mut moved = 0;
+
+    for cell in &mut cells[range] {
+        if cell.kind != ElementKind::PitchedElement {
+            continue;
+        }
+        let system = cell.pitch_system.unwrap_or_default();
+        let Some((degree, alter)) = parse_pitch_code(cell.pitch_code.as_deref().unwrap_or(&cell.glyph), system) else {
+            continue;
+        };
+        let Some((degree, alter, octave)) = transpose_degree(degree, alter, cell.octave, steps, scale) else {
+            continue;
+        };
+        if octave.abs() > MAX_OCTAVE_OFFSET {
+            continue;
+        }
+        let Some(code) = pitch_code_for(degree, alter, system) else {
+            continue;
+        };
+
+        cell.glyph = code.clone();
+        cell.pitch_code = Some(code);
+        cell.octave = octave;
+        moved += 1;
+    }
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::grammar::parse_cells;
+
+    fn glyphs(cells: &[Cell]) -> Vec<(&str, i8)> {
+        cells
+            .iter()
+            .filter(|cell| cell.kind == ElementKind::PitchedElement)
+            .map(|cell| (cell.glyph.as_str(), cell.octave))
+            .collect()
+    }
+
+    #[test]
+    fn test_step_up_in_major() {
+        let mut cells = parse_cells("1 3 5", PitchSystem::Number);
+        assert_eq!(transpose_cells_diatonic(&mut cells, 0, 5, 1, &MAJOR_SCALE), 3);
+        assert_eq!(glyphs(&cells), vec![("2", 0), ("4", 0), ("6", 0)]);
+
+        let mut western = parse_cells("c e g", PitchSystem::Western);
+        transpose_cells_diatonic(&mut western, 0, 5, 1, &MAJOR_SCALE);
+        assert_eq!(glyphs(&western), vec![("d", 0), ("f", 0), ("a", 0)]);
+    }
+
+    #[test]
+    fn test_steps_wrap_octaves() {
+        let mut cells = parse_cells("7 1", PitchSystem::Number);
+        transpose_cells_diatonic(&mut cells, 0, 3, 1, &MAJOR_SCALE);
+        assert_eq!(glyphs(&cells), vec![("1", 1), ("2", 0)]);
+
+        transpose_cells_diatonic(&mut cells, 0, 3, -2, &MAJOR_SCALE);
+        assert_eq!(glyphs(&cells), vec![("6", 0), ("7", -1)]);
+    }
+
+    #[test]
+    fn test_scale_with_omitted_degrees() {
+        let pentatonic = parse_scale("1 2 3 5 6").unwrap();
+        let mut cells = parse_cells("3 4 6", PitchSystem::Number);
+
+        assert_eq!(transpose_cells_diatonic(&mut cells, 0, 5, 1, &pentatonic), 2);
+        assert_eq!(glyphs(&cells), vec![("5", 0), ("4", 0), ("1", 1)], "4 is not in the scale");
+
+        assert_eq!(scale_by_name("Minor-Pentatonic").unwrap(), vec![(1, 0), (3, -1), (4, 0), (5, 0), (7, -1)]);
+        assert!(scale_by_name("lydian").is_err());
+        assert!(parse_scale("1 x").is_err());
+        assert_eq!(parse_scale("").unwrap(), MAJOR_SCALE.to_vec());
+    }
+
+    #[test]
+    fn test_melodic_minor_differs_by_direction() {
+        // In A minor, 6 is F#, 6b is F, 7 is G# and 7b is G
+        let e = (5, 0, 0);
+        let a_above = (1, 0, 1);
+        let allowed = |pitch, previous| is_pitch_allowed("melodic-minor", pitch, Some(previous)).unwrap();
+
+        assert!(allowed((6, 0, 0), e), "F# ascending");
+        assert!(allowed((7, 0, 0), (6, 0, 0)), "G# ascending");
+        assert!(!allowed((6, -1, 0), e), "F natural is not used ascending");
+
+        assert!(allowed((7, -1, 0), a_above), "G natural descending");
+        assert!(allowed((6, -1, 0), (7, -1, 0)), "F natural descending");
+        assert!(!allowed((7, 0, 0), a_above), "G# is not used descending");
+
+        assert!(is_pitch_allowed("melodic-minor", (7, -1, 0), None).unwrap());
+        assert!(!is_pitch_allowed("melodic-minor", (2, -1, 0), None).unwrap());
+        assert!(allowed((7, 0, 0), (7, 0, 0)), "a repeated note");
+    }
+
+    #[test]
+    fn test_harmonic_minor_is_the_same_both_ways() {
+        assert!(is_pitch_allowed("harmonic-minor", (7, 0, 0), Some((1, 0, 1))).unwrap());
+        assert!(!is_pitch_allowed("harmonic-minor", (7, -1, 0), Some((6, -1, 0))).unwrap());
+        assert_eq!(descending_scale_by_name("harmonic-minor").unwrap(), scale_by_name("harmonic-minor").unwrap());
+        assert!(is_pitch_allowed("lydian", (1, 0, 0), None).is_err());
+    }
+}
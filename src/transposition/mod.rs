@@ -0,0 +1,11 @@
+//! Transposition module for the Music Notation Editor
+//!
+//! This module converts scale degrees relative to a tonic into
+//! concrete western pitches for export, and moves notes by scale steps.
+
+pub mod degree_transpose;
+pub mod to_western_pitch;
+
+// Re-export commonly used types
+pub use degree_transpose::*;
+pub use to_western_pitch::*;
@@ -0,0 +1,279 @@
+//! Scale degree to western pitch conversion
+//!
+//! Number and sargam notation are relative to a tonic, so exporting them
+//! requires spelling each degree as a concrete step/alter/octave triple.
+
+use serde::{Deserialize, Serialize};
+use crate::utils::pitch_utils::degree_semitones;
+
+/// Natural-note semitone offsets of the steps C D E F G A B
+const STEP_SEMITONES: [i8; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+/// Step letters in order
+const STEP_NAMES: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+
+/// Circle-of-fifths position of each natural-note major key (C D E F G A B)
+const NATURAL_KEY_FIFTHS: [i8; 7] = [0, 2, 4, -1, 1, 3, 5];
+
+/// Steps in the order sharps are added to a key signature (F C G D A E B)
+const SHARP_ORDER: [usize; 7] = [3, 0, 4, 1, 5, 2, 6];
+
+/// How exported degrees choose between enharmonic spellings
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SpellingStrategy {
+    /// Spell every degree on its own letter (degree 3 is always the third letter)
+    #[default]
+    Degree,
+
+    /// Pick the spelling that needs the fewest accidentals against the key signature
+    KeySignature,
+}
+
+/// A spelled western pitch (MusicXML step/alter/octave)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WesternPitch {
+    /// Step letter (C-B)
+    pub step: char,
+
+    /// Chromatic alteration in semitones
+    pub alter: i8,
+
+    /// Scientific octave number (middle C = C4)
+    pub octave: i8,
+}
+
+impl WesternPitch {
+    /// MIDI note number (C4 = 60)
+    pub fn midi_number(&self) -> i32 {
+        let step_index = STEP_NAMES.iter().position(|&s| s == self.step).unwrap_or(0);
+        (self.octave as i32 + 1) * 12 + STEP_SEMITONES[step_index] as i32 + self.alter as i32
+    }
+
+    /// Note name with accidental symbols (e.g. "F#", "Bb")
+    pub fn name(&self) -> String {
+        let accidental = match self.alter {
+            2 => "##",
+            1 => "#",
+            -1 => "b",
+            -2 => "bb",
+            _ => "",
+        };
+        format!("{}{}", self.step, accidental)
+    }
+}
+
+/// Parse a tonic string ("C", "D#", "Bb", "f") into (step index, alter)
+///
+/// Unparseable or empty tonics fall back to C.
+pub fn parse_tonic(tonic: &str) -> (usize, i8) {
+    let mut chars = tonic.trim().chars();
+    let step = chars
+        .next()
+        .and_then(|c| STEP_NAMES.iter().position(|&s| s == c.to_ascii_uppercase()));
+
+    match step {
+        Some(step) => {
+            // Ignore anything after the accidental ("Bb major" -> Bb)
+            let accidental: String = chars.take_while(|c| matches!(c, '#' | 'b')).collect();
+            let alter = match accidental.as_str() {
+                "#" => 1,
+                "##" => 2,
+                "b" => -1,
+                "bb" => -2,
+                _ => 0,
+            };
+            (step, alter)
+        }
+        None => (0, 0),
+    }
+}
+
+/// Key signature (number of fifths) of the major key on a tonic
+pub fn key_signature_fifths(tonic: Option<&str>) -> i8 {
+    let (step, alter) = parse_tonic(tonic.unwrap_or("C"));
+    NATURAL_KEY_FIFTHS[step] + 7 * alter
+}
+
+/// Alteration the key signature applies to each step (C D E F G A B)
+///
+/// Keys beyond seven sharps or flats produce double accidentals.
+pub fn key_signature_alters(fifths: i8) -> [i8; 7] {
+    let mut alters = [0; 7];
+    for index in 0..fifths.unsigned_abs() as usize {
+        if fifths > 0 {
+            alters[SHARP_ORDER[index % 7]] += 1;
+        } else {
+            alters[SHARP_ORDER[6 - index % 7]] -= 1;
+        }
+    }
+    alters
+}
+
+/// Spell a scale degree relative to a tonic using the given strategy
+///
+/// `SpellingStrategy::Degree` is the same as [`to_western_pitch`].
+/// `SpellingStrategy::KeySignature` keeps the sounding pitch but may move it
+/// to a neighbouring letter: komal Re in Gb spells as G rather than Abb.
+/// Ties prefer the fewest accidentals overall, then the degree spelling.
+pub fn spell_western_pitch(
+    degree: u8,
+    alter: i8,
+    octave: i8,
+    tonic: Option<&str>,
+    strategy: SpellingStrategy,
+) -> WesternPitch {
+    let by_degree = to_western_pitch(degree, alter, octave, tonic);
+    if strategy == SpellingStrategy::Degree {
+        return by_degree;
+    }
+
+    let key_alters = key_signature_alters(key_signature_fifths(tonic));
+    let midi = by_degree.midi_number();
+    let cost = |pitch: &WesternPitch| {
+        let step = STEP_NAMES.iter().position(|&s| s == pitch.step).unwrap_or(0);
+        ((pitch.alter - key_alters[step]).abs(), pitch.alter.abs())
+    };
+
+    let mut best = by_degree;
+    for step in 0..7 {
+        let pitch_class = (midi - STEP_SEMITONES[step] as i32).rem_euclid(12);
+        let alter = if pitch_class > 6 { pitch_class - 12 } else { pitch_class };
+        if alter.abs() > 2 {
+            continue;
+        }
+        let candidate = WesternPitch {
+            step: STEP_NAMES[step],
+            alter: alter as i8,
+            octave: ((midi - STEP_SEMITONES[step] as i32 - alter) / 12 - 1) as i8,
+        };
+        if cost(&candidate) < cost(&best) {
+            best = candidate;
+        }
+    }
+    best
+}
+
+/// Spell a scale degree relative to a tonic as a western pitch
+///
+/// The step is always `degree - 1` letters above the tonic letter, so degree
+/// 7 in D major spells as C# rather than Db. `octave` is the cell octave
+/// (0 = the octave starting at the tonic above middle C).
+pub fn to_western_pitch(degree: u8, alter: i8, octave: i8, tonic: Option<&str>) -> WesternPitch {
+    let (tonic_step, tonic_alter) = parse_tonic(tonic.unwrap_or("C"));
+
+    let step_index = tonic_step + (degree.clamp(1, 7) as usize - 1);
+    let wraps = (step_index / 7) as i8;
+    let step = step_index % 7;
+
+    let semitones_above_c4 = STEP_SEMITONES[tonic_step] + tonic_alter
+        + degree_semitones(degree, alter)
+        + 12 * octave;
+    let natural_above_c4 = STEP_SEMITONES[step] + 12 * (wraps + octave);
+
+    WesternPitch {
+        step: STEP_NAMES[step],
+        alter: semitones_above_c4 - natural_above_c4,
+        octave: 4 + octave + wraps,
+    }
+}
+
+/// Scale degree, alteration and cell octave of a MIDI note (the inverse of `to_western_pitch`)
+///
+/// Chromatic notes are spelled as a raised lower degree or, with
+/// `prefer_flats`, a lowered upper degree ("1#" or "2b").
+pub fn degree_from_midi(midi: i32, tonic: Option<&str>, prefer_flats: bool) -> (u8, i8, i8) {
+    const SHARPS: [(u8, i8); 12] =
+        [(1, 0), (1, 1), (2, 0), (2, 1), (3, 0), (4, 0), (4, 1), (5, 0), (5, 1), (6, 0), (6, 1), (7, 0)];
+    const FLATS: [(u8, i8); 12] =
+        [(1, 0), (2, -1), (2, 0), (3, -1), (3, 0), (4, 0), (5, -1), (5, 0), (6, -1), (6, 0), (7, -1), (7, 0)];
+
+    let tonic_midi = to_western_pitch(1, 0, 0, tonic).midi_number();
+    let offset = midi - tonic_midi;
+    let (degree, alter) = if prefer_flats { FLATS } else { SHARPS }[offset.rem_euclid(12) as usize];
+    (degree, alter, offset.div_euclid(12) as i8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_degrees_in_c() {
+        let e = to_western_pitch(3, 0, 0, Some("C"));
+        assert_eq!((e.step, e.alter, e.octave), ('E', 0, 4));
+        assert_eq!(e.midi_number(), 64);
+
+        let d_flat = to_western_pitch(2, -1, 0, None);
+        assert_eq!(d_flat.name(), "Db");
+    }
+
+    #[test]
+    fn test_degree_from_midi_inverts_to_western_pitch() {
+        assert_eq!(degree_from_midi(64, Some("C"), false), (3, 0, 0));
+        assert_eq!(degree_from_midi(61, Some("C"), false), (1, 1, 0));
+        assert_eq!(degree_from_midi(61, Some("C"), true), (2, -1, 0));
+        assert_eq!(degree_from_midi(73, Some("D"), false), (7, 0, 0));
+        assert_eq!(degree_from_midi(50, Some("D"), false), (1, 0, -1));
+
+        for midi in 40..90 {
+            let (degree, alter, octave) = degree_from_midi(midi, Some("Eb"), true);
+            assert_eq!(to_western_pitch(degree, alter, octave, Some("Eb")).midi_number(), midi);
+        }
+    }
+
+    #[test]
+    fn test_degrees_wrap_octave_above_tonic() {
+        let c_sharp = to_western_pitch(7, 0, 0, Some("D"));
+        assert_eq!((c_sharp.step, c_sharp.alter, c_sharp.octave), ('C', 1, 5));
+        assert_eq!(c_sharp.midi_number(), 73);
+    }
+
+    #[test]
+    fn test_key_signature_spelling_of_komal_re() {
+        let in_c = spell_western_pitch(2, -1, 0, Some("C"), SpellingStrategy::KeySignature);
+        assert_eq!(in_c.name(), "Db");
+
+        let in_a = spell_western_pitch(2, -1, 0, Some("A"), SpellingStrategy::KeySignature);
+        assert_eq!((in_a.name(), in_a.octave), ("Bb".to_string(), 4));
+
+        // Abb is avoided when a plain letter of the key sounds the same
+        let in_g_flat = spell_western_pitch(2, -1, 0, Some("Gb"), SpellingStrategy::KeySignature);
+        assert_eq!(in_g_flat.name(), "G");
+        assert_eq!(to_western_pitch(2, -1, 0, Some("Gb")).name(), "Abb");
+    }
+
+    #[test]
+    fn test_key_signature_spelling_keeps_pitch_and_octave() {
+        // Komal Ga in D is F natural, never E#
+        let komal_ga = spell_western_pitch(3, -1, 0, Some("D"), SpellingStrategy::KeySignature);
+        assert_eq!((komal_ga.name(), komal_ga.octave), ("F".to_string(), 4));
+
+        // Tivra Ma in B: the key already sharpens F, so F natural beats E#
+        let tivra_ma = spell_western_pitch(4, 1, 0, Some("B"), SpellingStrategy::KeySignature);
+        assert_eq!(tivra_ma.name(), "F");
+        assert_eq!(tivra_ma.midi_number(), to_western_pitch(4, 1, 0, Some("B")).midi_number());
+
+        // B# belongs to C# major, and stays in the octave below the C5 it sounds as
+        let leading = spell_western_pitch(7, 0, 0, Some("C#"), SpellingStrategy::KeySignature);
+        assert_eq!((leading.name(), leading.octave), ("B#".to_string(), 4));
+    }
+
+    #[test]
+    fn test_key_signature_alters() {
+        assert_eq!(key_signature_alters(2), [1, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(key_signature_alters(-1), [0, 0, 0, 0, 0, 0, -1]);
+        assert_eq!(key_signature_fifths(Some("Eb")), -3);
+    }
+
+    #[test]
+    fn test_parse_tonic_ignores_mode_suffix() {
+        assert_eq!(parse_tonic("Bb major"), (6, -1));
+        assert_eq!(parse_tonic("f# minor"), (3, 1));
+    }
+
+    #[test]
+    fn test_octave_shift() {
+        let low_sa = to_western_pitch(1, 0, -1, Some("C"));
+        assert_eq!(low_sa.midi_number(), 48);
+    }
+}
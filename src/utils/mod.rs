@@ -4,6 +4,7 @@
 //! various aspects of the editor.
 
 pub mod performance;
+pub mod pitch_utils;
 
 // Re-export commonly used types
 pub use performance::*;
\ No newline at end of file
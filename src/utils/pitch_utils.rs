@@ -0,0 +1,283 @@
+//! Pitch code helpers
+//!
+//! Cells store their pitch as a `pitch_code` string in the notation of their
+//! pitch system ("1#", "c#", "r", "M"). These helpers normalize a pitch code
+//! into a scale degree (1-7) plus a chromatic alteration in semitones.
+
+use crate::models::PitchSystem;
+
+/// Semitone offsets of the major scale degrees above the tonic
+pub const MAJOR_SCALE_SEMITONES: [i8; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+/// Parse a trailing accidental string ("", "#", "##", "b", "bb", and the
+/// triple forms "###"/"bbb") into semitones
+pub fn accidental_semitones(accidental: &str) -> Option<i8> {
+    match accidental {
+        "" => Some(0),
+        "#" => Some(1),
+        "##" => Some(2),
+        "###" => Some(3),
+        "b" => Some(-1),
+        "bb" => Some(-2),
+        "bbb" => Some(-3),
+        _ => None,
+    }
+}
+
+/// Quarter-tone suffixes of microtonal pitch codes and their offsets in cents
+const QUARTER_TONE_SUFFIXES: [(&str, i32); 2] = [("hf", -50), ("hs", 50)];
+
+/// Split a microtonal pitch code into its semitone pitch code and cents offset
+///
+/// Half-flat and half-sharp notes carry an "hf" or "hs" suffix ("3hf" is a
+/// quarter tone below "3"). Other pitch codes are returned with offset 0.
+pub fn split_quarter_tone(pitch_code: &str) -> (&str, i32) {
+    QUARTER_TONE_SUFFIXES
+        .iter()
+        .find_map(|&(suffix, cents)| Some((pitch_code.strip_suffix(suffix).filter(|base| !base.is_empty())?, cents)))
+        .unwrap_or((pitch_code, 0))
+}
+
+/// Split a pitch code into its scale degree (1-7) and alteration in semitones
+///
+/// A quarter-tone suffix is ignored, so "3hf" reads as "3".
+///
+/// For the Western system the degree is counted from C (c=1 ... b=7).
+/// Sargam lowercase letters carry their komal/tivra alteration: r, g, d, n are
+/// flat, M (tivra Ma) is sharp, and s, m, p are the same as S, M-shuddha, P.
+pub fn parse_pitch_code(pitch_code: &str, pitch_system: PitchSystem) -> Option<(u8, i8)> {
+    let (pitch_code, _) = split_quarter_tone(pitch_code);
+    let mut chars = pitch_code.chars();
+    let base = chars.next()?;
+    let accidental = accidental_semitones(chars.as_str())?;
+
+    let (degree, base_alter) = match pitch_system {
+        PitchSystem::Number | PitchSystem::Tabla | PitchSystem::Unknown => {
+            match base {
+                '1'..='7' => (base as u8 - b'0', 0),
+                _ => return None,
+            }
+        }
+        PitchSystem::Western => {
+            let degree = match base.to_ascii_lowercase() {
+                'c' => 1,
+                'd' => 2,
+                'e' => 3,
+                'f' => 4,
+                'g' => 5,
+                'a' => 6,
+                'b' => 7,
+                _ => return None,
+            };
+            (degree, 0)
+        }
+        PitchSystem::Sargam | PitchSystem::Bhatkhande => match base {
+            'S' | 's' => (1, 0),
+            'r' => (2, -1),
+            'R' => (2, 0),
+            'g' => (3, -1),
+            'G' => (3, 0),
+            'm' => (4, 0),
+            'M' => (4, 1),
+            'P' | 'p' => (5, 0),
+            'd' => (6, -1),
+            'D' => (6, 0),
+            'n' => (7, -1),
+            'N' => (7, 0),
+            _ => return None,
+        },
+    };
+
+    Some((degree, base_alter + accidental))
+}
+
+/// Write a degree/alteration pair as a pitch code in a pitch system
+///
+/// The inverse of `parse_pitch_code`. Sargam uses its komal/tivra letters
+/// where they exist ("r", "M") and accidentals otherwise. Returns None for
+/// alterations beyond a triple accidental.
+pub fn pitch_code_for(degree: u8, alter: i8, pitch_system: PitchSystem) -> Option<String> {
+    let index = (degree.clamp(1, 7) - 1) as usize;
+    let accidental = match alter {
+        0 => "",
+        1 => "#",
+        2 => "##",
+        3 => "###",
+        -1 => "b",
+        -2 => "bb",
+        -3 => "bbb",
+        _ => return None,
+    };
+
+    let code = match pitch_system {
+        PitchSystem::Western => format!("{}{}", ['c', 'd', 'e', 'f', 'g', 'a', 'b'][index], accidental),
+        PitchSystem::Sargam | PitchSystem::Bhatkhande => match (degree, alter) {
+            (2, -1) => "r".to_string(),
+            (3, -1) => "g".to_string(),
+            (4, 1) => "M".to_string(),
+            (6, -1) => "d".to_string(),
+            (7, -1) => "n".to_string(),
+            _ => format!("{}{}", ['S', 'R', 'G', 'm', 'P', 'D', 'N'][index], accidental),
+        },
+        _ => format!("{}{}", index + 1, accidental),
+    };
+    Some(code)
+}
+
+/// Semitones above the tonic for a degree/alteration pair (not octave-reduced)
+pub fn degree_semitones(degree: u8, alter: i8) -> i8 {
+    MAJOR_SCALE_SEMITONES[(degree.clamp(1, 7) - 1) as usize] + alter
+}
+
+/// Pitch class (0-11) of a degree/alteration pair, with the tonic as 0
+pub fn pitch_class(degree: u8, alter: i8) -> u8 {
+    degree_semitones(degree, alter).rem_euclid(12) as u8
+}
+
+/// Name the interval between two pitches given as (degree, alter, octave)
+///
+/// The interval number comes from the scale degrees, so the spelling in the
+/// source pitch system decides enharmonic names: 1 to 2b is "m2" but 1 to 1#
+/// is "A1". Descending intervals are prefixed with "-" ("-M3").
+pub fn interval_name(from: (u8, i8, i8), to: (u8, i8, i8)) -> String {
+    let position = |(degree, alter, octave): (u8, i8, i8)| {
+        let degree = degree.clamp(1, 7);
+        (
+            octave as i32 * 7 + degree as i32 - 1,
+            octave as i32 * 12 + degree_semitones(degree, alter) as i32,
+        )
+    };
+    let (from_steps, from_semitones) = position(from);
+    let (to_steps, to_semitones) = position(to);
+
+    let (mut steps, mut semitones) = (to_steps - from_steps, to_semitones - from_semitones);
+    let descending = steps < 0 || (steps == 0 && semitones < 0);
+    if descending {
+        steps = -steps;
+        semitones = -semitones;
+    }
+
+    // Compare the octave-reduced size with the major/perfect interval of that size
+    let simple_steps = (steps % 7) as usize;
+    let simple_semitones = semitones - 12 * (steps / 7);
+    let deviation = simple_semitones - MAJOR_SCALE_SEMITONES[simple_steps] as i32;
+    let perfect = matches!(simple_steps, 0 | 3 | 4);
+
+    let quality = match (perfect, deviation) {
+        (true, 0) => "P".to_string(),
+        (false, 0) => "M".to_string(),
+        (false, -1) => "m".to_string(),
+        (_, d) if d > 0 => "A".repeat(d as usize),
+        (true, d) => "d".repeat(d.unsigned_abs() as usize),
+        (false, d) => "d".repeat(d.unsigned_abs() as usize - 1),
+    };
+
+    format!("{}{}{}", if descending { "-" } else { "" }, quality, steps + 1)
+}
+
+/// Movable-do solfège syllable for a degree/alteration pair
+///
+/// Uses the chromatic syllables (di, ra, ri, me, fi, se, si, le, li, te);
+/// other alterations append "#"/"b" to the natural syllable.
+pub fn solfege_name(degree: u8, alter: i8) -> String {
+    const NATURAL: [&str; 7] = ["do", "re", "mi", "fa", "sol", "la", "ti"];
+    const SHARP: [Option<&str>; 7] = [Some("di"), Some("ri"), None, Some("fi"), Some("si"), Some("li"), None];
+    const FLAT: [Option<&str>; 7] = [None, Some("ra"), Some("me"), None, Some("se"), Some("le"), Some("te")];
+
+    let index = (degree.clamp(1, 7) - 1) as usize;
+    let chromatic = match alter {
+        0 => Some(NATURAL[index]),
+        1 => SHARP[index],
+        -1 => FLAT[index],
+        _ => None,
+    };
+    match chromatic {
+        Some(name) => name.to_string(),
+        None if alter > 0 => format!("{}{}", NATURAL[index], "#".repeat(alter as usize)),
+        None => format!("{}{}", NATURAL[index], "b".repeat(alter.unsigned_abs() as usize)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quarter_tone_pitch_codes() {
+        assert_eq!(split_quarter_tone("3hf"), ("3", -50));
+        assert_eq!(split_quarter_tone("c#hs"), ("c#", 50));
+        assert_eq!(split_quarter_tone("3b"), ("3b", 0));
+        assert_eq!(parse_pitch_code("3hf", PitchSystem::Number), Some((3, 0)));
+    }
+
+    #[test]
+    fn test_parse_number_pitch_codes() {
+        assert_eq!(parse_pitch_code("1", PitchSystem::Number), Some((1, 0)));
+        assert_eq!(parse_pitch_code("4#", PitchSystem::Number), Some((4, 1)));
+        assert_eq!(parse_pitch_code("7bb", PitchSystem::Number), Some((7, -2)));
+        assert_eq!(parse_pitch_code("8", PitchSystem::Number), None);
+    }
+
+    #[test]
+    fn test_parse_sargam_pitch_codes() {
+        assert_eq!(parse_pitch_code("S", PitchSystem::Sargam), Some((1, 0)));
+        assert_eq!(parse_pitch_code("r", PitchSystem::Sargam), Some((2, -1)));
+        assert_eq!(parse_pitch_code("M", PitchSystem::Sargam), Some((4, 1)));
+        assert_eq!(parse_pitch_code("n", PitchSystem::Sargam), Some((7, -1)));
+    }
+
+    #[test]
+    fn test_parse_western_pitch_codes() {
+        assert_eq!(parse_pitch_code("c", PitchSystem::Western), Some((1, 0)));
+        assert_eq!(parse_pitch_code("bb", PitchSystem::Western), Some((7, -1)));
+        assert_eq!(parse_pitch_code("f#", PitchSystem::Western), Some((4, 1)));
+    }
+
+    #[test]
+    fn test_pitch_classes_wrap_at_the_octave() {
+        assert_eq!(pitch_class(1, 0), 0);
+        assert_eq!(pitch_class(5, 0), 7);
+        assert_eq!(pitch_class(7, 1), 0);
+        assert_eq!(pitch_class(1, -1), 11);
+    }
+
+    #[test]
+    fn test_interval_names() {
+        assert_eq!(interval_name((1, 0, 0), (3, 0, 0)), "M3");
+        assert_eq!(interval_name((3, 0, 0), (5, 0, 0)), "m3");
+        assert_eq!(interval_name((1, 0, 0), (1, 0, 0)), "P1");
+        assert_eq!(interval_name((4, 0, 0), (7, 0, 0)), "A4");
+        assert_eq!(interval_name((1, 0, 0), (1, 0, 1)), "P8");
+        assert_eq!(interval_name((1, 0, 0), (3, 0, 1)), "M10");
+    }
+
+    #[test]
+    fn test_interval_names_follow_spelling() {
+        assert_eq!(interval_name((1, 0, 0), (2, -1, 0)), "m2");
+        assert_eq!(interval_name((1, 0, 0), (1, 1, 0)), "A1");
+        assert_eq!(interval_name((7, 0, 0), (2, -1, 1)), "d3");
+        assert_eq!(interval_name((5, 0, 0), (3, 0, 0)), "-m3");
+    }
+
+    #[test]
+    fn test_solfege_names() {
+        assert_eq!(solfege_name(2, 0), "re");
+        assert_eq!(solfege_name(4, 1), "fi");
+        assert_eq!(solfege_name(7, -1), "te");
+        assert_eq!(solfege_name(3, 1), "mi#");
+    }
+
+    #[test]
+    fn test_pitch_code_round_trip() {
+        for system in [PitchSystem::Number, PitchSystem::Western, PitchSystem::Sargam] {
+            for degree in 1..=7 {
+                for alter in -1..=1 {
+                    let code = pitch_code_for(degree, alter, system).unwrap();
+                    assert_eq!(parse_pitch_code(&code, system), Some((degree, alter)), "{}", code);
+                }
+            }
+        }
+        assert_eq!(pitch_code_for(2, -1, PitchSystem::Sargam).as_deref(), Some("r"));
+        assert_eq!(pitch_code_for(4, 0, PitchSystem::Western).as_deref(), Some("f"));
+    }
+}